@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, SharedCircuitBreaker};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn bench_reads_under_load(c: &mut Criterion) {
+    let cb = SharedCircuitBreaker::new(
+        CircuitBreaker::builder()
+            .timeout(Duration::from_secs(1))
+            .inline_when_closed(true)
+            .build()
+            .unwrap(),
+    );
+    // Keeps the breaker's lock busy the way a loaded service would.
+    let stop = Arc::new(AtomicBool::new(false));
+    let callers: Vec<_> = (0..4)
+        .map(|_| {
+            let (cb, stop) = (cb.clone(), Arc::clone(&stop));
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let _ = cb.call(|| Ok::<_, ()>(1));
+                }
+            })
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("metrics_under_load");
+    group.bench_function("metrics", |b| b.iter(|| cb.metrics()));
+    group.bench_function("metrics_relaxed", |b| b.iter(|| cb.metrics_relaxed()));
+    group.finish();
+
+    stop.store(true, Ordering::Relaxed);
+    for caller in callers {
+        caller.join().unwrap();
+    }
+}
+
+criterion_group!(benches, bench_reads_under_load);
+criterion_main!(benches);
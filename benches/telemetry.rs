@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use circuit_breaker::CircuitBreaker;
+use criterion::{criterion_group, criterion_main, Criterion};
+use metrics_util::debugging::DebuggingRecorder;
+
+fn bench_emission(c: &mut Criterion) {
+    let recorder = DebuggingRecorder::new();
+    let mut group = c.benchmark_group("telemetry");
+
+    ::metrics::with_local_recorder(&recorder, || {
+        // Inline calls, so the emission is a noticeable part of each call.
+        let mut per_call = CircuitBreaker::builder()
+            .name("bench")
+            .inline_when_closed(true)
+            .build()
+            .unwrap();
+        group.bench_function("per_call", |b| b.iter(|| per_call.call(|| Ok::<_, ()>(1))));
+
+        let mut batched = CircuitBreaker::builder()
+            .name("bench")
+            .inline_when_closed(true)
+            .batch_telemetry(1024, Duration::from_secs(1))
+            .build()
+            .unwrap();
+        group.bench_function("batched", |b| b.iter(|| batched.call(|| Ok::<_, ()>(1))));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_emission);
+criterion_main!(benches);
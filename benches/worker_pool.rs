@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, WorkerPool};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn bench_execution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("call");
+
+    let mut spawning = CircuitBreaker::builder()
+        .timeout(Duration::from_secs(1))
+        .build()
+        .unwrap();
+    group.bench_function("thread_per_call", |b| {
+        b.iter(|| spawning.call(|| Ok::<_, ()>(1)))
+    });
+
+    let mut pooled = CircuitBreaker::builder()
+        .timeout(Duration::from_secs(1))
+        .worker_pool(WorkerPool::new(4))
+        .build()
+        .unwrap();
+    group.bench_function("worker_pool", |b| b.iter(|| pooled.call(|| Ok::<_, ()>(1))));
+
+    let mut inline = CircuitBreaker::builder()
+        .timeout(Duration::from_secs(1))
+        .inline_when_closed(true)
+        .build()
+        .unwrap();
+    group.bench_function("inline", |b| b.iter(|| inline.call(|| Ok::<_, ()>(1))));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_execution);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+//! Guarding a downstream call from an axum handler.
+//!
+//! The breaker lives in the application state; `SharedCircuitBreaker` is
+//! cheap to clone, so every handler sees the same breaker. A rejected call
+//! answers `503 Service Unavailable` straight away instead of waiting on a
+//! downstream that is known to be failing.
+//!
+//! Run with `cargo run --example axum_service --features tokio`, then
+//! `curl localhost:3000/quote`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use circuit_breaker::{CircuitBreaker, CircuitBreakerError, SharedCircuitBreaker};
+
+/// Stands in for an HTTP client or database handle.
+#[derive(Clone, Default)]
+struct Downstream {
+    down: Arc<AtomicBool>,
+}
+
+impl Downstream {
+    async fn quote(&self) -> Result<String, &'static str> {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        if self.down.load(Ordering::SeqCst) {
+            Err("connection refused")
+        } else {
+            Ok("42.00".to_string())
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    breaker: SharedCircuitBreaker,
+    downstream: Downstream,
+}
+
+async fn quote(State(state): State<AppState>) -> (StatusCode, String) {
+    match state.breaker.call_async(|| state.downstream.quote()).await {
+        Ok(Some(price)) => (StatusCode::OK, price),
+        // The call that half-opens the breaker does not run.
+        Ok(None) => (StatusCode::SERVICE_UNAVAILABLE, "retry shortly".into()),
+        Err(CircuitBreakerError::Rejected(reason)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("rejected: {reason}"),
+        ),
+        Err(CircuitBreakerError::TimeoutError) => {
+            (StatusCode::GATEWAY_TIMEOUT, "downstream timed out".into())
+        }
+        Err(e) => (StatusCode::BAD_GATEWAY, e.to_string()),
+    }
+}
+
+fn app(downstream: Downstream) -> Router {
+    let breaker = CircuitBreaker::builder()
+        .name("quotes")
+        .failure_threshold(1)
+        .timeout(Duration::from_millis(500))
+        .recovery_time(Duration::from_secs(10))
+        .build()
+        .expect("valid breaker configuration");
+    Router::new()
+        .route("/quote", get(quote))
+        .with_state(AppState {
+            breaker: breaker.into(),
+            downstream,
+        })
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+        .await
+        .expect("port 3000 is free");
+    println!("listening on http://{}", listener.local_addr().unwrap());
+    axum::serve(listener, app(Downstream::default()))
+        .await
+        .expect("server error");
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn get_quote(app: &Router) -> (StatusCode, String) {
+        let request = Request::get("/quote").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_endpoint_rejects_once_downstream_fails() {
+        let downstream = Downstream::default();
+        let app = app(downstream.clone());
+
+        assert_eq!(get_quote(&app).await, (StatusCode::OK, "42.00".into()));
+
+        downstream.down.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert_eq!(get_quote(&app).await.0, StatusCode::BAD_GATEWAY);
+        }
+        let (status, body) = get_quote(&app).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.starts_with("rejected"), "{body}");
+    }
+}
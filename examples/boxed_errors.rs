@@ -0,0 +1,71 @@
+//! Guarding calls that fail with assorted error types, boxed into one.
+//!
+//! `BoxedCircuitBreaker` fixes the error type of every call to
+//! `Box<dyn Error + Send>`, so closures need no type annotations.
+//!
+//! Run with `cargo run --example boxed_errors`.
+
+use std::io;
+use std::num::ParseIntError;
+use std::time::Duration;
+
+use circuit_breaker::{BoxError, BoxedCircuitBreaker, CircuitBreaker, CircuitBreakerError};
+
+/// Reads a port from a config source that may be unavailable or garbled.
+fn read_port(source: &'static str) -> Result<u16, BoxError> {
+    if source.is_empty() {
+        return Err(Box::new(io::Error::other("config source unavailable")));
+    }
+    source
+        .parse()
+        .map_err(|e: ParseIntError| Box::new(e) as BoxError)
+}
+
+fn breaker() -> BoxedCircuitBreaker {
+    CircuitBreaker::builder()
+        .failure_threshold(1)
+        .timeout(Duration::from_secs(1))
+        .recovery_time(Duration::from_secs(30))
+        .build()
+        .expect("valid configuration")
+        .into()
+}
+
+fn main() {
+    let mut cb = breaker();
+    for source in ["8080", "eighty", "", "8081"] {
+        match cb.call(move || read_port(source)) {
+            Ok(Some(port)) => println!("port {port}"),
+            Ok(None) => println!("skipped"),
+            Err(CircuitBreakerError::FunctionError(e)) => println!("failed: {e}"),
+            Err(e) => println!("breaker: {e}"),
+        }
+    }
+    println!("breaker is now {:?}", cb.state());
+}
+
+#[cfg(test)]
+mod tests {
+    use circuit_breaker::{RejectionReason, State};
+
+    use super::*;
+
+    #[test]
+    fn test_mixed_errors_trip_the_breaker() {
+        let mut cb = breaker();
+        assert!(matches!(cb.call(|| read_port("8080")), Ok(Some(8080))));
+
+        let res = cb.call(|| read_port("eighty"));
+        assert!(matches!(res, Err(CircuitBreakerError::FunctionError(_))));
+        let res = cb.call(|| read_port(""));
+        assert!(
+            matches!(res, Err(CircuitBreakerError::FunctionError(e)) if e.to_string().contains("unavailable"))
+        );
+        assert_eq!(cb.state(), State::Open);
+
+        assert!(matches!(
+            cb.call(|| read_port("8081")),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+}
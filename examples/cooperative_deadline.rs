@@ -0,0 +1,51 @@
+//! Running a CPU-bound job on the caller's thread with a cooperative
+//! deadline, instead of moving it to a worker thread.
+//!
+//! The job checks its [`DeadlineGuard`] between chunks of work and stops
+//! early once the breaker's timeout has passed.
+//!
+//! Run with `cargo run --example cooperative_deadline`.
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, CircuitBreakerError, DeadlineGuard};
+
+/// Sums as many primes as it can below `limit`, giving up at the deadline.
+/// `seen` is a `RefCell`, which is not `Sync`, so a reference to it could not
+/// be sent to a worker thread.
+fn sum_primes(limit: u64, seen: &RefCell<Vec<u64>>, deadline: &DeadlineGuard) -> Result<u64, u64> {
+    let mut sum = 0;
+    for n in 2..limit {
+        if n % 1_000 == 0 && deadline.expired() {
+            return Err(n);
+        }
+        if (2..n).take_while(|d| d * d <= n).all(|d| n % d != 0) {
+            sum += n;
+            seen.borrow_mut().push(n);
+        }
+    }
+    Ok(sum)
+}
+
+fn main() {
+    let mut cb = CircuitBreaker::builder()
+        .timeout(Duration::from_millis(50))
+        .build()
+        .expect("valid configuration");
+    let seen = RefCell::new(Vec::new());
+
+    for limit in [10_000, 100_000_000] {
+        match cb.call_with_deadline(|deadline| sum_primes(limit, &seen, deadline)) {
+            Ok(Some(sum)) => println!("sum of primes below {limit}: {sum}"),
+            Err(CircuitBreakerError::TimeoutError) => {
+                println!(
+                    "stopped at the deadline after {} primes",
+                    seen.borrow().len()
+                )
+            }
+            other => println!("unexpected result: {other:?}"),
+        }
+    }
+    println!("breaker is now {:?}", cb.state());
+}
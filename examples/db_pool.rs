@@ -0,0 +1,156 @@
+//! Guarding database pool checkouts with a breaker.
+//!
+//! A pool that is merely exhausted says nothing about the database, so
+//! `is_failure` keeps those errors from counting, while errors from a
+//! database that is down still trip the breaker.
+//!
+//! Run with `cargo run --example db_pool`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, CircuitBreakerError, SharedCircuitBreaker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoolError {
+    /// Every connection is checked out; waiting would help.
+    Exhausted,
+    /// The database refused a new connection.
+    Unreachable,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::Exhausted => write!(f, "pool exhausted"),
+            PoolError::Unreachable => write!(f, "database unreachable"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Connection(u32);
+
+/// Stands in for a real pool such as r2d2 or deadpool: hands out up to
+/// `size` connections while the database is up.
+#[derive(Debug, Clone)]
+struct MockPool {
+    inner: Arc<Mutex<PoolState>>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    size: u32,
+    checked_out: u32,
+    db_up: bool,
+}
+
+impl MockPool {
+    fn new(size: u32) -> Self {
+        MockPool {
+            inner: Arc::new(Mutex::new(PoolState {
+                size,
+                checked_out: 0,
+                db_up: true,
+            })),
+        }
+    }
+
+    fn get(&self) -> Result<Connection, PoolError> {
+        let mut pool = self.inner.lock().unwrap();
+        if !pool.db_up {
+            return Err(PoolError::Unreachable);
+        }
+        if pool.checked_out == pool.size {
+            return Err(PoolError::Exhausted);
+        }
+        pool.checked_out += 1;
+        Ok(Connection(pool.checked_out))
+    }
+
+    fn set_db_up(&self, up: bool) {
+        self.inner.lock().unwrap().db_up = up;
+    }
+}
+
+fn guarded_breaker() -> SharedCircuitBreaker {
+    CircuitBreaker::builder()
+        .name("db-pool")
+        .failure_threshold(2)
+        .timeout(Duration::from_millis(500))
+        .recovery_time(Duration::from_secs(5))
+        .is_failure(|e: &PoolError| *e != PoolError::Exhausted)
+        .build()
+        .expect("valid configuration")
+        .into()
+}
+
+fn checkout(
+    cb: &SharedCircuitBreaker,
+    pool: &MockPool,
+) -> Result<Option<Connection>, CircuitBreakerError<PoolError>> {
+    let pool = pool.clone();
+    cb.call(move || pool.get())
+}
+
+fn main() {
+    let cb = guarded_breaker();
+    let pool = MockPool::new(2);
+
+    for _ in 0..6 {
+        match checkout(&cb, &pool) {
+            Ok(Some(conn)) => println!("got connection {}", conn.0),
+            Ok(None) => println!("breaker half-open, try again"),
+            Err(CircuitBreakerError::FunctionError(e)) => println!("checkout failed: {e}"),
+            Err(e) => println!("rejected: {e}"),
+        }
+    }
+    println!("after exhaustion: {:?}", cb.state());
+
+    pool.set_db_up(false);
+    for _ in 0..4 {
+        if let Err(e) = checkout(&cb, &pool) {
+            println!("checkout failed: {e}");
+        }
+    }
+    println!("after database went down: {:?}", cb.state());
+}
+
+#[cfg(test)]
+mod tests {
+    use circuit_breaker::{RejectionReason, State};
+
+    use super::*;
+
+    #[test]
+    fn test_exhaustion_does_not_trip_but_db_down_does() {
+        let cb = guarded_breaker();
+        let pool = MockPool::new(2);
+
+        assert!(matches!(checkout(&cb, &pool), Ok(Some(_))));
+        assert!(matches!(checkout(&cb, &pool), Ok(Some(_))));
+        for _ in 0..5 {
+            assert!(matches!(
+                checkout(&cb, &pool),
+                Err(CircuitBreakerError::FunctionError(PoolError::Exhausted))
+            ));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failures, 0);
+
+        pool.set_db_up(false);
+        while cb.state() == State::Closed {
+            assert!(matches!(
+                checkout(&cb, &pool),
+                Err(CircuitBreakerError::FunctionError(PoolError::Unreachable))
+            ));
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().failures, 3);
+        assert!(matches!(
+            checkout(&cb, &pool),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+}
@@ -0,0 +1,417 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::broadcast;
+
+use crate::breaker::{Admission, Outcome};
+use crate::{
+    CallOutcome, CircuitBreaker, CircuitBreakerError, Clock, SharedCircuitBreaker, State,
+    StateTransition,
+};
+
+impl<C: Clock, D> CircuitBreaker<C, D> {
+    /// Subscribes to state transitions from async code.
+    ///
+    /// The channel holds the 16 most recent transitions. A receiver that
+    /// falls further behind gets `RecvError::Lagged` and skips ahead; the
+    /// breaker itself never waits on subscribers.
+    pub fn subscribe_async(&self) -> broadcast::Receiver<StateTransition> {
+        self.transition_tx.subscribe()
+    }
+
+    /// Resolves once the breaker is next closed, or at once if it already
+    /// is, so a task can park until a dependency recovers instead of
+    /// retrying in a loop. Degraded counts as closed, since calls flow.
+    ///
+    /// The future does not borrow the breaker, which has to keep taking
+    /// calls for it to ever close; it is built on
+    /// [`subscribe_async`](CircuitBreaker::subscribe_async). It also
+    /// resolves if the breaker is dropped. A waiter that falls more than 16
+    /// transitions behind may miss a brief closing and wait for the next.
+    pub fn wait_until_closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.subscribe_async();
+        let closed = matches!(self.state(), State::Closed | State::Degraded);
+        async move {
+            if closed {
+                return;
+            }
+            loop {
+                match rx.recv().await {
+                    Ok(transition) if matches!(transition.to, State::Closed | State::Degraded) => {
+                        return
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        }
+    }
+
+    /// Async counterpart of [`CircuitBreaker::call`].
+    ///
+    /// The future is awaited on the calling task under `tokio::time::timeout`
+    /// and no thread is spawned, so the future does not need to be `Send` and
+    /// this works on a current-thread runtime. The breaker's accounting runs
+    /// on the same task once the future completes or times out.
+    pub async fn call_async<F, Fut, R, E>(
+        &mut self,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        R: 'static,
+        E: 'static,
+    {
+        let (_slots, admitted_in) = match self.admit()? {
+            Admission::Skip(value) => return Ok(value),
+            Admission::Run {
+                slots, admitted_in, ..
+            } => (slots, admitted_in),
+        };
+
+        let start = Instant::now();
+        let guard = self.in_flight_guard();
+        let res = tokio::time::timeout(self.call_timeout(), func()).await;
+        drop(guard);
+        let outcome = match res {
+            Ok(Ok(data)) => Outcome::Ok(data),
+            Ok(Err(e)) => Outcome::Err(e),
+            Err(_) => Outcome::Timeout,
+        };
+        self.complete(outcome, start.elapsed(), admitted_in, None)
+            .map(Some)
+    }
+
+    /// Like [`CircuitBreaker::call_async`], for operations such as chunked
+    /// downloads that make partial progress before they finish.
+    ///
+    /// `func` gets a [`StreamProgress`] to report progress on, e.g. bytes
+    /// received. A call that returns `Ok` is recorded as usual. A call that
+    /// fails or times out counts against the breaker only if it reported
+    /// less than `min_progress` in total; one that got at least that far
+    /// counts as a success, since the dependency was delivering. Either way
+    /// the error is returned to the caller.
+    pub async fn call_async_streaming<F, Fut, R, E>(
+        &mut self,
+        min_progress: u64,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: FnOnce(StreamProgress) -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        R: 'static,
+        E: 'static,
+    {
+        let (_slots, admitted_in) = match self.admit()? {
+            Admission::Skip(value) => return Ok(value),
+            Admission::Run {
+                slots, admitted_in, ..
+            } => (slots, admitted_in),
+        };
+
+        let progress = StreamProgress::default();
+        let start = Instant::now();
+        let guard = self.in_flight_guard();
+        let res = tokio::time::timeout(self.call_timeout(), func(progress.clone())).await;
+        drop(guard);
+        let elapsed = start.elapsed();
+        let outcome = match res {
+            Ok(Ok(data)) => Outcome::Ok(data),
+            Ok(Err(e)) => Outcome::Err(e),
+            Err(_) => Outcome::Timeout,
+        };
+        if matches!(outcome, Outcome::Ok(_))
+            || progress.get() < min_progress
+            || !self.moves_state(admitted_in)
+        {
+            return self.complete(outcome, elapsed, admitted_in, None).map(Some);
+        }
+        self.on_success(elapsed);
+        self.record_outcome_latency(CallOutcome::Success, elapsed);
+        self.report(CallOutcome::Success, Some(elapsed));
+        self.check_invariants();
+        self.within_max_duration(outcome.into_result().map(Some), elapsed)
+    }
+
+    /// Async counterpart of [`CircuitBreaker::call_with_fallback`]: awaits
+    /// the future from `fallback` instead whenever `primary` is rejected,
+    /// fails or times out.
+    pub async fn call_async_with_fallback<F, Fut, G, GFut, R, E>(
+        &mut self,
+        primary: F,
+        fallback: G,
+    ) -> R
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        G: FnOnce() -> GFut,
+        GFut: Future<Output = R>,
+        R: 'static,
+        E: 'static,
+    {
+        match self.call_async(primary).await {
+            Ok(Some(value)) => value,
+            Ok(None) | Err(_) => fallback().await,
+        }
+    }
+}
+
+impl<C: Clock, D> SharedCircuitBreaker<C, D> {
+    /// See [`CircuitBreaker::wait_until_closed`].
+    pub async fn wait_until_closed(&self) {
+        let closed = self.lock().wait_until_closed();
+        closed.await
+    }
+
+    /// Async counterpart of [`SharedCircuitBreaker::call`].
+    ///
+    /// The breaker is locked only to admit the call and to record how it
+    /// went, never across an `.await`, so the returned future is `Send`
+    /// whenever `func`'s is and can be awaited from, e.g., a web handler on
+    /// a multi-threaded runtime. Clone the breaker into the framework's
+    /// application state to share it between handlers.
+    pub async fn call_async<F, Fut, R, E>(
+        &self,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        R: 'static,
+        E: 'static,
+    {
+        let (timeout, guard, slots, admitted_in) = {
+            let mut breaker = self.lock();
+            let (slots, admitted_in) = match breaker.admit()? {
+                Admission::Skip(value) => return Ok(value),
+                Admission::Run {
+                    slots, admitted_in, ..
+                } => (slots, admitted_in),
+            };
+            let timeout = breaker.call_timeout();
+            (timeout, breaker.in_flight_guard(), slots, admitted_in)
+        };
+        let start = Instant::now();
+        let res = tokio::time::timeout(timeout, func()).await;
+        drop(guard);
+        let outcome = match res {
+            Ok(Ok(data)) => Outcome::Ok(data),
+            Ok(Err(e)) => Outcome::Err(e),
+            Err(_) => Outcome::Timeout,
+        };
+        let res = self
+            .lock()
+            .complete(outcome, start.elapsed(), admitted_in, None);
+        self.release_slots(slots);
+        res.map(Some)
+    }
+}
+
+/// Progress reported by a call made with
+/// [`CircuitBreaker::call_async_streaming`]. Clones share the same total.
+#[derive(Debug, Clone, Default)]
+pub struct StreamProgress(Arc<AtomicU64>);
+
+impl StreamProgress {
+    /// Adds `amount`, e.g. the size of a chunk just received.
+    pub fn add(&self, amount: u64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// The total reported so far.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::RejectionReason;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_call_async_on_current_thread() {
+        let mut cb = CircuitBreaker::new(0, Duration::from_millis(50), Duration::from_secs(60), 1);
+
+        // Rc is !Send, so this only compiles if nothing is moved to a thread.
+        let value = Rc::new(5);
+        let res = cb
+            .call_async(|| {
+                let value = Rc::clone(&value);
+                async move { Ok::<_, ()>(*value) }
+            })
+            .await;
+        assert!(matches!(res, Ok(Some(5))));
+        assert_eq!(cb.metrics().successes, 1);
+
+        let res = cb
+            .call_async(|| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, ()>(())
+            })
+            .await;
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        assert_eq!(cb.state(), State::Open);
+
+        let res = cb.call_async(|| async { Ok::<_, ()>(()) }).await;
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_subscribe_async_receives_trip() {
+        let mut cb = CircuitBreaker::new(0, Duration::from_millis(50), Duration::from_secs(60), 1);
+        let mut rx = cb.subscribe_async();
+
+        let _ = cb.call_async(|| async { Err::<(), _>("boom") }).await;
+        let transition = rx.recv().await.unwrap();
+        assert_eq!(
+            (transition.from, transition.to),
+            (State::Closed, State::Open)
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_call_async_with_fallback_when_open() {
+        let mut cb = CircuitBreaker::new(1, Duration::from_millis(50), Duration::from_secs(60), 1);
+        while cb.state() != State::Open {
+            let _ = cb.call_async(|| async { Err::<(), _>("boom") }).await;
+        }
+
+        let value = cb
+            .call_async_with_fallback(
+                || async { Ok::<_, ()>("primary") },
+                || async {
+                    tokio::task::yield_now().await;
+                    "fallback"
+                },
+            )
+            .await;
+        assert_eq!(value, "fallback");
+        assert_eq!(cb.metrics().rejected, 1);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_call_async_streaming_counts_only_early_aborts() {
+        let mut cb = CircuitBreaker::new(1, Duration::from_millis(50), Duration::from_secs(60), 1);
+        let download = |chunks: u64, completes: bool| {
+            move |progress: StreamProgress| async move {
+                for _ in 0..chunks {
+                    tokio::task::yield_now().await;
+                    progress.add(1024);
+                }
+                if completes {
+                    Ok(chunks * 1024)
+                } else {
+                    Err("connection reset")
+                }
+            }
+        };
+
+        let res = cb.call_async_streaming(4096, download(8, true)).await;
+        assert!(matches!(res, Ok(Some(8192))));
+
+        let res = cb.call_async_streaming(4096, download(6, false)).await;
+        assert!(matches!(res, Err(CircuitBreakerError::FunctionError(_))));
+        assert_eq!(cb.metrics().failures, 0);
+        assert_eq!(cb.metrics().successes, 2);
+
+        for _ in 0..2 {
+            let _ = cb.call_async_streaming(4096, download(1, false)).await;
+        }
+        assert_eq!(cb.metrics().failures, 2);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_call_async_streaming_respects_force_closed() {
+        use crate::Override;
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .state_store(|_: Option<&str>| Override::ForceClosed)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let res = cb
+                .call_async_streaming(1, |_| async { Err::<(), _>("reset") })
+                .await;
+            assert!(matches!(res, Err(CircuitBreakerError::FunctionError(_))));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failures, 2);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_shared_call_async_is_send() {
+        fn assert_send<T: Send>(value: T) -> T {
+            value
+        }
+
+        let cb = SharedCircuitBreaker::new(CircuitBreaker::new(
+            0,
+            Duration::from_millis(50),
+            Duration::from_secs(60),
+            1,
+        ));
+        let res = assert_send(cb.call_async(|| async { Ok::<_, ()>(1) })).await;
+        assert!(matches!(res, Ok(Some(1))));
+        assert_eq!(cb.in_flight(), 0);
+
+        let _ = cb.call_async(|| async { Err::<(), _>("boom") }).await;
+        let res = cb.call_async(|| async { Ok::<_, ()>(1) }).await;
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_wait_until_closed() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(1))
+                .half_open_successes(1)
+                .build_with_clock(clock.clone())
+                .unwrap(),
+        );
+        tokio::time::timeout(Duration::from_secs(1), cb.wait_until_closed())
+            .await
+            .expect("a closed breaker resolves at once");
+
+        while cb.state() != State::Open {
+            let _ = cb.call_async(|| async { Err::<(), _>("boom") }).await;
+        }
+        let waiter = tokio::spawn({
+            let cb = cb.clone();
+            async move { cb.wait_until_closed().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call_async(|| async { Ok::<_, ()>(()) }).await;
+        assert_eq!(cb.state(), State::HalfOpen);
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        let _ = cb.call_async(|| async { Ok::<_, ()>(()) }).await;
+        assert_eq!(cb.state(), State::Closed);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter resolves once closed")
+            .unwrap();
+    }
+}
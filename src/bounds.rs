@@ -0,0 +1,54 @@
+/// A protected function that can run on a worker thread with a timeout:
+/// a closure returning `Result`, that is `Send + 'static`.
+///
+/// Implemented for every such closure, so there is nothing to implement.
+/// It exists so that a closure which borrows local data or captures
+/// something like an `Rc` fails to compile with an error that says it is
+/// "required for `{closure}` to implement `RunsOnWorkerThread`", pointing
+/// at the reason: the call may outlive the caller on another thread. Use
+/// [`runs_on_worker_thread`] to check a closure where it is written rather
+/// than where it is passed to the breaker.
+pub trait RunsOnWorkerThread<R, E>: FnOnce() -> Result<R, E> + Send + 'static {}
+
+impl<F, R, E> RunsOnWorkerThread<R, E> for F where F: FnOnce() -> Result<R, E> + Send + 'static {}
+
+/// Returns `func` unchanged, failing to compile unless it can be passed to
+/// [`CircuitBreaker::call`](crate::CircuitBreaker::call).
+///
+/// A closure that owns what it uses passes:
+///
+/// ```
+/// use circuit_breaker::runs_on_worker_thread;
+///
+/// let url = String::from("http://localhost");
+/// let fetch = runs_on_worker_thread(move || Ok::<_, ()>(url.len()));
+/// ```
+///
+/// One that borrows a local, or captures an `Rc`, does not, because the
+/// worker may still be running it after a timeout has returned control to
+/// the caller:
+///
+/// ```compile_fail
+/// use circuit_breaker::runs_on_worker_thread;
+///
+/// let url = String::from("http://localhost");
+/// let fetch = runs_on_worker_thread(|| Ok::<_, ()>(url.len()));
+/// ```
+///
+/// ```compile_fail
+/// use std::rc::Rc;
+/// use circuit_breaker::runs_on_worker_thread;
+///
+/// let url = Rc::new(String::from("http://localhost"));
+/// let fetch = runs_on_worker_thread(move || Ok::<_, ()>(url.len()));
+/// ```
+///
+/// Move owned or `Arc`-shared data into the closure instead, or use
+/// [`CircuitBreaker::call_with_deadline`](crate::CircuitBreaker::call_with_deadline),
+/// which runs on the calling thread.
+pub fn runs_on_worker_thread<F, R, E>(func: F) -> F
+where
+    F: RunsOnWorkerThread<R, E>,
+{
+    func
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use crate::{CircuitBreaker, CircuitBreakerError, Clock, RunsOnWorkerThread, SystemClock};
+
+/// The error type of calls made through a [`BoxedCircuitBreaker`].
+pub type BoxError = Box<dyn Error + Send>;
+
+/// A [`CircuitBreaker`] whose calls all return [`BoxError`], for callers
+/// that box their errors anyway.
+///
+/// With the error type fixed, a closure such as `|| Ok(1)` needs no
+/// annotation. Everything but the calls themselves is reached through the
+/// wrapped breaker, which this derefs to.
+pub struct BoxedCircuitBreaker<C = SystemClock>(CircuitBreaker<C>);
+
+impl<C: Clock> BoxedCircuitBreaker<C> {
+    pub fn new(breaker: CircuitBreaker<C>) -> Self {
+        BoxedCircuitBreaker(breaker)
+    }
+
+    /// See [`CircuitBreaker::call`].
+    pub fn call<F, R>(&mut self, func: F) -> Result<Option<R>, CircuitBreakerError<BoxError>>
+    where
+        F: RunsOnWorkerThread<R, BoxError>,
+        R: Send + 'static,
+    {
+        self.0.call(func)
+    }
+
+    /// See [`CircuitBreaker::call_labeled`].
+    pub fn call_labeled<F, R>(
+        &mut self,
+        label: &str,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<BoxError>>
+    where
+        F: RunsOnWorkerThread<R, BoxError>,
+        R: Send + 'static,
+    {
+        self.0.call_labeled(label, func)
+    }
+
+    /// Unwraps the breaker.
+    pub fn into_inner(self) -> CircuitBreaker<C> {
+        self.0
+    }
+}
+
+impl<C: Clock> fmt::Debug for BoxedCircuitBreaker<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("BoxedCircuitBreaker").field(&self.0).finish()
+    }
+}
+
+impl<C> Deref for BoxedCircuitBreaker<C> {
+    type Target = CircuitBreaker<C>;
+
+    fn deref(&self) -> &CircuitBreaker<C> {
+        &self.0
+    }
+}
+
+impl<C> DerefMut for BoxedCircuitBreaker<C> {
+    fn deref_mut(&mut self) -> &mut CircuitBreaker<C> {
+        &mut self.0
+    }
+}
+
+impl<C: Clock> From<CircuitBreaker<C>> for BoxedCircuitBreaker<C> {
+    fn from(breaker: CircuitBreaker<C>) -> Self {
+        BoxedCircuitBreaker::new(breaker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::State;
+
+    #[test]
+    fn test_boxed_calls_need_no_annotations() {
+        let mut cb = BoxedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+        );
+
+        assert!(matches!(cb.call(|| Ok(1)), Ok(Some(1))));
+        for _ in 0..2 {
+            let res = cb.call_labeled("write", || {
+                Err::<(), _>(Box::new(io::Error::other("disk full")) as _)
+            });
+            let Err(CircuitBreakerError::FunctionError(e)) = res else {
+                panic!("expected the function's error, got {res:?}");
+            };
+            assert_eq!(e.to_string(), "disk full");
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.recent_failures()[0].label.as_deref(), Some("write"));
+    }
+}
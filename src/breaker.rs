@@ -0,0 +1,5967 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::builder::{
+    CloneFn, DefaultFn, LenientProbes, RecoveryFailure, ResultHook, ThresholdWarning,
+    TransitionGuard, TransitionLog,
+};
+use crate::config::validate_config;
+use crate::failure::LateResults;
+use crate::hooks::{self, AnyKey, AnyPredicate, AnyRetryHint, DataHook, ErrorKey, Hook, KeyEq};
+use crate::metrics::PublishedMetrics;
+use crate::telemetry::{self, CallBatch, TraceContext};
+use crate::{
+    BuildError, CallOutcome, CallResult, CircuitBreakerBuilder, CircuitBreakerError,
+    Classification, Clock, Completion, Config, DeadlineGuard, FailureKind, FailureRecord,
+    HalfOpenIdle, InFlightAfterTrip, LateResult, LatencyBuckets, LatencyByOutcome, Metrics,
+    Override, Priority, RejectionPolicy, RejectionReason, RelaxedMetrics, RunsOnWorkerThread,
+    SelfTestReport, Semaphore, State, StateDurations, StateStore, StateTransition, SystemClock,
+    ThreadLimit, TimeoutStrategy, TransitionRecord, TripPolicy, TripReason, WorkerPool,
+};
+
+/// Protects calls to an unreliable service, rejecting them while the
+/// service looks unhealthy.
+///
+/// `call` takes `&mut self`; wrap the breaker in a
+/// [`SharedCircuitBreaker`](crate::SharedCircuitBreaker) to call it from
+/// several threads. The breaker is `Send + Sync` when its data `D` is.
+///
+/// Time is read from `C`, the real [`SystemClock`] unless the breaker was
+/// built with [`CircuitBreakerBuilder::build_with_clock`]. `D` is the user
+/// data attached with [`CircuitBreakerBuilder::data`], none by default.
+pub struct CircuitBreaker<C = SystemClock, D = ()> {
+    clock: C,
+    name: Option<String>,
+    /// The name as reported to telemetry, see
+    /// [`CircuitBreakerBuilder::metrics_label`].
+    label: Option<String>,
+    state: State,
+    failure_threshold: u32,
+    warning_threshold: Option<u32>,
+    failure_count: u32,
+    last_failure_time: Option<Instant>,
+    last_trip_reason: Option<TripReason>,
+    pub(crate) timeout: Duration,
+    recovery_time: Duration,
+    min_open_duration: Duration,
+    retry_after: Option<Duration>,
+    backoff_max: Option<Duration>,
+    open_success_count: u64,
+    open_threshold_count: u64,
+    successes: u64,
+    failures: u64,
+    timeouts: u64,
+    pub(crate) admitted: u64,
+    rejected: u64,
+    slow_calls: u64,
+    slow_call_threshold: Option<Duration>,
+    max_call_duration: Option<Duration>,
+    #[cfg(feature = "hdrhistogram")]
+    latency: hdrhistogram::Histogram<u64>,
+    latency_by_outcome: LatencyByOutcome,
+    queue_wait: LatencyBuckets,
+    default_when_open: Option<Hook<DefaultFn>>,
+    /// How long a cached success stays fresh and how to copy one, and the
+    /// last success with when it finished.
+    cache_last_success: Option<(Duration, Hook<CloneFn>)>,
+    cached_success: Option<(Box<dyn Any + Send + Sync>, Instant)>,
+    in_flight: Arc<AtomicUsize>,
+    orphans: Arc<Orphans>,
+    /// Half-open probes currently running, and the limit on them.
+    pub(crate) probes_in_flight: Arc<AtomicUsize>,
+    half_open_max_concurrent: Option<u32>,
+    half_open_min_priority: Priority,
+    lenient_probes: Option<LenientProbes>,
+    /// Limits on calls running that were admitted in other states, with
+    /// how many are running.
+    concurrency_limits: Vec<(State, u32, Arc<AtomicUsize>)>,
+    semaphore: Option<Arc<Semaphore>>,
+    /// Consecutive closed-state timeouts, and how many are tolerated.
+    timeout_count: u32,
+    timeout_threshold: Option<u32>,
+    /// How close together timeouts count as one, and when the last counted
+    /// one happened.
+    timeout_coalescing: Option<Duration>,
+    last_counted_timeout: Option<Instant>,
+    /// The half-open queue settings, and how many calls are waiting in it,
+    /// see [`SharedCircuitBreaker`](crate::SharedCircuitBreaker).
+    pub(crate) half_open_queue: Option<(usize, Duration)>,
+    pub(crate) half_open_queued: usize,
+    on_half_open: Option<Hook<dyn Fn() + Send + Sync>>,
+    on_open: Option<Hook<dyn Fn() + Send + Sync>>,
+    on_closed: Option<Hook<dyn Fn() + Send + Sync>>,
+    on_state_change: Option<Hook<dyn Fn(StateTransition) + Send + Sync>>,
+    on_shutdown: Option<Hook<dyn Fn(State) + Send + Sync>>,
+    on_result: Option<Hook<ResultHook>>,
+    on_state_change_with_data: Option<Hook<DataHook<D>>>,
+    transition_log: Option<Hook<TransitionLog>>,
+    data: D,
+    flap_detection: Option<(usize, Duration)>,
+    on_flap: Option<Hook<dyn Fn(usize) + Send + Sync>>,
+    on_clock_anomaly: Option<Hook<dyn Fn(Duration) + Send + Sync>>,
+    /// The `on_recovery_failure` count and hook.
+    recovery_failure: Option<(u32, Hook<RecoveryFailure>)>,
+    /// When recent transitions happened, for flap detection, and whether
+    /// `on_flap` already fired for the current episode.
+    flap_times: VecDeque<Instant>,
+    flapping: bool,
+    /// The `on_threshold_warning` level and hook, and whether it fired for
+    /// the current crossing.
+    threshold_warning: Option<(u8, Hook<ThresholdWarning>)>,
+    threshold_warned: bool,
+    call_batch: Option<CallBatch>,
+    on_slow_call: Option<Hook<dyn Fn(Duration) + Send + Sync>>,
+    transition_guard: Option<Hook<TransitionGuard>>,
+    state_store: Option<Hook<dyn StateStore>>,
+    /// Whether the last call was admitted under [`Override::ForceClosed`].
+    forced_closed: bool,
+    is_failure: Option<Hook<AnyPredicate>>,
+    is_fatal: Option<Hook<AnyPredicate>>,
+    evaluate: Option<Hook<AnyPredicate>>,
+    retry_after_from_error: Option<Hook<AnyRetryHint>>,
+    /// The `dedupe_errors` window and key, and the key of the error counted
+    /// last with when it was counted.
+    dedupe_errors: Option<(Duration, Hook<AnyKey>, KeyEq)>,
+    last_error: Option<(ErrorKey, Instant)>,
+    recent_failures: VecDeque<FailureRecord>,
+    recent_failures_capacity: usize,
+    transitions: VecDeque<StateTransition>,
+    /// What [`CircuitBreaker::metrics_relaxed`] reads.
+    published: Arc<PublishedMetrics>,
+    transitions_capacity: usize,
+    inline_probes: bool,
+    inline_when_closed: bool,
+    reset_on_half_open: bool,
+    trip_policy: TripPolicy,
+    failure_window: VecDeque<Instant>,
+    /// The [`TripPolicy::Decaying`] score and when it was last updated.
+    failure_score: Option<(f64, Instant)>,
+    worker_pool: Option<WorkerPool>,
+    thread_limit: Option<ThreadLimit>,
+    timeout_strategy: Option<Hook<dyn TimeoutStrategy>>,
+    no_threads: bool,
+    max_probe_attempts: Option<u32>,
+    failed_probe_cycles: u32,
+    half_open_timeout_grace: u32,
+    /// When the probe successes counted in `open_success_count` happened,
+    /// if only recent ones count.
+    half_open_success_window: Option<Duration>,
+    success_window: VecDeque<Instant>,
+    half_open_timeouts: u32,
+    half_open_idle: HalfOpenIdle,
+    /// The percentage of calls admitted while soft-open, and the credit
+    /// towards admitting the next one.
+    soft_open: Option<u8>,
+    soft_open_credit: u32,
+    /// The close ramp's window and starting percentage, when the ramp
+    /// started, and the credit towards admitting the next call, like
+    /// `soft_open_credit`.
+    close_ramp: Option<(Duration, u8)>,
+    ramp_since: Option<Instant>,
+    ramp_credit: u32,
+    rejection_policy: RejectionPolicy,
+    in_flight_after_trip: InFlightAfterTrip,
+    /// When the breaker last entered half-open, and how many probes it has
+    /// admitted since.
+    half_open_since: Option<Instant>,
+    half_open_probes: u32,
+    /// When the current state was entered, and the time spent in earlier
+    /// stretches of each state.
+    state_since: Instant,
+    time_in_state: StateDurations,
+    replay: Option<Replay<C, D>>,
+    late_results: Option<Arc<LateResults>>,
+    count_panics: bool,
+    enabled: bool,
+    #[cfg(feature = "tokio")]
+    pub(crate) transition_tx: tokio::sync::broadcast::Sender<StateTransition>,
+}
+
+/// A failed call kept by [`CircuitBreaker::call_replayable`] to be run again
+/// as the next half-open probe.
+type Replay<C, D> = Box<dyn FnOnce(&mut CircuitBreaker<C, D>) + Send + Sync>;
+
+/// Counts a call as in flight until dropped, including when the protected
+/// function panics.
+pub(crate) struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    pub(crate) fn new(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(Arc::clone(counter))
+    }
+
+    /// Takes over a count the caller already added to `counter`.
+    pub(crate) fn counted(counter: &Arc<AtomicUsize>) -> Self {
+        InFlightGuard(Arc::clone(counter))
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        failure_threshold: u32,
+        timeout: Duration,
+        recovery_time: Duration,
+        open_threshold_count: u64,
+    ) -> Self {
+        CircuitBreaker::with_clock(
+            failure_threshold,
+            timeout,
+            recovery_time,
+            open_threshold_count,
+            SystemClock,
+            (),
+        )
+    }
+
+    /// Equivalent to [`CircuitBreaker::new`] with `timeout` and
+    /// `recovery_time` given in milliseconds.
+    #[deprecated(note = "use `CircuitBreaker::new` or the builder with `Duration` arguments")]
+    pub fn new_millis(
+        failure_threshold: u32,
+        timeout: u64,
+        recovery_time: u64,
+        open_threshold_count: u64,
+    ) -> Self {
+        CircuitBreaker::new(
+            failure_threshold,
+            Duration::from_millis(timeout),
+            Duration::from_millis(recovery_time),
+            open_threshold_count,
+        )
+    }
+
+    pub fn builder() -> CircuitBreakerBuilder {
+        CircuitBreakerBuilder::default()
+    }
+}
+
+impl<C: Clock, D> CircuitBreaker<C, D> {
+    pub(crate) fn with_clock(
+        failure_threshold: u32,
+        timeout: Duration,
+        recovery_time: Duration,
+        open_threshold_count: u64,
+        clock: C,
+        data: D,
+    ) -> Self {
+        CircuitBreaker {
+            name: None,
+            label: None,
+            state: State::Closed,
+            failure_threshold,
+            warning_threshold: None,
+            failure_count: 0,
+            last_failure_time: None,
+            last_trip_reason: None,
+            recovery_time,
+            min_open_duration: Duration::ZERO,
+            retry_after: None,
+            backoff_max: None,
+            timeout,
+            open_success_count: 0,
+            open_threshold_count,
+            successes: 0,
+            failures: 0,
+            timeouts: 0,
+            admitted: 0,
+            rejected: 0,
+            slow_calls: 0,
+            slow_call_threshold: None,
+            max_call_duration: None,
+            #[cfg(feature = "hdrhistogram")]
+            latency: hdrhistogram::Histogram::new(3).expect("3 significant figures is valid"),
+            latency_by_outcome: LatencyByOutcome::default(),
+            queue_wait: LatencyBuckets::default(),
+            default_when_open: None,
+            cache_last_success: None,
+            cached_success: None,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            orphans: Arc::default(),
+            probes_in_flight: Arc::new(AtomicUsize::new(0)),
+            half_open_max_concurrent: None,
+            half_open_min_priority: Priority::Low,
+            lenient_probes: None,
+            concurrency_limits: Vec::new(),
+            semaphore: None,
+            timeout_count: 0,
+            timeout_threshold: None,
+            timeout_coalescing: None,
+            last_counted_timeout: None,
+            half_open_queue: None,
+            half_open_queued: 0,
+            on_half_open: None,
+            on_open: None,
+            on_closed: None,
+            on_state_change: None,
+            on_shutdown: None,
+            on_result: None,
+            on_state_change_with_data: None,
+            transition_log: None,
+            data,
+            flap_detection: None,
+            on_flap: None,
+            on_clock_anomaly: None,
+            recovery_failure: None,
+            flap_times: VecDeque::new(),
+            flapping: false,
+            threshold_warning: None,
+            threshold_warned: false,
+            call_batch: None,
+            on_slow_call: None,
+            transition_guard: None,
+            state_store: None,
+            forced_closed: false,
+            is_failure: None,
+            is_fatal: None,
+            evaluate: None,
+            retry_after_from_error: None,
+            dedupe_errors: None,
+            last_error: None,
+            recent_failures: VecDeque::new(),
+            recent_failures_capacity: 10,
+            transitions: VecDeque::new(),
+            published: Arc::new(PublishedMetrics::new(State::Closed)),
+            transitions_capacity: 32,
+            inline_probes: false,
+            inline_when_closed: false,
+            reset_on_half_open: true,
+            trip_policy: TripPolicy::Consecutive,
+            failure_window: VecDeque::new(),
+            failure_score: None,
+            worker_pool: None,
+            thread_limit: None,
+            timeout_strategy: None,
+            no_threads: false,
+            max_probe_attempts: None,
+            failed_probe_cycles: 0,
+            half_open_timeout_grace: 0,
+            half_open_success_window: None,
+            success_window: VecDeque::new(),
+            half_open_timeouts: 0,
+            half_open_idle: HalfOpenIdle::Stay,
+            soft_open: None,
+            soft_open_credit: 0,
+            close_ramp: None,
+            ramp_since: None,
+            ramp_credit: 0,
+            rejection_policy: RejectionPolicy::Error,
+            in_flight_after_trip: InFlightAfterTrip::Count,
+            half_open_since: None,
+            half_open_probes: 0,
+            state_since: clock.now(),
+            time_in_state: StateDurations::default(),
+            replay: None,
+            late_results: None,
+            count_panics: false,
+            enabled: true,
+            #[cfg(feature = "tokio")]
+            transition_tx: tokio::sync::broadcast::channel(16).0,
+            clock,
+        }
+    }
+
+    pub(crate) fn from_builder(builder: CircuitBreakerBuilder<D>, clock: C) -> Self {
+        let mut cb = CircuitBreaker::with_clock(
+            builder.failure_threshold,
+            builder.timeout,
+            builder.recovery_time,
+            builder.half_open_successes,
+            clock,
+            builder.data,
+        );
+        cb.label = match (&builder.name, &builder.metrics_label) {
+            (Some(name), Some(label)) => Some((label.0)(name)),
+            (name, _) => name.clone(),
+        };
+        cb.name = builder.name;
+        cb.min_open_duration = builder.min_open_duration;
+        cb.default_when_open = builder.default_when_open;
+        cb.cache_last_success = builder.cache_last_success;
+        cb.on_half_open = builder.on_half_open;
+        cb.on_open = builder.on_open;
+        cb.on_closed = builder.on_closed;
+        cb.on_state_change = builder.on_state_change;
+        cb.on_shutdown = builder.on_shutdown;
+        cb.on_result = builder.on_result;
+        cb.on_state_change_with_data = builder.on_state_change_with_data;
+        cb.transition_log = builder.transition_log;
+        cb.flap_detection = builder.flap_detection;
+        cb.on_flap = builder.on_flap;
+        cb.on_clock_anomaly = builder.on_clock_anomaly;
+        cb.recovery_failure = builder.recovery_failure;
+        cb.threshold_warning = builder.threshold_warning;
+        cb.half_open_max_concurrent = builder.half_open_max_concurrent;
+        cb.half_open_min_priority = builder.half_open_min_priority;
+        cb.concurrency_limits = builder
+            .concurrency_limits
+            .iter()
+            .map(|&(state, calls)| (state, calls, Arc::new(AtomicUsize::new(0))))
+            .collect();
+        cb.semaphore = builder.semaphore;
+        cb.timeout_threshold = builder.timeout_threshold;
+        cb.timeout_coalescing = builder.timeout_coalescing;
+        cb.half_open_queue = builder.half_open_queue;
+        cb.call_batch = builder
+            .batch_telemetry
+            .map(|(max_pending, interval)| CallBatch::new(max_pending, interval));
+        cb.on_slow_call = builder.on_slow_call;
+        cb.slow_call_threshold = builder.slow_call_threshold;
+        cb.lenient_probes = builder.lenient_probes;
+        cb.max_call_duration = builder.max_call_duration;
+        cb.transition_guard = builder.transition_guard;
+        cb.state_store = builder.state_store;
+        cb.is_failure = hooks::any_of(builder.is_failure, true);
+        cb.is_fatal = hooks::any_of(builder.is_fatal, false);
+        cb.evaluate = builder.evaluate;
+        cb.retry_after_from_error = builder.retry_after_from_error;
+        cb.dedupe_errors = builder.dedupe_errors;
+        cb.backoff_max = builder.backoff_max;
+        cb.recent_failures_capacity = builder.recent_failures;
+        cb.transitions_capacity = builder.transition_history;
+        cb.inline_probes = builder.inline_probes;
+        cb.inline_when_closed = builder.inline_when_closed;
+        cb.count_panics = builder.count_panics;
+        cb.reset_on_half_open = builder.reset_on_half_open;
+        cb.trip_policy = builder.trip_policy;
+        cb.worker_pool = builder.worker_pool;
+        cb.thread_limit = builder.thread_limit;
+        cb.timeout_strategy = builder.timeout_strategy;
+        cb.no_threads = builder.no_threads;
+        cb.max_probe_attempts = builder.max_probe_attempts;
+        cb.warning_threshold = builder.warning_threshold;
+        cb.half_open_timeout_grace = builder.half_open_timeout_grace;
+        cb.half_open_success_window = builder.half_open_success_window;
+        cb.half_open_idle = builder.half_open_idle;
+        cb.soft_open = builder.soft_open;
+        cb.close_ramp = builder.close_ramp;
+        cb.rejection_policy = builder.rejection_policy;
+        cb.in_flight_after_trip = builder.in_flight_after_trip;
+        cb.late_results = builder
+            .late_results
+            .map(|cap| Arc::new(LateResults::new(cap)));
+        cb.state = builder.initial_state;
+        if let Some(ago) = builder.last_failure_ago {
+            // A failure too long ago to represent is taken as just long
+            // enough ago for the recovery time to have passed.
+            let now = cb.clock.now();
+            cb.last_failure_time = now
+                .checked_sub(ago)
+                .or_else(|| now.checked_sub(cb.recovery_time));
+        } else if cb.state == State::Open {
+            // Recovery is timed from the last failure, so count the breaker
+            // as having just failed.
+            cb.last_failure_time = Some(cb.clock.now());
+        }
+        telemetry::state(cb.label.as_deref(), cb.state);
+        cb.published.set_state(cb.state);
+        cb
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub(crate) fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns whether a `call` made now would be accepted rather than
+    /// rejected, without executing anything or changing state.
+    ///
+    /// This is `false` when `call` would return
+    /// [`CircuitBreakerError::Rejected`] now, e.g. while the breaker is open
+    /// with the recovery time not yet elapsed, throttled, half-open with
+    /// every probe slot taken, or out of threads or concurrency slots. The
+    /// answer can be stale by the time `call` runs, so `call` remains the
+    /// authoritative check.
+    pub fn would_admit(&self) -> bool {
+        self.turned_away(self.read_override(), Priority::Normal)
+            .is_none()
+    }
+
+    /// Why a call of `priority` made now would be rejected, without
+    /// changing or acquiring anything, or `None` if it would run or only
+    /// move an open breaker to half-open. `forced` is the controller's
+    /// override.
+    fn turned_away(&self, forced: Override, priority: Priority) -> Option<RejectionReason> {
+        let bypassed = match forced {
+            Override::ForceOpen => return Some(RejectionReason::ForcedOpen),
+            Override::ForceClosed => true,
+            Override::Auto => !self.enabled,
+        };
+        let inline = self.admits_inline(bypassed);
+        if self.threads_exhausted(inline) && (bypassed || self.state != State::Open) {
+            return Some(RejectionReason::ResourceExhausted);
+        }
+        if bypassed {
+            return None;
+        }
+        match self.state {
+            State::Open => self.open_rejection(),
+            State::HalfOpen if !self.may_probe(priority) => Some(RejectionReason::LowPriority),
+            State::HalfOpen if self.probes_busy() => Some(RejectionReason::ProbesBusy),
+            _ if self.throttled() => Some(RejectionReason::Throttled),
+            _ => self.slots_full(),
+        }
+    }
+
+    /// Why an open breaker would reject a call now, or `None` if the call
+    /// moves it to half-open.
+    fn open_rejection(&self) -> Option<RejectionReason> {
+        if self.probes_exhausted() {
+            Some(RejectionReason::ProbesExhausted)
+        } else if !self.recovery_elapsed() {
+            Some(RejectionReason::Open)
+        } else {
+            None
+        }
+    }
+
+    fn soft_open_percent(&self) -> u32 {
+        self.soft_open.map_or(100, u32::from)
+    }
+
+    /// The percentage of calls admitted in the current state if it is
+    /// throttled: soft-open, or closed while the close ramp lasts.
+    fn throttle_percent(&self) -> Option<u32> {
+        match self.state {
+            State::SoftOpen => Some(self.soft_open_percent()),
+            State::Closed | State::Degraded => self.ramp_percent(),
+            State::Open | State::HalfOpen => None,
+        }
+    }
+
+    /// The credit that spreads a throttled state's admissions evenly: each
+    /// call adds the percentage admitted, and runs if that makes 100.
+    fn throttle_credit(&mut self) -> &mut u32 {
+        match self.state {
+            State::SoftOpen => &mut self.soft_open_credit,
+            _ => &mut self.ramp_credit,
+        }
+    }
+
+    /// Whether the throttle turns the next call away.
+    fn throttled(&self) -> bool {
+        let credit = match self.state {
+            State::SoftOpen => self.soft_open_credit,
+            _ => self.ramp_credit,
+        };
+        self.throttle_percent()
+            .is_some_and(|percent| credit + percent < 100)
+    }
+
+    /// The percentage of calls admitted while the close ramp lasts, or
+    /// `None` once it is over.
+    fn ramp_percent(&self) -> Option<u32> {
+        let (window, from) = self.close_ramp?;
+        let elapsed = self.since(self.ramp_since?);
+        if elapsed >= window {
+            return None;
+        }
+        let from = f64::from(from);
+        let ramped = (100.0 - from) * elapsed.as_secs_f64() / window.as_secs_f64();
+        Some((from + ramped) as u32)
+    }
+
+    fn recovery_elapsed(&self) -> bool {
+        !self.probes_exhausted()
+            && self
+                .last_failure_time
+                .is_none_or(|t| self.since(t) >= self.effective_recovery_time())
+            && self.min_open_elapsed()
+    }
+
+    /// Whether the breaker has been open for at least the
+    /// [`min_open_duration`](crate::CircuitBreakerBuilder::min_open_duration).
+    fn min_open_elapsed(&self) -> bool {
+        self.state != State::Open || self.since(self.state_since) >= self.min_open_duration
+    }
+
+    /// Whether the breaker has used up its probe attempts and stays open
+    /// until [`CircuitBreaker::reset`].
+    pub fn probes_exhausted(&self) -> bool {
+        self.max_probe_attempts
+            .is_some_and(|max| self.failed_probe_cycles >= max)
+    }
+
+    /// The settings [`CircuitBreaker::reconfigure`] can change, as they are
+    /// now.
+    pub fn config(&self) -> Config {
+        Config {
+            failure_threshold: self.failure_threshold,
+            timeout: self.timeout,
+            recovery_time: self.recovery_time,
+            half_open_successes: self.open_threshold_count,
+        }
+    }
+
+    /// Replaces the breaker's settings without losing its state or counters,
+    /// e.g. to tighten the threshold during an incident.
+    ///
+    /// `config` is validated like the builder's settings and nothing changes
+    /// if it is rejected. The new threshold and half-open success count
+    /// apply from the next call; counters already past them are lowered to
+    /// them, so the change itself never trips or closes the breaker. A new
+    /// recovery time also applies to a breaker that is already open, timed
+    /// from when it last failed. A new timeout applies to calls started
+    /// afterwards.
+    pub fn reconfigure(&mut self, config: Config) -> Result<(), BuildError> {
+        validate_config(&config, self.no_threads, self.warning_threshold)?;
+
+        self.failure_threshold = config.failure_threshold;
+        self.timeout = config.timeout;
+        self.recovery_time = config.recovery_time;
+        self.open_threshold_count = config.half_open_successes;
+        self.failure_count = self.failure_count.min(self.failure_threshold);
+        self.open_success_count = self.open_success_count.min(self.open_threshold_count - 1);
+        while self.success_window.len() as u64 > self.open_success_count {
+            self.success_window.pop_front();
+        }
+        self.check_invariants();
+        Ok(())
+    }
+
+    /// A one-line summary of the breaker's state and counters for periodic
+    /// logging, e.g.
+    /// `cb[payments] state=open failures=5/3 retry_in=8s admitted=1020 rejected=142`.
+    ///
+    /// `failures` is the current count against the threshold, `retry_in`
+    /// only appears while open, and `admitted` and `rejected` are totals.
+    pub fn status_line(&self) -> String {
+        let mut line = match &self.name {
+            Some(name) => format!("cb[{name}]"),
+            None => "cb".to_string(),
+        };
+        let _ = write!(
+            line,
+            " state={} failures={}/{}",
+            self.state.as_str(),
+            self.failure_count,
+            self.failure_threshold
+        );
+        if let Some(retry_in) = self.time_until_retry() {
+            let _ = write!(line, " retry_in={:?}", rounded(retry_in));
+        }
+        let _ = write!(
+            line,
+            " admitted={} rejected={}",
+            self.admitted, self.rejected
+        );
+        line
+    }
+
+    /// The breaker's state machine as a Graphviz DOT graph, with the
+    /// configured thresholds on the edges, e.g.
+    /// `closed -> open [label="more than 3 failures in a row"];`. States the
+    /// configuration never reaches are left out, and the current state is
+    /// drawn bold. Render it with e.g. `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let threshold = self.failure_threshold;
+        let failures = match self.trip_policy {
+            TripPolicy::Consecutive => format!("more than {threshold} failures in a row"),
+            TripPolicy::Windowed { window } => {
+                format!("more than {threshold} failures in {window:?}")
+            }
+            TripPolicy::ConsecutiveOrWindowed { window, total } => {
+                format!("more than {threshold} failures in a row or {total} in {window:?}")
+            }
+            TripPolicy::Decaying { half_life } => {
+                format!("failure score above {threshold}, half-life {half_life:?}")
+            }
+        };
+        let trips_to = match self.soft_open {
+            Some(_) => State::SoftOpen,
+            None => State::Open,
+        };
+        let successes = match self.open_threshold_count {
+            1 => "1 success".to_string(),
+            n => format!("{n} successes in a row"),
+        };
+        let mut edges = vec![(State::Closed, trips_to, failures.clone())];
+        if self.timeout != Duration::MAX {
+            let timeouts = match self.timeout_threshold {
+                Some(n) => format!("more than {n} timeouts"),
+                None => format!("a call over {:?}", self.timeout),
+            };
+            edges.push((State::Closed, State::Open, timeouts));
+        }
+        if let Some(warning) = self.warning_threshold {
+            edges.push((
+                State::Closed,
+                State::Degraded,
+                format!("more than {warning} failures"),
+            ));
+            edges.push((
+                State::Degraded,
+                State::Closed,
+                format!("{warning} failures or fewer"),
+            ));
+            edges.push((State::Degraded, trips_to, failures));
+        }
+        let recovery = match self.backoff_max {
+            Some(max) => format!("after {:?}, backing off to {max:?}", self.recovery_time),
+            None => format!("after {:?}", self.recovery_time),
+        };
+        edges.push((State::Open, State::HalfOpen, recovery));
+        edges.push((State::HalfOpen, State::Closed, successes.clone()));
+        edges.push((State::HalfOpen, State::Open, "a failed probe".to_string()));
+        if let HalfOpenIdle::Reopen { after } = self.half_open_idle {
+            edges.push((State::HalfOpen, State::Open, format!("idle for {after:?}")));
+        }
+        if self.soft_open.is_some() {
+            edges.push((State::SoftOpen, State::Closed, successes));
+            edges.push((State::SoftOpen, State::Open, "a failure".to_string()));
+        }
+
+        let mut dot = String::from("digraph circuit_breaker {\n");
+        if let Some(name) = &self.name {
+            let _ = writeln!(dot, "    label=\"{}\";", name.replace('"', "\\\""));
+        }
+        let mut states: Vec<State> = Vec::new();
+        for &(from, to, _) in &edges {
+            for state in [from, to] {
+                if !states.contains(&state) {
+                    states.push(state);
+                }
+            }
+        }
+        for state in states {
+            let style = if state == self.state {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(dot, "    {} [shape=circle{style}];", state.as_str());
+        }
+        for (from, to, label) in edges {
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label=\"{label}\"];",
+                from.as_str(),
+                to.as_str()
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Checks that the breaker is wired up sensibly, for smoke tests and
+    /// readiness probes: that its clock is sane and its settings are valid
+    /// and consistent, and which state it is in. Nothing is run and nothing
+    /// changes.
+    pub fn self_test(&self) -> SelfTestReport {
+        let now = self.clock.now();
+        let clock_ok = now >= self.state_since && self.clock.now() >= now;
+        let mut problems = Vec::new();
+        if let Err(e) = validate_config(&self.config(), self.no_threads, self.warning_threshold) {
+            problems.push(e.to_string());
+        }
+        if self.recovery_time < self.min_open_duration {
+            problems.push(format!(
+                "recovery_time {:?} is shorter than min_open_duration {:?}, so it never applies",
+                self.recovery_time, self.min_open_duration
+            ));
+        }
+        SelfTestReport {
+            state: self.state,
+            clock_ok,
+            problems,
+        }
+    }
+
+    /// Forgets recent failures, clearing the trip window or score, the
+    /// failure count and [`CircuitBreaker::recent_failures`], e.g. after a known transient
+    /// incident. An open or half-open breaker stays so; a degraded one
+    /// returns to closed. Totals in [`CircuitBreaker::metrics`] are kept.
+    pub fn flush_window(&mut self) {
+        self.failure_window.clear();
+        self.failure_score = None;
+        self.recent_failures.clear();
+        self.failure_count = 0;
+        self.timeout_count = 0;
+        self.update_degraded();
+    }
+
+    /// Turns the breaker off or back on, e.g. to bypass it during a canary
+    /// or an incident.
+    ///
+    /// While disabled every call runs, whatever the state, and its outcome
+    /// shows up in [`CircuitBreaker::metrics`] but never moves the breaker.
+    /// Re-enabling carries on from the state it was left in.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Closes the breaker and clears its failure and probe counters, as if it
+    /// had just been built. Totals in [`CircuitBreaker::metrics`] and the
+    /// recorded history are kept.
+    pub fn reset(&mut self) {
+        self.enter(State::Closed);
+        self.failure_count = 0;
+        self.timeout_count = 0;
+        self.last_counted_timeout = None;
+        self.threshold_warned = false;
+        self.open_success_count = 0;
+        self.failed_probe_cycles = 0;
+        self.last_failure_time = None;
+        self.failure_window.clear();
+        self.failure_score = None;
+        self.replay = None;
+    }
+
+    /// Opens the breaker by hand, e.g. when an operator knows the
+    /// dependency is down, bypassing any transition guard. It recovers
+    /// like after any other trip. What happens to calls already running is
+    /// up to [`in_flight_after_trip`](CircuitBreakerBuilder::in_flight_after_trip).
+    pub fn trip(&mut self) {
+        self.last_trip_reason = Some(TripReason::Manual);
+        self.enter(State::Open);
+        self.last_failure_time = Some(self.clock.now());
+    }
+
+    /// Restarts the recovery time of an open breaker, e.g. after a manual
+    /// fix failed, so it waits the full recovery time from now before
+    /// probing again. Unlike [`reset`](Self::reset) it stays open. Does
+    /// nothing unless the breaker is open.
+    pub fn restart_recovery(&mut self) {
+        if self.state == State::Open {
+            self.last_failure_time = Some(self.clock.now());
+        }
+    }
+
+    /// Moves an open breaker whose recovery time has elapsed to half-open
+    /// now rather than on the next call, firing the same hooks, so a
+    /// breaker that sees little traffic is ready to probe as soon as it
+    /// may. Returns whether it moved. Nothing happens while the breaker is
+    /// disabled, forced either way by its state store or out of probe
+    /// attempts, or if the transition guard vetoes it. See
+    /// [`SharedCircuitBreaker::spawn_recovery_timer`](crate::SharedCircuitBreaker::spawn_recovery_timer)
+    /// to call this from a background thread.
+    pub fn poll_recovery(&mut self) -> bool {
+        if self.state != State::Open
+            || !self.enabled
+            || self.read_override() != Override::Auto
+            || self.probes_exhausted()
+            || !self.recovery_elapsed()
+        {
+            return false;
+        }
+        self.transition_to(State::HalfOpen);
+        if self.state != State::HalfOpen {
+            return false;
+        }
+        self.begin_half_open();
+        true
+    }
+
+    /// Shuts the breaker down, waiting up to `grace` for workers whose call
+    /// timed out to finish, and joining the threads it spawned for them.
+    /// Returns how many were still running when `grace` ran out; those are
+    /// left to finish on their own. Calls that did not time out have already
+    /// finished, since they borrow the breaker.
+    ///
+    /// Then [`on_shutdown`](CircuitBreakerBuilder::on_shutdown) is called
+    /// with the final state and, with the `tokio` feature, `subscribe_async`
+    /// receivers see the channel close. There is nothing to flush: a
+    /// [`StateStore`] is only ever read. Taking `self` rules out use after
+    /// shutdown.
+    pub fn shutdown(self, grace: Duration) -> usize {
+        let running = self.orphans.join(grace);
+        if let Some(hook) = &self.on_shutdown {
+            (hook.0)(self.state);
+        }
+        running
+    }
+
+    /// The data attached with [`CircuitBreakerBuilder::data`].
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    /// The data attached with [`CircuitBreakerBuilder::data`], to update.
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    /// Number of protected calls currently executing.
+    ///
+    /// Calls that timed out keep counting until their worker thread actually
+    /// finishes, so a growing count points at workers stuck on a slow
+    /// dependency.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Number of workers whose call timed out but which are still running,
+    /// the part of [`CircuitBreaker::in_flight`] nobody is waiting for.
+    ///
+    /// Each one drops out of the count itself when it finishes. A count that
+    /// keeps climbing means the timeout is shorter than the dependency's
+    /// real latency, and every timed-out call is still holding a thread.
+    pub fn orphaned_workers(&self) -> usize {
+        self.orphans.count.load(Ordering::SeqCst)
+    }
+
+    /// Joins the threads spawned for timed-out calls that have since
+    /// finished, and drops their handles.
+    ///
+    /// The breaker keeps the thread of every call it gives up on, to join it
+    /// on [`shutdown`](Self::shutdown). Finished ones are also joined
+    /// whenever another call times out, so this only frees them sooner after
+    /// a burst of timeouts.
+    pub fn reap_finished(&mut self) {
+        self.orphans.reap();
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn in_flight_guard(&self) -> InFlightGuard {
+        InFlightGuard::new(&self.in_flight)
+    }
+
+    /// How long until an open breaker lets a call through again, or `None`
+    /// if it is not open.
+    pub fn time_until_retry(&self) -> Option<Duration> {
+        if self.state != State::Open {
+            return None;
+        }
+        let elapsed = self
+            .last_failure_time
+            .map_or(Duration::MAX, |t| self.since(t));
+        let floor = self
+            .min_open_duration
+            .saturating_sub(self.since(self.state_since));
+        Some(
+            self.effective_recovery_time()
+                .saturating_sub(elapsed)
+                .max(floor),
+        )
+    }
+
+    /// The [`TripPolicy::Decaying`] failure score as of now, compared with
+    /// the failure threshold on every failure. Always zero under the other
+    /// trip policies.
+    pub fn failure_score(&self) -> f64 {
+        let TripPolicy::Decaying { half_life } = self.trip_policy else {
+            return 0.0;
+        };
+        self.failure_score.map_or(0.0, |(score, at)| {
+            score * 0.5f64.powf(self.since(at).as_secs_f64() / half_life.as_secs_f64())
+        })
+    }
+
+    /// Time passed since `t` by the breaker's clock.
+    fn since(&self, t: Instant) -> Duration {
+        self.between(t, self.clock.now())
+    }
+
+    /// Time from `earlier` to `later`, both read from the breaker's clock.
+    /// Zero if the clock went backwards in between, which is reported to
+    /// `on_clock_anomaly`.
+    fn between(&self, earlier: Instant, later: Instant) -> Duration {
+        later.checked_duration_since(earlier).unwrap_or_else(|| {
+            if let Some(hook) = &self.on_clock_anomaly {
+                (hook.0)(earlier.duration_since(later));
+            }
+            Duration::ZERO
+        })
+    }
+
+    /// How long the breaker stays open this time, as opposed to the
+    /// configured `recovery_time`: the retry hint of the error that opened
+    /// it, if there was one, or `recovery_time`, doubled for each failed
+    /// probe cycle when backoff is on. While not open, the window the next
+    /// trip would use.
+    pub fn effective_recovery_time(&self) -> Duration {
+        if let Some(retry_after) = self.retry_after {
+            return retry_after;
+        }
+        let Some(max) = self.backoff_max else {
+            return self.recovery_time;
+        };
+        // 2^31 times any non-zero recovery time is already over a year, and
+        // capping the exponent keeps the shift itself from overflowing.
+        let exponent = self.failed_probe_cycles.min(31);
+        self.recovery_time
+            .checked_mul(1 << exponent)
+            .map_or(max, |window| window.min(max))
+    }
+
+    /// What tripped the breaker the last time it opened or went soft-open,
+    /// or `None` if it never has. Kept after it recovers, and by
+    /// [`CircuitBreaker::reset`].
+    pub fn last_trip_reason(&self) -> Option<TripReason> {
+        self.last_trip_reason
+    }
+
+    /// The most recent failures, oldest first.
+    pub fn recent_failures(&self) -> Vec<FailureRecord> {
+        self.recent_failures.iter().cloned().collect()
+    }
+
+    fn push_recent_failure(
+        &mut self,
+        kind: FailureKind,
+        label: Option<&str>,
+        message: Option<String>,
+    ) {
+        if self.recent_failures_capacity == 0 {
+            return;
+        }
+        if self.recent_failures.len() == self.recent_failures_capacity {
+            self.recent_failures.pop_front();
+        }
+        self.recent_failures.push_back(FailureRecord {
+            label: label.map(str::to_owned),
+            kind,
+            message,
+            at: self.clock.now(),
+        });
+    }
+
+    /// Takes the results of timed-out calls that finished since the last
+    /// drain, oldest first. Always empty unless enabled with
+    /// [`CircuitBreakerBuilder::late_results`].
+    pub fn drain_late_results(&self) -> Vec<LateResult> {
+        self.late_results
+            .as_ref()
+            .map_or_else(Vec::new, |late| late.drain())
+    }
+
+    /// The most recent state transitions, oldest first.
+    pub fn transition_history(&self) -> Vec<StateTransition> {
+        self.transitions.iter().copied().collect()
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            name: self.name.clone(),
+            label: self.label.clone(),
+            state: self.state,
+            failure_count: self.failure_count,
+            successes: self.successes,
+            failures: self.failures,
+            timeouts: self.timeouts,
+            admitted: self.admitted,
+            rejected: self.rejected,
+            slow_calls: self.slow_calls,
+            #[cfg(feature = "hdrhistogram")]
+            latency_p50: self.latency_percentile(50.0),
+            #[cfg(feature = "hdrhistogram")]
+            latency_p90: self.latency_percentile(90.0),
+            #[cfg(feature = "hdrhistogram")]
+            latency_p99: self.latency_percentile(99.0),
+            latency_by_outcome: self.latency_by_outcome,
+            queue_wait: self.queue_wait,
+            time_in_state: {
+                let mut durations = self.time_in_state;
+                durations.add(self.state, self.since(self.state_since));
+                durations
+            },
+        }
+    }
+
+    /// The counters of [`CircuitBreaker::metrics`] read without taking a
+    /// consistent snapshot, see
+    /// [`SharedCircuitBreaker::metrics_relaxed`](crate::SharedCircuitBreaker::metrics_relaxed)
+    /// for where that matters.
+    pub fn metrics_relaxed(&self) -> RelaxedMetrics {
+        self.published.load()
+    }
+
+    pub(crate) fn published_metrics(&self) -> Arc<PublishedMetrics> {
+        Arc::clone(&self.published)
+    }
+
+    /// Copies the counters to where [`CircuitBreaker::metrics_relaxed`]
+    /// reads them.
+    fn publish_metrics(&self) {
+        self.published.store(RelaxedMetrics {
+            state: self.state,
+            successes: self.successes,
+            failures: self.failures,
+            timeouts: self.timeouts,
+            admitted: self.admitted,
+            rejected: self.rejected,
+            slow_calls: self.slow_calls,
+        });
+    }
+
+    /// The fraction of calls, since the breaker was built, that it rejected
+    /// while open, from 0.0 to 1.0. Calls served
+    /// [`default_when_open`](CircuitBreakerBuilder::default_when_open)
+    /// count as rejected; the call that only moves the breaker to half-open
+    /// counts as neither. Returns 0.0 before any call was made.
+    pub fn rejection_ratio(&self) -> f64 {
+        let attempted = self.admitted + self.rejected;
+        if attempted == 0 {
+            return 0.0;
+        }
+        self.rejected as f64 / attempted as f64
+    }
+
+    /// Latency of successful and [measured](Self::measure) calls at
+    /// percentile `p` (0.0 to 100.0), or `None` if nothing was recorded yet.
+    /// Recorded at nanosecond precision.
+    #[cfg(feature = "hdrhistogram")]
+    pub fn latency_percentile(&self, p: f64) -> Option<Duration> {
+        if self.latency.is_empty() {
+            return None;
+        }
+        Some(Duration::from_nanos(self.latency.value_at_percentile(p)))
+    }
+
+    pub(crate) fn record_latency(&mut self, _elapsed: Duration) {
+        // The histogram auto-resizes, so recording only fails for values too
+        // large to track at all; those are dropped.
+        #[cfg(feature = "hdrhistogram")]
+        let _ = self
+            .latency
+            .record(u64::try_from(_elapsed.as_nanos()).unwrap_or(u64::MAX));
+    }
+
+    pub(crate) fn record_outcome_latency(&mut self, outcome: CallOutcome, elapsed: Duration) {
+        self.latency_by_outcome.record(outcome, elapsed);
+    }
+
+    pub(crate) fn record_queue_wait(&mut self, waited: Duration) {
+        self.queue_wait.record(waited);
+    }
+
+    /// Runs `func` on a worker thread unless the breaker is open, and
+    /// records whether it succeeded, failed or timed out.
+    ///
+    /// Returns `Ok(Some(value))` on success and `Ok(None)` for the call that
+    /// finds the recovery time elapsed: that call moves the breaker to
+    /// half-open without running `func`. While the breaker is open, calls
+    /// are rejected with [`CircuitBreakerError::Rejected`], or return
+    /// `Ok(None)` or a default value instead, depending on the
+    /// [`RejectionPolicy`].
+    ///
+    /// `func` runs on another thread and may outlive this call if it times
+    /// out, so it must be `Send + 'static`, see [`RunsOnWorkerThread`].
+    /// Boxed closures work too, since `Box<dyn FnOnce() -> Result<R, E> +
+    /// Send>` is itself `FnOnce`.
+    ///
+    /// `R` and `E` belong to the call, not the breaker, so one breaker can
+    /// guard several different operations, e.g. every endpoint behind one
+    /// API key, and their failures all count against the same threshold.
+    pub fn call<F, R, E>(&mut self, func: F) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(None, Priority::Normal, func)
+    }
+
+    /// Like [`CircuitBreaker::call`], with a priority that decides whether
+    /// the call may be a probe while the breaker is half-open, see
+    /// [`half_open_min_priority`](CircuitBreakerBuilder::half_open_min_priority).
+    /// It makes no difference in any other state.
+    pub fn call_with_priority<F, R, E>(
+        &mut self,
+        priority: Priority,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(None, priority, func)
+    }
+
+    /// Like [`CircuitBreaker::call`], but also returns the state the breaker
+    /// is in right after the call, so the two cannot disagree.
+    pub fn call_with_state<F, R, E>(
+        &mut self,
+        func: F,
+    ) -> (Result<Option<R>, CircuitBreakerError<E>>, State)
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let res = self.call(func);
+        (res, self.state)
+    }
+
+    /// Like [`CircuitBreaker::call`], but also says how the breaker counted
+    /// the call, so an error the breaker ignored can be logged differently
+    /// from one that brought it closer to tripping.
+    pub fn call_classified<F, R, E>(
+        &mut self,
+        func: F,
+    ) -> (Result<Option<R>, CircuitBreakerError<E>>, Classification)
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let successes = self.successes;
+        let failures = self.failures + self.timeouts;
+        let res = self.call(func);
+        let class = if self.successes > successes {
+            Classification::Success
+        } else if self.failures + self.timeouts > failures {
+            Classification::CountedFailure
+        } else if matches!(res, Ok(None) | Err(CircuitBreakerError::Rejected(_))) {
+            Classification::NotRun
+        } else {
+            Classification::IgnoredFailure
+        };
+        (res, class)
+    }
+
+    /// Like [`CircuitBreaker::call`], but tags any failure or timeout of this
+    /// call with `label` in [`CircuitBreaker::recent_failures`], so one
+    /// breaker can guard several related operations and still attribute
+    /// problems to the right one.
+    pub fn call_labeled<F, R, E>(
+        &mut self,
+        label: &str,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(Some(label), Priority::Normal, func)
+    }
+
+    /// Like [`CircuitBreaker::call`], for a function that cannot fail. It
+    /// can still time out, panic or be rejected, which is what the error
+    /// reports.
+    pub fn call_infallible<F, R>(
+        &mut self,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<Infallible>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.call(move || Ok(func()))
+    }
+
+    /// Runs `func` right here, outside the breaker, and records how long it
+    /// took in the latency histogram, e.g. to measure a dependency's normal
+    /// latency before picking a `timeout` or `slow_call_threshold`.
+    ///
+    /// Nothing else is recorded: whatever `func` returns, the call is never
+    /// rejected or timed out and the state and counts stay as they are.
+    pub fn measure<F, R>(&mut self, func: F) -> Duration
+    where
+        F: FnOnce() -> R,
+    {
+        let elapsed = time(func);
+        self.record_latency(elapsed);
+        elapsed
+    }
+
+    /// Calls `primary` through the breaker, and `fallback` instead whenever
+    /// `primary` is rejected, fails or times out.
+    pub fn call_with_fallback<F, G, R, E>(&mut self, primary: F, fallback: G) -> R
+    where
+        F: RunsOnWorkerThread<R, E>,
+        G: FnOnce() -> R,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        match self.call(primary) {
+            Ok(Some(value)) => value,
+            Ok(None) | Err(_) => fallback(),
+        }
+    }
+
+    /// Calls `fallback` right away if the breaker will not run `primary`,
+    /// and `primary` otherwise.
+    ///
+    /// Unlike [`CircuitBreaker::call_with_fallback`], `fallback` is only used
+    /// when `primary` is not admitted; once `primary` runs its error or
+    /// timeout is returned as usual. The rejected path never waits on a
+    /// worker, which suits latency-critical callers.
+    pub fn call_fast_fallback<F, G, R, E>(
+        &mut self,
+        primary: F,
+        fallback: G,
+    ) -> Result<R, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        G: FnOnce() -> R,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        match self.admit::<R, E>() {
+            Ok(Admission::Run {
+                inline,
+                slots: _slots,
+                admitted_in,
+            }) => {
+                let (outcome, elapsed) = self.runner().run(inline, primary);
+                self.complete(outcome, elapsed, admitted_in, None)
+            }
+            Ok(Admission::Skip(_)) | Err(_) => Ok(fallback()),
+        }
+    }
+
+    /// Like [`CircuitBreaker::call`], but runs `func` on the calling thread
+    /// and hands it a [`DeadlineGuard`] to poll instead of abandoning it on
+    /// another thread when the timeout passes.
+    ///
+    /// Nothing is spawned and `func` need not be `Send`, but the timeout is
+    /// cooperative: `func` must check [`DeadlineGuard::expired`] and return
+    /// early. Whatever it returns, a call that overran counts as timed out.
+    pub fn call_with_deadline<F, R, E>(
+        &mut self,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: FnOnce(&DeadlineGuard) -> Result<R, E>,
+        R: 'static,
+        E: 'static,
+    {
+        match self.admit()? {
+            Admission::Skip(value) => Ok(value),
+            Admission::Run {
+                slots: _slots,
+                admitted_in,
+                ..
+            } => {
+                let start = Instant::now();
+                let timeout = self.call_timeout();
+                let deadline = DeadlineGuard::new(timeout);
+                let guard = InFlightGuard::new(&self.in_flight);
+                let res = catch(|| func(&deadline), self.count_panics);
+                drop(guard);
+                let elapsed = start.elapsed();
+                let outcome = match res {
+                    _ if deadline.expired() || elapsed > timeout => Outcome::Timeout,
+                    res => Outcome::from_finished(res),
+                };
+                self.complete(outcome, elapsed, admitted_in, None).map(Some)
+            }
+        }
+    }
+
+    /// Like [`CircuitBreaker::call`], but keeps a copy of the last call that
+    /// failed or timed out and runs it again as the probe once the breaker
+    /// half-opens.
+    ///
+    /// Without this, the call that finds the recovery time elapsed only
+    /// moves the breaker to half-open and returns `Ok(None)`. Here the kept
+    /// call is replayed first, and `func` then runs or is rejected depending
+    /// on how the probe went, so a recovered dependency is confirmed without
+    /// waiting for more traffic. A probe that fails again is kept for the
+    /// next attempt; the copy is dropped once a call succeeds.
+    ///
+    /// Only use this for idempotent operations: the replayed call has
+    /// already run at least once, and may have had an effect even though it
+    /// failed or timed out.
+    pub fn call_replayable<F, R, E>(&mut self, func: F) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E> + Clone + Sync,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        if self.state == State::Open && self.replay.is_some() && self.recovery_elapsed() {
+            if let Err(reason) = self.handle_open_state() {
+                return self.not_run(Some(reason));
+            }
+            if let Some(probe) = self.replay.take() {
+                probe(self);
+            }
+        }
+
+        let failed_before = self.failures + self.timeouts;
+        let kept = func.clone();
+        let res = self.call(func);
+        if self.failures + self.timeouts > failed_before {
+            self.replay = Some(replay_of(kept));
+        } else if matches!(res, Ok(Some(_))) {
+            self.replay = None;
+        }
+        res
+    }
+
+    /// Like [`CircuitBreaker::call`], but passes a successful result
+    /// through `check` before the breaker records it, e.g. to reject a
+    /// response that arrived fine but is empty when data was expected. An
+    /// error from `check` counts like one from `func` and is returned as a
+    /// [`CircuitBreakerError::FunctionError`]; otherwise its value is what
+    /// the call returns. Unlike
+    /// [`evaluate`](CircuitBreakerBuilder::evaluate), it can change the
+    /// value as well as judge it. `check` runs on the calling thread, and
+    /// only for calls that finished in time.
+    pub fn call_and_then<F, G, R, T, E>(
+        &mut self,
+        func: F,
+        check: G,
+    ) -> Result<Option<T>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        G: FnOnce(R) -> Result<T, E>,
+        R: Send + 'static,
+        T: 'static,
+        E: Send + 'static,
+    {
+        match self.admit::<T, E>()? {
+            Admission::Skip(value) => Ok(value),
+            Admission::Run {
+                inline,
+                slots: _slots,
+                admitted_in,
+            } => {
+                let (outcome, elapsed) = self.runner().run(inline, func);
+                self.complete(outcome.and_then(check), elapsed, admitted_in, None)
+                    .map(Some)
+            }
+        }
+    }
+
+    fn call_with_label<F, R, E>(
+        &mut self,
+        label: Option<&str>,
+        priority: Priority,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        match self.admit_with_priority(priority)? {
+            Admission::Skip(value) => Ok(value),
+            Admission::Run {
+                inline,
+                slots: _slots,
+                admitted_in,
+            } => {
+                let (outcome, elapsed) = self.runner().run(inline, func);
+                self.complete(outcome, elapsed, admitted_in, label)
+                    .map(Some)
+            }
+        }
+    }
+
+    /// Decides whether a call may run, moving an open breaker to half-open
+    /// once the recovery time has elapsed.
+    pub(crate) fn admit<R: 'static, E>(&mut self) -> Result<Admission<R>, CircuitBreakerError<E>> {
+        self.admit_with_priority(Priority::Normal)
+    }
+
+    pub(crate) fn admit_with_priority<R: 'static, E>(
+        &mut self,
+        priority: Priority,
+    ) -> Result<Admission<R>, CircuitBreakerError<E>> {
+        let admission = self.decide_admission(priority);
+        self.check_invariants();
+        self.publish_metrics();
+        admission
+    }
+
+    fn decide_admission<R: 'static, E>(
+        &mut self,
+        priority: Priority,
+    ) -> Result<Admission<R>, CircuitBreakerError<E>> {
+        let forced = self.read_override();
+        self.forced_closed = forced == Override::ForceClosed;
+        if forced == Override::ForceOpen {
+            return self.reject(RejectionReason::ForcedOpen);
+        }
+        if !self.bypassed() {
+            self.settle_idle_half_open();
+        }
+        if let Some(reason) = self.turned_away(forced, priority) {
+            if let (RejectionReason::Throttled, Some(percent)) = (reason, self.throttle_percent()) {
+                *self.throttle_credit() += percent;
+            }
+            return self.reject(reason);
+        }
+        let inline = self.admits_inline(self.bypassed());
+        if self.bypassed() {
+            self.admitted += 1;
+            return Ok(Admission::Run {
+                inline,
+                slots: Vec::new(),
+                admitted_in: None,
+            });
+        }
+        if self.state == State::Open {
+            let reason = self.handle_open_state().err();
+            return self.not_run(reason).map(Admission::Skip);
+        }
+        let mut slots = match self.take_slots() {
+            Ok(slots) => slots,
+            Err(reason) => return self.reject(reason),
+        };
+        if self.state == State::HalfOpen {
+            slots.push(InFlightGuard::new(&self.probes_in_flight));
+            self.half_open_probes += 1;
+        } else if let Some(percent) = self.throttle_percent() {
+            let credit = self.throttle_credit();
+            *credit = (*credit + percent).saturating_sub(100);
+        }
+        self.admitted += 1;
+        Ok(Admission::Run {
+            inline,
+            slots,
+            admitted_in: self.admitted_in(),
+        })
+    }
+
+    /// Counts a call turned away before running and returns what `call`
+    /// gives back for it.
+    fn reject<R: 'static, E>(
+        &mut self,
+        reason: RejectionReason,
+    ) -> Result<Admission<R>, CircuitBreakerError<E>> {
+        self.rejected += 1;
+        self.count_call("rejected");
+        self.not_run(Some(reason)).map(Admission::Skip)
+    }
+
+    /// Places for a call under the current state's
+    /// [`max_concurrent`](crate::CircuitBreakerBuilder::max_concurrent)
+    /// limit and the shared [`Semaphore`], where configured, or why the
+    /// call cannot have them.
+    fn take_slots(&self) -> Result<Vec<InFlightGuard>, RejectionReason> {
+        if let Some(reason) = self.slots_full() {
+            return Err(reason);
+        }
+        let mut slots = Vec::new();
+        if let Some((.., running)) = self.concurrency_limit() {
+            slots.push(InFlightGuard::new(running));
+        }
+        if let Some(semaphore) = &self.semaphore {
+            let permit = semaphore.try_acquire();
+            slots.push(permit.ok_or(RejectionReason::SemaphoreExhausted)?);
+        }
+        Ok(slots)
+    }
+
+    /// Why [`take_slots`](Self::take_slots) would fail now, without taking
+    /// anything.
+    fn slots_full(&self) -> Option<RejectionReason> {
+        if self
+            .concurrency_limit()
+            .is_some_and(|(_, max, running)| running.load(Ordering::SeqCst) >= *max as usize)
+        {
+            Some(RejectionReason::ConcurrencyLimit)
+        } else if self
+            .semaphore
+            .as_ref()
+            .is_some_and(|semaphore| semaphore.available() == 0)
+        {
+            Some(RejectionReason::SemaphoreExhausted)
+        } else {
+            None
+        }
+    }
+
+    /// The `max_concurrent` limit for the current state, if there is one.
+    fn concurrency_limit(&self) -> Option<&(State, u32, Arc<AtomicUsize>)> {
+        self.concurrency_limits
+            .iter()
+            .find(|(state, ..)| *state == self.state)
+    }
+
+    /// The controller's override from the
+    /// [`state_store`](crate::CircuitBreakerBuilder::state_store), if any.
+    fn read_override(&self) -> Override {
+        self.state_store
+            .as_ref()
+            .map_or(Override::Auto, |store| store.0.read_override(self.name()))
+    }
+
+    /// Whether calls run without moving the breaker, because it is disabled
+    /// or was forced closed when the call was admitted.
+    fn bypassed(&self) -> bool {
+        !self.enabled || self.forced_closed
+    }
+
+    /// Whether a call of `priority` may be a half-open probe.
+    pub(crate) fn may_probe(&self, priority: Priority) -> bool {
+        priority >= self.half_open_min_priority
+    }
+
+    /// Whether the breaker is half-open with every probe slot taken.
+    pub(crate) fn probes_busy(&self) -> bool {
+        self.enabled
+            && self.state == State::HalfOpen
+            && self
+                .half_open_max_concurrent
+                .is_some_and(|max| self.probes_in_flight.load(Ordering::SeqCst) >= max as usize)
+    }
+
+    /// Undoes [`CircuitBreaker::admit`] for a call that was reserved but
+    /// never ran, see [`Permit`](crate::Permit). `admitted_in` is the state
+    /// the call was admitted in, or `None` if the breaker was disabled; the
+    /// probe or soft-open slot is only handed back if that has not changed.
+    pub(crate) fn release_admission(&mut self, admitted_in: Option<State>) {
+        self.admitted = self.admitted.saturating_sub(1);
+        self.publish_metrics();
+        if admitted_in != Some(self.state) || self.bypassed() {
+            return;
+        }
+        match self.state {
+            State::HalfOpen => self.half_open_probes = self.half_open_probes.saturating_sub(1),
+            State::SoftOpen => self.soft_open_credit += 100,
+            State::Closed | State::Degraded | State::Open => {}
+        }
+    }
+
+    /// The state a call admitted now is accounted in, or `None` while the
+    /// breaker is disabled.
+    pub(crate) fn admitted_in(&self) -> Option<State> {
+        (!self.bypassed()).then_some(self.state)
+    }
+
+    /// Counts a call in telemetry, or holds it back for the next batch.
+    fn count_call(&mut self, outcome: &'static str) {
+        self.publish_metrics();
+        let Some(batch) = &mut self.call_batch else {
+            telemetry::call(self.label.as_deref(), outcome);
+            return;
+        };
+        if batch.add(outcome, self.clock.now()) {
+            batch.flush(self.label.as_deref());
+        }
+    }
+
+    /// Reports any call counts held back by
+    /// [`batch_telemetry`](CircuitBreakerBuilder::batch_telemetry) now,
+    /// e.g. before a scrape that must be exact. Dropping the breaker does
+    /// this too.
+    pub fn flush_telemetry(&mut self) {
+        if let Some(batch) = &mut self.call_batch {
+            batch.flush(self.label.as_deref());
+        }
+    }
+
+    /// Flushes the call counts held back if the batch interval has passed
+    /// since the first of them, for a caller that checks periodically.
+    pub(crate) fn flush_due_telemetry(&mut self) {
+        if self.telemetry_due_in().is_some_and(|due| due.is_zero()) {
+            self.flush_telemetry();
+        }
+    }
+
+    /// How long until the call counts held back are due to be flushed, or
+    /// `None` if none are.
+    pub(crate) fn telemetry_due_in(&self) -> Option<Duration> {
+        self.call_batch.as_ref()?.due_in(self.clock.now())
+    }
+
+    /// Whether a call run now would need a new thread that the
+    /// [`ThreadLimit`] has no room for.
+    fn threads_exhausted(&self, inline: bool) -> bool {
+        !inline
+            && self.worker_pool.is_none()
+            && self.timeout_strategy.is_none()
+            && self
+                .thread_limit
+                .as_ref()
+                .is_some_and(ThreadLimit::is_reached)
+    }
+
+    /// Whether a call admitted now runs on the calling thread, for
+    /// [`runs_inline`](Self::runs_inline). `bypassed` is whether the
+    /// breaker is disabled or forced closed.
+    fn admits_inline(&self, bypassed: bool) -> bool {
+        self.runs_inline(match self.state {
+            _ if bypassed => false,
+            State::HalfOpen => self.inline_probes,
+            State::Closed | State::Degraded => self.inline_when_closed,
+            State::Open | State::SoftOpen => false,
+        })
+    }
+
+    /// Whether an admitted call runs on the calling thread: when asked to,
+    /// or always once the timeout is disabled.
+    fn runs_inline(&self, requested: bool) -> bool {
+        requested || self.timeout == Duration::MAX
+    }
+
+    /// Applies the [`HalfOpenIdle`] policy if the breaker has been half-open
+    /// long enough.
+    fn settle_idle_half_open(&mut self) {
+        let Some(since) = self.half_open_since else {
+            return;
+        };
+        if self.state != State::HalfOpen {
+            return;
+        }
+        let idle = self.since(since);
+        match self.half_open_idle {
+            HalfOpenIdle::Stay => {}
+            HalfOpenIdle::Close { after } => {
+                if idle >= after && self.open_success_count > 0 {
+                    self.transition_to(State::Closed);
+                    self.open_success_count = 0;
+                    self.failure_count = 0;
+                }
+            }
+            HalfOpenIdle::Reopen { after } => {
+                if idle >= after && self.half_open_probes == 0 {
+                    self.trip_to(State::Open, TripReason::HalfOpenIdle);
+                    self.last_failure_time = Some(self.clock.now());
+                }
+            }
+        }
+    }
+
+    /// What `call` returns for a call that did not run, per the
+    /// [`RejectionPolicy`]: one rejected for `reason`, or the call that only
+    /// moved the breaker to half-open if there is none.
+    fn not_run<R: 'static, E>(
+        &self,
+        reason: Option<RejectionReason>,
+    ) -> Result<Option<R>, CircuitBreakerError<E>> {
+        self.report(
+            reason.map_or(CallOutcome::Skipped, CallOutcome::Rejected),
+            None,
+        );
+        if let Some(value) = self.cached_success() {
+            return Ok(Some(value));
+        }
+        if self.rejection_policy == RejectionPolicy::ReturnDefault {
+            if let Some(value) = self.default_when_open() {
+                return Ok(Some(value));
+            }
+        }
+        match reason {
+            Some(reason) if self.rejection_policy != RejectionPolicy::ReturnNone => {
+                Err(CircuitBreakerError::Rejected(reason))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Moves an open breaker to half-open if the recovery time has elapsed,
+    /// or counts the call as rejected and says why.
+    pub(crate) fn handle_open_state(&mut self) -> Result<(), RejectionReason> {
+        let reason = self.open_rejection().unwrap_or_else(|| {
+            self.transition_to(State::HalfOpen);
+            RejectionReason::Vetoed
+        });
+        if self.state == State::HalfOpen {
+            self.begin_half_open();
+            Ok(())
+        } else {
+            self.rejected += 1;
+            self.count_call("rejected");
+            Err(reason)
+        }
+    }
+
+    /// Resets the counts a half-open stretch starts from.
+    fn begin_half_open(&mut self) {
+        self.open_success_count = 0;
+        if self.reset_on_half_open {
+            self.failure_count = 0;
+        }
+    }
+
+    /// A copy of the cached success while open, if there is a fresh one of
+    /// type `R`.
+    fn cached_success<R: 'static>(&self) -> Option<R> {
+        let (max_age, clone) = self.cache_last_success.as_ref()?;
+        let (value, at) = self.cached_success.as_ref()?;
+        if self.state != State::Open || self.since(*at) > *max_age {
+            return None;
+        }
+        (clone.0)(value.as_ref())?
+            .downcast::<R>()
+            .ok()
+            .map(|value| *value)
+    }
+
+    /// The configured open-state value, if there is one of type `R`.
+    pub(crate) fn default_when_open<R: 'static>(&self) -> Option<R> {
+        let hook = self.default_when_open.as_ref()?;
+        (hook.0)().downcast::<R>().ok().map(|value| *value)
+    }
+
+    /// The lenient probe settings, if the call admitted last was one of the
+    /// first probes of this half-open stretch.
+    fn lenient_probe(&self) -> Option<&LenientProbes> {
+        self.lenient_probes.as_ref().filter(|lenient| {
+            self.state == State::HalfOpen && self.half_open_probes <= lenient.probes
+        })
+    }
+
+    /// The timeout for the call just admitted.
+    pub(crate) fn call_timeout(&self) -> Duration {
+        self.lenient_probe()
+            .map_or(self.timeout, |lenient| lenient.timeout)
+    }
+
+    /// What is needed to run a call without holding on to the breaker.
+    pub(crate) fn runner(&self) -> Runner {
+        Runner {
+            timeout: self.call_timeout(),
+            worker_pool: self.worker_pool.clone(),
+            thread_limit: self.thread_limit.clone(),
+            timeout_strategy: self.timeout_strategy.clone(),
+            worker_name: match &self.name {
+                Some(name) => format!("cb-{name}-worker"),
+                None => "cb-worker".to_string(),
+            },
+            in_flight: Arc::clone(&self.in_flight),
+            orphans: Arc::clone(&self.orphans),
+            late_results: self.late_results.clone(),
+            count_panics: self.count_panics,
+        }
+    }
+
+    /// Records how an admitted call finished and turns it into `call`'s
+    /// result. `admitted_in` is the state the call was admitted in, as
+    /// [`Admission::Run`] has it.
+    pub(crate) fn complete<R: 'static, E: 'static>(
+        &mut self,
+        outcome: Outcome<R, E>,
+        elapsed: Duration,
+        admitted_in: Option<State>,
+        label: Option<&str>,
+    ) -> Result<R, CircuitBreakerError<E>> {
+        if self.ignores_in_flight() {
+            return self.within_max_duration(outcome.into_result(), elapsed);
+        }
+        let mut reported = match outcome {
+            Outcome::Ok(_) => CallOutcome::Success,
+            Outcome::Timeout => CallOutcome::Timeout,
+            Outcome::Err(_) | Outcome::Panic(_) => CallOutcome::Failure,
+            Outcome::Disconnected => CallOutcome::Internal,
+        };
+        let res = if self.bypassed() {
+            self.complete_bypassed(outcome, elapsed)
+        } else if self.is_stale(admitted_in) {
+            self.complete_stale(outcome, elapsed, label)
+        } else {
+            match outcome {
+                Outcome::Ok(data) => {
+                    if !self.on_ok(&data, elapsed, label) {
+                        reported = CallOutcome::Failure;
+                    }
+                    Ok(data)
+                }
+                Outcome::Err(e) => {
+                    self.on_error(&e, label);
+                    Err(CircuitBreakerError::FunctionError(e))
+                }
+                Outcome::Timeout => {
+                    self.on_timeout(label);
+                    Err(CircuitBreakerError::TimeoutError)
+                }
+                Outcome::Panic(message) => {
+                    let error = panicked(message.as_deref());
+                    self.push_recent_failure(FailureKind::Panic, label, message);
+                    self.on_failure();
+                    Err(error)
+                }
+                Outcome::Disconnected => Err(CircuitBreakerError::Internal(
+                    "worker exited without returning a result".to_string(),
+                )),
+            }
+        };
+        self.record_outcome_latency(reported, elapsed);
+        self.report(reported, Some(elapsed));
+        self.check_invariants();
+        self.within_max_duration(res, elapsed)
+    }
+
+    /// Whether a call admitted in `admitted_in` and finishing now counts
+    /// against the breaker's state, as [`complete`](Self::complete) decides:
+    /// not while it is bypassed, for an ignored in-flight call, or for a
+    /// [stale](Self::is_stale) call.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn moves_state(&self, admitted_in: Option<State>) -> bool {
+        !self.bypassed() && !self.ignores_in_flight() && !self.is_stale(admitted_in)
+    }
+
+    /// Turns a success that took longer than the `max_call_duration` into
+    /// [`CircuitBreakerError::DeadlineExceeded`].
+    pub(crate) fn within_max_duration<R, E>(
+        &self,
+        res: Result<R, CircuitBreakerError<E>>,
+        elapsed: Duration,
+    ) -> Result<R, CircuitBreakerError<E>> {
+        match res {
+            Ok(_) if self.max_call_duration.is_some_and(|max| elapsed > max) => {
+                Err(CircuitBreakerError::DeadlineExceeded)
+            }
+            res => res,
+        }
+    }
+
+    /// Whether a call finishing now was running when the breaker was tripped
+    /// by hand, and should not count per [`InFlightAfterTrip::Ignore`].
+    /// Nothing is admitted while open, so every such call was.
+    fn ignores_in_flight(&self) -> bool {
+        self.in_flight_after_trip == InFlightAfterTrip::Ignore
+            && self.state == State::Open
+            && self.last_trip_reason == Some(TripReason::Manual)
+            && !self.bypassed()
+    }
+
+    /// Checks, in debug builds only, what must hold between operations, so
+    /// a logic error fails where it happens instead of surfacing later as
+    /// a breaker that never recovers or never trips.
+    pub(crate) fn check_invariants(&self) {
+        debug_assert!(
+            self.state != State::Open || self.last_failure_time.is_some(),
+            "breaker is open without a last failure time to recover from"
+        );
+        // A transition guard may veto the trip and keep the breaker closed.
+        debug_assert!(
+            !matches!(self.state, State::Closed | State::Degraded)
+                || self.failure_count <= self.failure_threshold
+                || self.transition_guard.is_some(),
+            "breaker is {:?} with {} failures, over its threshold of {}",
+            self.state,
+            self.failure_count,
+            self.failure_threshold
+        );
+        debug_assert!(
+            !matches!(self.state, State::HalfOpen | State::SoftOpen)
+                || self.open_success_count < self.open_threshold_count
+                || self.transition_guard.is_some(),
+            "breaker is {:?} with {} successful probes, enough to have closed at {}",
+            self.state,
+            self.open_success_count,
+            self.open_threshold_count
+        );
+        debug_assert!(
+            self.state != State::Degraded || self.warning_threshold.is_some(),
+            "breaker is degraded without a warning threshold"
+        );
+    }
+
+    /// Passes a finished or turned-away call to the `on_result` hook.
+    pub(crate) fn report(&self, outcome: CallOutcome, latency: Option<Duration>) {
+        telemetry::call_span(self.label.as_deref(), outcome, latency);
+        if let Some(hook) = &self.on_result {
+            (hook.0)(&CallResult {
+                outcome,
+                latency,
+                state: self.state,
+            });
+        }
+    }
+
+    /// Like [`CircuitBreaker::complete`] while the breaker is disabled: the
+    /// outcome is counted in the metrics, but the state and failure count
+    /// are left alone.
+    fn complete_bypassed<R, E>(
+        &mut self,
+        outcome: Outcome<R, E>,
+        elapsed: Duration,
+    ) -> Result<R, CircuitBreakerError<E>> {
+        match outcome {
+            Outcome::Ok(_) => {
+                self.record_latency(elapsed);
+                self.successes += 1;
+                self.count_call("success");
+            }
+            Outcome::Err(_) | Outcome::Panic(_) => {
+                self.failures += 1;
+                self.count_call("failure");
+            }
+            Outcome::Timeout => {
+                self.timeouts += 1;
+                self.count_call("timeout");
+            }
+            Outcome::Disconnected => {}
+        }
+        outcome.into_result()
+    }
+
+    /// Whether a call admitted in `admitted_in` finishes after the breaker
+    /// left that state, e.g. one still running on another thread of a
+    /// shared breaker when a failure tripped it. Closed and degraded count
+    /// as the same state.
+    fn is_stale(&self, admitted_in: Option<State>) -> bool {
+        let closed = |state| matches!(state, State::Closed | State::Degraded);
+        admitted_in
+            .is_some_and(|state| state != self.state && !(closed(state) && closed(self.state)))
+    }
+
+    /// Like [`CircuitBreaker::complete`] for a call that is
+    /// [stale](Self::is_stale): it says nothing about the state the breaker
+    /// is in now, so it is counted in the metrics and recent failures, but
+    /// neither moves the breaker nor restarts its recovery time. A late
+    /// failure landing while half-open is not taken for a failed probe.
+    fn complete_stale<R, E: 'static>(
+        &mut self,
+        outcome: Outcome<R, E>,
+        elapsed: Duration,
+        label: Option<&str>,
+    ) -> Result<R, CircuitBreakerError<E>> {
+        let (kind, message) = match &outcome {
+            Outcome::Err(e) if !self.counts_as_failure(e) => return outcome.into_result(),
+            Outcome::Err(_) => (FailureKind::Error, None),
+            Outcome::Timeout => (FailureKind::Timeout, None),
+            Outcome::Panic(message) => (FailureKind::Panic, message.clone()),
+            Outcome::Ok(_) | Outcome::Disconnected => {
+                return self.complete_bypassed(outcome, elapsed);
+            }
+        };
+        self.push_recent_failure(kind, label, message);
+        self.complete_bypassed(outcome, elapsed)
+    }
+
+    /// Records a failure as if a protected call had returned an error,
+    /// without running anything, so tests can put the breaker in a known
+    /// state.
+    #[cfg(feature = "test-util")]
+    pub fn record_failure(&mut self) {
+        self.push_recent_failure(FailureKind::Error, None, None);
+        self.on_failure();
+    }
+
+    /// Records a success as if a protected call had returned `Ok`, without
+    /// running anything.
+    #[cfg(feature = "test-util")]
+    pub fn record_success(&mut self) {
+        self.count_success();
+    }
+
+    /// Moves to `to`, running the hooks for the state being entered.
+    ///
+    /// Does nothing if the transition guard vetoes it.
+    fn transition_to(&mut self, to: State) {
+        if self.state == to {
+            return;
+        }
+        if let Some(guard) = &self.transition_guard {
+            if !(guard.0)(self.state, to) {
+                return;
+            }
+        }
+        self.enter(to);
+    }
+
+    /// Moves to `to`, a tripped state, and records `reason` for it unless
+    /// the breaker was already there or the guard vetoed the move.
+    fn trip_to(&mut self, to: State, reason: TripReason) {
+        let (from, previous) = (self.state, self.last_trip_reason);
+        // Set first, so hooks and the transition log see it.
+        self.last_trip_reason = Some(reason);
+        self.transition_to(to);
+        if from == to || self.state != to {
+            self.last_trip_reason = previous;
+        }
+    }
+
+    /// Moves to `to` like [`CircuitBreaker::transition_to`], bypassing the
+    /// transition guard.
+    fn enter(&mut self, to: State) {
+        if self.state == to {
+            return;
+        }
+        let transition = StateTransition {
+            from: self.state,
+            to,
+            at: self.clock.now(),
+        };
+        if to == State::Open {
+            self.timeout_count = 0;
+            self.threshold_warned = false;
+        }
+        if self.state == State::HalfOpen && to == State::Open {
+            self.failed_probe_cycles = self.failed_probe_cycles.saturating_add(1);
+        } else if to == State::Closed {
+            self.failed_probe_cycles = 0;
+        }
+        self.time_in_state
+            .add(self.state, self.between(self.state_since, transition.at));
+        self.state_since = transition.at;
+        self.state = to;
+        self.published.set_state(to);
+        self.retry_after = None;
+        self.half_open_timeouts = 0;
+        self.half_open_probes = 0;
+        self.half_open_since = (to == State::HalfOpen).then_some(transition.at);
+        self.success_window.clear();
+        if to == State::SoftOpen {
+            self.soft_open_credit = 0;
+            self.open_success_count = 0;
+        }
+        match to {
+            State::Closed if transition.from == State::HalfOpen && self.close_ramp.is_some() => {
+                self.ramp_since = Some(transition.at);
+                self.ramp_credit = 0;
+            }
+            State::Closed | State::Degraded => {}
+            State::HalfOpen | State::Open | State::SoftOpen => self.ramp_since = None,
+        }
+        if to == State::Open {
+            self.failure_window.clear();
+            self.failure_score = None;
+        }
+        if self.transitions_capacity > 0 {
+            if self.transitions.len() == self.transitions_capacity {
+                self.transitions.pop_front();
+            }
+            self.transitions.push_back(transition);
+        }
+        // Sending only fails when nobody is subscribed.
+        #[cfg(feature = "tokio")]
+        let _ = self.transition_tx.send(transition);
+        telemetry::transition(self.label.as_deref(), transition);
+        self.notify_state_change(transition);
+        self.detect_flapping(transition.at);
+        let on_enter = match to {
+            State::Open => &self.on_open,
+            State::Closed => &self.on_closed,
+            State::HalfOpen => &self.on_half_open,
+            State::Degraded | State::SoftOpen => &None,
+        };
+        if let Some(hook) = on_enter {
+            (hook.0)();
+        }
+        if transition.from == State::HalfOpen && to == State::Open {
+            if let Some((after, hook)) = &self.recovery_failure {
+                if self.failed_probe_cycles >= *after {
+                    (hook.0)(self.failed_probe_cycles);
+                }
+            }
+        }
+    }
+
+    fn detect_flapping(&mut self, now: Instant) {
+        let Some((max, window)) = self.flap_detection else {
+            return;
+        };
+        self.flap_times.push_back(now);
+        while self
+            .flap_times
+            .front()
+            .is_some_and(|&t| self.between(t, now) > window)
+        {
+            self.flap_times.pop_front();
+        }
+        let recent = self.flap_times.len();
+        if recent <= max {
+            self.flapping = false;
+        } else if !self.flapping {
+            self.flapping = true;
+            if let Some(hook) = &self.on_flap {
+                (hook.0)(recent);
+            }
+        }
+    }
+
+    /// Whether the breaker made more transitions within the
+    /// [`flap_detection`](CircuitBreakerBuilder::flap_detection) window, up
+    /// to now, than it allows; a sign of a misconfigured threshold or an
+    /// unstable dependency. Always `false` without flap detection.
+    pub fn is_flapping(&self) -> bool {
+        let Some((max, window)) = self.flap_detection else {
+            return false;
+        };
+        let recent = self
+            .flap_times
+            .iter()
+            .filter(|&&t| self.since(t) <= window)
+            .count();
+        recent > max
+    }
+
+    /// How many times in a row a probe failed and reopened the breaker
+    /// since it last closed.
+    pub fn failed_recovery_cycles(&self) -> u32 {
+        self.failed_probe_cycles
+    }
+
+    fn notify_state_change(&mut self, transition: StateTransition) {
+        if let Some(hook) = &self.on_state_change {
+            (hook.0)(transition);
+        }
+        if let Some(hook) = &self.on_state_change_with_data {
+            (hook.0)(&mut self.data, transition);
+        }
+        if let Some(log) = &self.transition_log {
+            let record = TransitionRecord {
+                breaker: self.name.clone(),
+                from: transition.from,
+                to: transition.to,
+                at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64),
+                reason: self.last_trip_reason.filter(|_| {
+                    transition.from != transition.to
+                        && matches!(transition.to, State::Open | State::SoftOpen)
+                }),
+            };
+            let mut writer = log.0.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = writeln!(writer, "{}", record.to_json()).and_then(|()| writer.flush());
+        }
+    }
+
+    /// Records a call that returned `Ok`, unless `evaluate` says the value
+    /// should count as a failure. Returns whether it counted as a success.
+    pub(crate) fn on_ok(
+        &mut self,
+        value: &dyn Any,
+        elapsed: Duration,
+        label: Option<&str>,
+    ) -> bool {
+        if self.evaluate.as_ref().is_some_and(|hook| (hook.0)(value)) {
+            self.push_recent_failure(FailureKind::Value, label, None);
+            self.on_failure();
+            false
+        } else {
+            self.on_success(elapsed);
+            if let Some((_, clone)) = &self.cache_last_success {
+                if let Some(value) = (clone.0)(value) {
+                    self.cached_success = Some((value, self.clock.now()));
+                }
+            }
+            true
+        }
+    }
+
+    pub(crate) fn on_success(&mut self, elapsed: Duration) {
+        self.record_latency(elapsed);
+        self.last_error = None;
+        let slow_call_threshold = match self.lenient_probe() {
+            Some(lenient) => Some(lenient.slow_call_threshold),
+            None => self.slow_call_threshold,
+        };
+        if slow_call_threshold.is_some_and(|slow| elapsed > slow) {
+            self.slow_calls += 1;
+            if let Some(hook) = &self.on_slow_call {
+                (hook.0)(elapsed);
+            }
+        }
+        self.count_success();
+    }
+
+    fn count_success(&mut self) {
+        self.successes += 1;
+        self.count_call("success");
+        match self.state {
+            State::HalfOpen | State::SoftOpen => {
+                self.half_open_timeouts = 0;
+                self.open_success_count = match self.half_open_success_window {
+                    Some(window) => self.count_success_in_window(window),
+                    None => self.open_success_count + 1,
+                };
+                if self.open_success_count >= self.open_threshold_count {
+                    self.transition_to(State::Closed);
+                    self.open_success_count = 0;
+                    self.failure_count = 0;
+                }
+            }
+            State::Closed | State::Degraded | State::Open => {
+                if !matches!(
+                    self.trip_policy,
+                    TripPolicy::Windowed { .. } | TripPolicy::Decaying { .. }
+                ) {
+                    self.failure_count = 0;
+                }
+                self.timeout_count = 0;
+                self.update_degraded();
+            }
+        }
+    }
+
+    /// Classifies an error from the protected function and records it.
+    pub(crate) fn on_error(&mut self, e: &dyn Any, label: Option<&str>) {
+        self.count_error(e, label);
+        if self.state == State::Open {
+            if let Some(hint) = &self.retry_after_from_error {
+                if let Some(retry_after) = (hint.0)(e) {
+                    self.retry_after = Some(retry_after);
+                }
+            }
+        }
+    }
+
+    /// Whether `e` counts against the breaker per `is_fatal` and
+    /// `is_failure`.
+    fn counts_as_failure(&self, e: &dyn Any) -> bool {
+        self.is_fatal.as_ref().is_some_and(|hook| (hook.0)(e))
+            || self.is_failure.as_ref().is_none_or(|hook| (hook.0)(e))
+    }
+
+    fn count_error(&mut self, e: &dyn Any, label: Option<&str>) {
+        if self.is_fatal.as_ref().is_some_and(|hook| (hook.0)(e)) {
+            self.push_recent_failure(FailureKind::Error, label, None);
+            self.on_failure();
+            if self.state != State::Open {
+                self.trip_to(State::Open, TripReason::FatalError);
+            }
+        } else if self.is_failure.as_ref().is_none_or(|hook| (hook.0)(e)) && !self.is_repeat(e) {
+            self.push_recent_failure(FailureKind::Error, label, None);
+            self.on_failure();
+        }
+    }
+
+    /// Whether `e` repeats the error counted last closely enough to be left
+    /// out per `dedupe_errors`. Remembers it as the one counted otherwise.
+    fn is_repeat(&mut self, e: &dyn Any) -> bool {
+        let Some((window, key, eq)) = &self.dedupe_errors else {
+            return false;
+        };
+        let (window, eq) = (*window, *eq);
+        let Some(key) = (key.0)(e) else {
+            return false;
+        };
+        if !matches!(self.state, State::Closed | State::Degraded) {
+            return false;
+        }
+        let now = self.clock.now();
+        if self
+            .last_error
+            .as_ref()
+            .is_some_and(|(last, at)| eq(&**last, &*key) && self.between(*at, now) <= window)
+        {
+            return true;
+        }
+        self.last_error = Some((key, now));
+        false
+    }
+
+    fn on_failure(&mut self) {
+        self.failures += 1;
+        self.count_call("failure");
+        self.last_failure_time = Some(self.clock.now());
+        match self.state {
+            State::HalfOpen => {
+                self.failure_count = self.failed_probe_count();
+                self.trip_to(State::Open, TripReason::ProbeFailed);
+            }
+            State::SoftOpen => {
+                self.failure_count = self.failure_count.saturating_add(1);
+                self.trip_to(State::Open, TripReason::ProbeFailed);
+            }
+            State::Closed | State::Degraded | State::Open => {
+                let trips = match self.trip_policy {
+                    TripPolicy::Consecutive => {
+                        self.failure_count += 1;
+                        self.failure_count > self.failure_threshold
+                    }
+                    TripPolicy::Windowed { window } => {
+                        self.failure_count = self.count_in_window(window);
+                        self.failure_count > self.failure_threshold
+                    }
+                    TripPolicy::ConsecutiveOrWindowed { window, total } => {
+                        self.failure_count += 1;
+                        self.failure_count > self.failure_threshold
+                            || self.count_in_window(window) > total
+                    }
+                    TripPolicy::Decaying { .. } => {
+                        let score = self.failure_score() + 1.0;
+                        self.failure_score = Some((score, self.clock.now()));
+                        self.failure_count = score as u32;
+                        score > f64::from(self.failure_threshold)
+                    }
+                };
+                if trips {
+                    let tripped = match self.soft_open {
+                        Some(_) if self.state != State::Open => State::SoftOpen,
+                        _ => State::Open,
+                    };
+                    self.trip_to(tripped, TripReason::FailureThreshold);
+                } else {
+                    self.update_degraded();
+                }
+            }
+        }
+    }
+
+    /// Adds a failure now to the trailing window and returns how many
+    /// failures it holds.
+    fn count_in_window(&mut self, window: Duration) -> u32 {
+        let now = self.clock.now();
+        self.failure_window.push_back(now);
+        while self
+            .failure_window
+            .front()
+            .is_some_and(|&t| self.between(t, now) > window)
+        {
+            self.failure_window.pop_front();
+        }
+        self.failure_window.len() as u32
+    }
+
+    /// Adds a probe success now to the trailing window and returns how many
+    /// successes it holds.
+    fn count_success_in_window(&mut self, window: Duration) -> u64 {
+        let now = self.clock.now();
+        self.success_window.push_back(now);
+        while self
+            .success_window
+            .front()
+            .is_some_and(|&t| self.between(t, now) > window)
+        {
+            self.success_window.pop_front();
+        }
+        self.success_window.len() as u64
+    }
+
+    /// Moves between closed and degraded as the failure count crosses the
+    /// warning threshold, and fires `on_threshold_warning` if it is due.
+    fn update_degraded(&mut self) {
+        self.warn_near_threshold();
+        let Some(warning) = self.warning_threshold else {
+            return;
+        };
+        if matches!(self.state, State::Closed | State::Degraded) {
+            if self.failure_count > warning {
+                self.transition_to(State::Degraded);
+            } else {
+                self.transition_to(State::Closed);
+            }
+        }
+    }
+
+    fn warn_near_threshold(&mut self) {
+        let Some((percent, hook)) = &self.threshold_warning else {
+            return;
+        };
+        let level = (u64::from(self.failure_threshold) * u64::from(*percent)).div_ceil(100);
+        if u64::from(self.failure_count) < level {
+            self.threshold_warned = false;
+        } else if !self.threshold_warned {
+            self.threshold_warned = true;
+            (hook.0)(self.failure_count, self.failure_threshold);
+        }
+    }
+
+    /// Records a timed-out call, which opens the breaker at once unless it
+    /// is half-open and still within its timeout grace.
+    ///
+    /// Concurrent calls on a shared breaker often time out together. Only
+    /// the first one opens it; the rest are counted but do not restart the
+    /// recovery time, so the breaker opens once and for as long as a single
+    /// timeout would keep it open.
+    pub(crate) fn on_timeout(&mut self, label: Option<&str>) {
+        self.push_recent_failure(FailureKind::Timeout, label, None);
+        self.timeouts += 1;
+        self.count_call("timeout");
+        if self.state == State::Open {
+            return;
+        }
+        if self.state == State::HalfOpen && self.half_open_timeouts < self.half_open_timeout_grace {
+            self.half_open_timeouts += 1;
+            return;
+        }
+        if matches!(self.state, State::Closed | State::Degraded) {
+            if let Some(window) = self.timeout_coalescing {
+                let now = self.clock.now();
+                if self
+                    .last_counted_timeout
+                    .is_some_and(|t| self.between(t, now) < window)
+                {
+                    return;
+                }
+                self.last_counted_timeout = Some(now);
+            }
+            if let Some(tolerated) = self.timeout_threshold {
+                self.timeout_count += 1;
+                if self.timeout_count <= tolerated {
+                    return;
+                }
+            }
+        }
+        let count = if self.state == State::HalfOpen {
+            self.failed_probe_count()
+        } else {
+            1
+        };
+        let reason = match self.state {
+            State::HalfOpen | State::SoftOpen => TripReason::ProbeFailed,
+            State::Closed | State::Degraded | State::Open => TripReason::Timeout,
+        };
+        self.trip_to(State::Open, reason);
+        self.last_failure_time = Some(self.clock.now());
+        self.failure_count = count;
+    }
+
+    /// The failure count after a failed probe: one, or one more than before
+    /// if counts are kept across half-open.
+    fn failed_probe_count(&self) -> u32 {
+        if self.reset_on_half_open {
+            1
+        } else {
+            self.failure_count.saturating_add(1)
+        }
+    }
+}
+
+/// What [`CircuitBreaker::admit`] decided for a call.
+pub(crate) enum Admission<R> {
+    /// Run the protected function, on the calling thread if `inline`. A
+    /// call admitted under concurrency limits, such as a half-open probe,
+    /// holds its places in `slots` until they are dropped. `admitted_in`
+    /// is the state it is accounted in, see
+    /// [`admitted_in`](CircuitBreaker::admitted_in).
+    Run {
+        inline: bool,
+        slots: Vec<InFlightGuard>,
+        admitted_in: Option<State>,
+    },
+    /// Do not run it; `call` returns this value instead.
+    Skip(Option<R>),
+}
+
+/// How an admitted call finished.
+pub(crate) enum Outcome<R, E> {
+    Ok(R),
+    Err(E),
+    Timeout,
+    /// The protected function panicked, with the panic message if it was a
+    /// string. Only produced when panics are counted.
+    Panic(Option<String>),
+    /// The worker went away without sending a result, e.g. it panicked.
+    Disconnected,
+}
+
+/// What a protected function came back with: its own result, or the
+/// message it panicked with if panics are counted.
+pub(crate) type Finished<R, E> = Result<Result<R, E>, Option<String>>;
+
+impl<R, E> Outcome<R, E> {
+    /// Classifies how a protected function finished, the same way for every
+    /// call variant. Timeouts are decided by the caller, which knows how
+    /// long it waited.
+    pub(crate) fn from_finished(res: Finished<R, E>) -> Self {
+        match res {
+            Ok(Ok(data)) => Outcome::Ok(data),
+            Ok(Err(e)) => Outcome::Err(e),
+            Err(message) => Outcome::Panic(message),
+        }
+    }
+
+    /// Passes a successful value through `check`, which may turn it into
+    /// another value or into an error.
+    pub(crate) fn and_then<T>(self, check: impl FnOnce(R) -> Result<T, E>) -> Outcome<T, E> {
+        match self {
+            Outcome::Ok(data) => match check(data) {
+                Ok(data) => Outcome::Ok(data),
+                Err(e) => Outcome::Err(e),
+            },
+            Outcome::Err(e) => Outcome::Err(e),
+            Outcome::Timeout => Outcome::Timeout,
+            Outcome::Panic(message) => Outcome::Panic(message),
+            Outcome::Disconnected => Outcome::Disconnected,
+        }
+    }
+
+    /// What `call` returns for this outcome, without recording it.
+    pub(crate) fn into_result(self) -> Result<R, CircuitBreakerError<E>> {
+        match self {
+            Outcome::Ok(data) => Ok(data),
+            Outcome::Err(e) => Err(CircuitBreakerError::FunctionError(e)),
+            Outcome::Timeout => Err(CircuitBreakerError::TimeoutError),
+            Outcome::Panic(message) => Err(panicked(message.as_deref())),
+            Outcome::Disconnected => Err(CircuitBreakerError::Internal(
+                "worker exited without returning a result".to_string(),
+            )),
+        }
+    }
+}
+
+/// Runs `func`, catching a panic if `count_panics` is set. A panic that is
+/// not counted carries on as if it had not been caught.
+pub(crate) fn catch<R, E>(
+    func: impl FnOnce() -> Result<R, E>,
+    count_panics: bool,
+) -> Finished<R, E> {
+    panic::catch_unwind(AssertUnwindSafe(func)).map_err(|payload| {
+        if !count_panics {
+            panic::resume_unwind(payload);
+        }
+        panic_message(payload.as_ref())
+    })
+}
+
+/// How long `func` takes to run, its result dropped.
+pub(crate) fn time<R>(func: impl FnOnce() -> R) -> Duration {
+    let start = Instant::now();
+    drop(func());
+    start.elapsed()
+}
+
+fn panicked<E>(message: Option<&str>) -> CircuitBreakerError<E> {
+    CircuitBreakerError::Internal(format!(
+        "protected function panicked: {}",
+        message.unwrap_or("<non-string payload>")
+    ))
+}
+
+/// The message of a panic payload, which is a `&str` or `String` for
+/// `panic!` with a literal or format string and anything at all for
+/// `panic_any`.
+fn panic_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+}
+
+/// Runs protected functions with the breaker's timeout, without borrowing
+/// the breaker, so a shared breaker need not stay locked during the call.
+pub(crate) struct Runner {
+    timeout: Duration,
+    worker_pool: Option<WorkerPool>,
+    thread_limit: Option<ThreadLimit>,
+    timeout_strategy: Option<Hook<dyn TimeoutStrategy>>,
+    /// Name for a thread spawned for the call, so stuck workers can be told
+    /// apart in thread dumps and profilers.
+    worker_name: String,
+    in_flight: Arc<AtomicUsize>,
+    orphans: Arc<Orphans>,
+    late_results: Option<Arc<LateResults>>,
+    count_panics: bool,
+}
+
+const RUNNING: u8 = 0;
+const FINISHED: u8 = 1;
+const ABANDONED: u8 = 2;
+
+/// Workers whose call timed out and that are still running, with the
+/// threads spawned for them, kept to be joined once they finish.
+#[derive(Default)]
+pub(crate) struct Orphans {
+    count: AtomicUsize,
+    /// Each spawned thread with its worker's status.
+    threads: Mutex<Vec<(Arc<AtomicU8>, JoinHandle<()>)>>,
+    /// Signalled, under `threads`, whenever an orphan finishes.
+    finished: Condvar,
+}
+
+impl Orphans {
+    fn threads(&self) -> std::sync::MutexGuard<'_, Vec<(Arc<AtomicU8>, JoinHandle<()>)>> {
+        self.threads.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Keeps the thread of a worker that was abandoned, joining those of
+    /// earlier ones that have since finished.
+    fn keep(&self, status: Arc<AtomicU8>, thread: JoinHandle<()>) {
+        let finished = {
+            let mut threads = self.threads();
+            threads.push((status, thread));
+            take_finished(&mut threads)
+        };
+        join_all(finished);
+    }
+
+    /// Joins the threads of orphans that have finished.
+    fn reap(&self) {
+        let finished = take_finished(&mut self.threads());
+        join_all(finished);
+    }
+
+    /// Waits up to `grace` for every orphan to finish, joins the threads of
+    /// those that did, and returns how many are still running.
+    fn join(&self, grace: Duration) -> usize {
+        let deadline = Instant::now() + grace;
+        let mut threads = self.threads();
+        while self.count.load(Ordering::SeqCst) > 0 {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            threads = self
+                .finished
+                .wait_timeout(threads, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        let finished = take_finished(&mut threads);
+        drop(threads);
+        join_all(finished);
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// Takes out the threads whose worker has finished.
+fn take_finished(threads: &mut Vec<(Arc<AtomicU8>, JoinHandle<()>)>) -> Vec<JoinHandle<()>> {
+    let (finished, running): (Vec<_>, Vec<_>) = std::mem::take(threads)
+        .into_iter()
+        .partition(|(status, _)| status.load(Ordering::SeqCst) == FINISHED);
+    *threads = running;
+    finished.into_iter().map(|(_, thread)| thread).collect()
+}
+
+/// Joins threads whose worker has finished. Each exits right after, but
+/// may still need [`Orphans::threads`] to get there, so this must not run
+/// with it locked.
+fn join_all(threads: Vec<JoinHandle<()>>) {
+    for thread in threads {
+        let _ = thread.join();
+    }
+}
+
+/// Held by a worker until it finishes. The worker and the caller that
+/// gives up waiting on it each swap in their side of `status`; whichever
+/// comes second knows the worker has finished after being abandoned and
+/// takes it off the orphan count.
+struct WorkerExit {
+    status: Arc<AtomicU8>,
+    orphans: Arc<Orphans>,
+}
+
+impl Drop for WorkerExit {
+    fn drop(&mut self) {
+        if self.status.swap(FINISHED, Ordering::SeqCst) == ABANDONED {
+            self.orphans.count.fetch_sub(1, Ordering::SeqCst);
+            let _threads = self.orphans.threads();
+            self.orphans.finished.notify_all();
+        }
+    }
+}
+
+impl Runner {
+    /// Counts a call that the caller runs itself as in flight, until the
+    /// returned guard is dropped.
+    pub(crate) fn enter(&self) -> InFlightGuard {
+        InFlightGuard::new(&self.in_flight)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Runs `func`, returning how it finished and how long it took.
+    ///
+    /// Inline calls run on the calling thread and count as timed out after
+    /// the fact if they overran. Otherwise `func` is handed to the timeout
+    /// strategy, if there is one, or runs on a worker from the configured
+    /// pool, or one spawned for this call, and is abandoned once the
+    /// timeout passes.
+    pub(crate) fn run<F, R, E>(&self, inline: bool, func: F) -> (Outcome<R, E>, Duration)
+    where
+        F: FnOnce() -> Result<R, E> + Send + 'static,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let start = Instant::now();
+        let guard = InFlightGuard::new(&self.in_flight);
+        let count_panics = self.count_panics;
+        let func = move || catch(func, count_panics);
+
+        if inline {
+            let res = func();
+            drop(guard);
+            let elapsed = start.elapsed();
+            let outcome = match res {
+                _ if elapsed > self.timeout => Outcome::Timeout,
+                res => Outcome::from_finished(res),
+            };
+            return (outcome, elapsed);
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let late_results = self.late_results.clone();
+        let trace = TraceContext::current();
+        let status = Arc::new(AtomicU8::new(RUNNING));
+        let exit = WorkerExit {
+            status: Arc::clone(&status),
+            orphans: Arc::clone(&self.orphans),
+        };
+        let job = move || {
+            // Dropped in reverse, so the call has left `in_flight` by the
+            // time the worker counts as finished.
+            let _exit = exit;
+            let _guard = guard;
+            let res = trace.in_scope(func);
+            let succeeded = matches!(res, Ok(Ok(_)));
+            // The receiver is gone if the call already timed out.
+            if tx.send(res).is_err() {
+                if let Some(late) = late_results {
+                    late.push(LateResult {
+                        succeeded,
+                        elapsed: start.elapsed(),
+                        at: Instant::now(),
+                    });
+                }
+            }
+        };
+        if let Some(strategy) = &self.timeout_strategy {
+            let outcome = match strategy.0.run_with_timeout(Box::new(job), self.timeout) {
+                Completion::Finished => rx
+                    .try_recv()
+                    .map_or(Outcome::Disconnected, Outcome::from_finished),
+                Completion::TimedOut => self.abandon(&status, None),
+            };
+            return (outcome, start.elapsed());
+        }
+        let spawned = match &self.worker_pool {
+            Some(pool) => {
+                pool.execute(job);
+                None
+            }
+            None => {
+                // If no thread can be spawned, or the thread limit filled up
+                // since the call was admitted, the job is dropped with its
+                // sender, and the call fails as disconnected.
+                let thread = self.thread_limit.as_ref().map(ThreadLimit::take);
+                if matches!(thread, Some(None)) {
+                    None
+                } else {
+                    thread::Builder::new()
+                        .name(self.worker_name.clone())
+                        .spawn(move || {
+                            let _thread = thread;
+                            job();
+                        })
+                        .ok()
+                }
+            }
+        };
+
+        let outcome = match rx.recv_timeout(self.timeout) {
+            Ok(res) => Outcome::from_finished(res),
+            Err(RecvTimeoutError::Timeout) => {
+                return (self.abandon(&status, spawned), start.elapsed())
+            }
+            Err(RecvTimeoutError::Disconnected) => Outcome::Disconnected,
+        };
+        // The worker is done with the call, so this only waits for its
+        // thread to exit.
+        if let Some(spawned) = spawned {
+            let _ = spawned.join();
+        }
+        (outcome, start.elapsed())
+    }
+
+    /// Gives up on a worker that did not finish in time, counting it as
+    /// orphaned until it does, and keeping its thread, if it has its own,
+    /// to be joined then.
+    fn abandon<R, E>(
+        &self,
+        status: &Arc<AtomicU8>,
+        thread: Option<JoinHandle<()>>,
+    ) -> Outcome<R, E> {
+        // Counted first, so the worker never takes off a count that is not
+        // there yet.
+        self.orphans.count.fetch_add(1, Ordering::SeqCst);
+        if status.swap(ABANDONED, Ordering::SeqCst) == FINISHED {
+            self.orphans.count.fetch_sub(1, Ordering::SeqCst);
+            if let Some(thread) = thread {
+                let _ = thread.join();
+            }
+        } else if let Some(thread) = thread {
+            self.orphans.keep(Arc::clone(status), thread);
+        }
+        Outcome::Timeout
+    }
+}
+
+/// Wraps `func` so it can be run as a half-open probe, keeping it for
+/// another attempt if it fails again.
+fn replay_of<C, D, F, R, E>(func: F) -> Replay<C, D>
+where
+    C: Clock,
+    F: FnOnce() -> Result<R, E> + Clone + Send + Sync + 'static,
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    Box::new(move |cb: &mut CircuitBreaker<C, D>| {
+        let failed_before = cb.failures + cb.timeouts;
+        let kept = func.clone();
+        let inline = cb.runs_inline(cb.inline_probes);
+        let admitted_in = cb.admitted_in();
+        let (outcome, elapsed) = cb.runner().run(inline, func);
+        let _ = cb.complete(outcome, elapsed, admitted_in, None);
+        if cb.failures + cb.timeouts > failed_before {
+            cb.replay = Some(replay_of(kept));
+        }
+    })
+}
+
+/// Rounds to whole milliseconds so durations read as `1.5s` rather than
+/// `1.499873212s`.
+fn rounded(d: Duration) -> Duration {
+    Duration::from_millis(d.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+impl<C, D> Drop for CircuitBreaker<C, D> {
+    fn drop(&mut self) {
+        if let Some(batch) = &mut self.call_batch {
+            batch.flush(self.label.as_deref());
+        }
+    }
+}
+
+impl<C: Clock, D> fmt::Debug for CircuitBreaker<C, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hooks: Vec<&str> = [
+            ("default_when_open", self.default_when_open.is_some()),
+            ("on_half_open", self.on_half_open.is_some()),
+            ("on_open", self.on_open.is_some()),
+            ("on_closed", self.on_closed.is_some()),
+            ("on_state_change", self.on_state_change.is_some()),
+            ("on_shutdown", self.on_shutdown.is_some()),
+            ("transition_log", self.transition_log.is_some()),
+            ("on_result", self.on_result.is_some()),
+            ("on_flap", self.on_flap.is_some()),
+            ("on_clock_anomaly", self.on_clock_anomaly.is_some()),
+            ("on_recovery_failure", self.recovery_failure.is_some()),
+            ("on_threshold_warning", self.threshold_warning.is_some()),
+            (
+                "on_state_change_with_data",
+                self.on_state_change_with_data.is_some(),
+            ),
+            ("on_slow_call", self.on_slow_call.is_some()),
+            ("transition_guard", self.transition_guard.is_some()),
+            ("state_store", self.state_store.is_some()),
+            ("timeout_strategy", self.timeout_strategy.is_some()),
+            ("is_failure", self.is_failure.is_some()),
+            ("is_fatal", self.is_fatal.is_some()),
+            ("evaluate", self.evaluate.is_some()),
+            (
+                "retry_after_from_error",
+                self.retry_after_from_error.is_some(),
+            ),
+            ("dedupe_errors", self.dedupe_errors.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+
+        let mut s = f.debug_struct("CircuitBreaker");
+        s.field("name", &self.name)
+            .field("state", &self.state)
+            .field(
+                "failures",
+                &format_args!("{}/{}", self.failure_count, self.failure_threshold),
+            )
+            .field(
+                "half_open_successes",
+                &format_args!("{}/{}", self.open_success_count, self.open_threshold_count),
+            )
+            .field("time_until_retry", &self.time_until_retry().map(rounded))
+            .field(
+                "failed_probe_cycles",
+                &format_args!(
+                    "{}/{}",
+                    self.failed_probe_cycles,
+                    self.max_probe_attempts
+                        .map_or("unlimited".to_string(), |max| max.to_string())
+                ),
+            )
+            .field(
+                "half_open_timeouts",
+                &format_args!(
+                    "{}/{}",
+                    self.half_open_timeouts, self.half_open_timeout_grace
+                ),
+            )
+            .field(
+                "last_failure_ago",
+                &self.last_failure_time.map(|t| rounded(self.since(t))),
+            )
+            .field("timeout", &self.timeout)
+            .field("recovery_time", &self.recovery_time)
+            .field("backoff_max", &self.backoff_max)
+            .field("retry_after", &self.retry_after)
+            .field("trip_policy", &self.trip_policy)
+            .field("half_open_idle", &self.half_open_idle)
+            .field("inline_probes", &self.inline_probes)
+            .field("inline_when_closed", &self.inline_when_closed)
+            .field("reset_on_half_open", &self.reset_on_half_open)
+            .field("worker_pool", &self.worker_pool)
+            .field("thread_limit", &self.thread_limit)
+            .field("semaphore", &self.semaphore)
+            .field("in_flight", &self.in_flight())
+            .field("orphaned_workers", &self.orphaned_workers())
+            .field("total_successes", &self.successes)
+            .field("total_failures", &self.failures)
+            .field("total_timeouts", &self.timeouts)
+            .field("total_admitted", &self.admitted)
+            .field("total_rejected", &self.rejected)
+            .field("total_slow_calls", &self.slow_calls)
+            .field("slow_call_threshold", &self.slow_call_threshold)
+            .field("lenient_probes", &self.lenient_probes)
+            .field(
+                "recent_failures",
+                &format_args!(
+                    "{}/{}",
+                    self.recent_failures.len(),
+                    self.recent_failures_capacity
+                ),
+            )
+            .field(
+                "transition_history",
+                &format_args!("{}/{}", self.transitions.len(), self.transitions_capacity),
+            )
+            .field("failure_window", &self.failure_window.len())
+            .field("replay_pending", &self.replay.is_some())
+            .field(
+                "cached_success_age",
+                &self
+                    .cached_success
+                    .as_ref()
+                    .map(|(_, at)| rounded(self.since(*at))),
+            )
+            .field(
+                "late_results",
+                &self
+                    .late_results
+                    .as_ref()
+                    .map(|late| format!("{}/{}", late.len(), late.capacity())),
+            );
+        #[cfg(feature = "hdrhistogram")]
+        s.field("latency_samples", &self.latency.len());
+        #[cfg(feature = "tokio")]
+        s.field("async_subscribers", &self.transition_tx.receiver_count());
+        s.field("hooks", &hooks).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails calls until the breaker opens.
+    fn trip<D>(cb: &mut CircuitBreaker<SystemClock, D>) {
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+    }
+
+    #[test]
+    fn test_name_in_metrics_labels() {
+        let mut cb = CircuitBreaker::builder().name("payments").build().unwrap();
+        let _ = cb.call(|| Ok::<_, ()>(1));
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.name.as_deref(), Some("payments"));
+        assert_eq!(metrics.labels(), vec![("breaker", "payments".to_string())]);
+        assert!(format!("{:?}", cb).contains("payments"));
+
+        let unnamed = CircuitBreaker::builder().build().unwrap();
+        assert_eq!(unnamed.name(), None);
+        assert!(unnamed.metrics().labels().is_empty());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_new_millis_matches_new() {
+        let mut from_millis = CircuitBreaker::new_millis(1, 50, 100, 1);
+        let mut from_duration =
+            CircuitBreaker::new(1, Duration::from_millis(50), Duration::from_millis(100), 1);
+
+        for cb in [&mut from_millis, &mut from_duration] {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+            let _ = cb.call(|| Err::<(), _>("boom"));
+            assert_eq!(cb.state(), State::Open);
+            assert!(matches!(
+                cb.call(|| Ok::<_, ()>(())),
+                Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+            ));
+
+            thread::sleep(Duration::from_millis(120));
+            assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+            assert_eq!(cb.state(), State::HalfOpen);
+            assert!(matches!(cb.call(|| Ok::<_, ()>(7)), Ok(Some(7))));
+            assert_eq!(cb.state(), State::Closed);
+        }
+        let (a, b) = (from_millis.metrics(), from_duration.metrics());
+        assert_eq!(
+            (a.state, a.failure_count, a.successes, a.failures),
+            (b.state, b.failure_count, b.successes, b.failures)
+        );
+        assert_eq!(from_millis.timeout, from_duration.timeout);
+        assert_eq!(from_millis.recovery_time, from_duration.recovery_time);
+    }
+
+    #[test]
+    fn test_would_admit_each_state() {
+        let mut cb =
+            CircuitBreaker::new(0, Duration::from_millis(50), Duration::from_millis(100), 1);
+        assert_eq!(cb.state(), State::Closed);
+        assert!(cb.would_admit());
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert!(!cb.would_admit());
+        assert_eq!(cb.state(), State::Open);
+
+        thread::sleep(Duration::from_millis(120));
+        assert!(cb.would_admit());
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert!(cb.would_admit());
+
+        // Each limit `call` enforces, held without a call running.
+        let threads = ThreadLimit::new(1);
+        let semaphore = Arc::new(Semaphore::new(1));
+        let shared = crate::SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .max_concurrent(State::Closed, 1)
+                .thread_limit(threads.clone())
+                .semaphore(Arc::clone(&semaphore))
+                .build()
+                .unwrap(),
+        );
+        let held = shared.start_call().unwrap();
+        assert!(!shared.would_admit());
+        held.cancel();
+        assert!(shared.would_admit());
+        let held = semaphore.try_acquire().unwrap();
+        assert!(!shared.would_admit());
+        drop(held);
+        let held = threads.take().unwrap();
+        assert!(!shared.would_admit());
+        assert!(matches!(
+            shared.call(|| Ok::<_, ()>(())),
+            Err(CircuitBreakerError::Rejected(
+                RejectionReason::ResourceExhausted
+            ))
+        ));
+        drop(held);
+        assert!(shared.would_admit());
+
+        cb.half_open_min_priority = Priority::High;
+        assert!(!cb.would_admit());
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn test_latency_percentiles() {
+        let mut cb = CircuitBreaker::builder().build().unwrap();
+        assert_eq!(cb.latency_percentile(50.0), None);
+
+        for ms in 1..=100 {
+            cb.record_latency(Duration::from_millis(ms));
+        }
+        let within = |p: f64, expected_ms: u64| {
+            let got = cb.latency_percentile(p).unwrap();
+            let expected = Duration::from_millis(expected_ms);
+            got >= expected - Duration::from_millis(1) && got <= expected + Duration::from_millis(1)
+        };
+        assert!(within(50.0, 50));
+        assert!(within(90.0, 90));
+        assert!(within(99.0, 99));
+
+        let _ = cb.call(|| {
+            thread::sleep(Duration::from_millis(200));
+            Ok::<_, ()>(())
+        });
+        let max = cb.latency_percentile(100.0).unwrap();
+        assert!(max >= Duration::from_millis(200));
+        assert_eq!(cb.metrics().latency_p99, cb.latency_percentile(99.0));
+    }
+
+    #[test]
+    fn test_latency_by_outcome() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(10)
+            .timeout(Duration::from_secs(2))
+            .build_with_clock(crate::FakeClock::new())
+            .unwrap();
+        crate::Replay::new(&mut cb)
+            .latency(Duration::from_millis(3))
+            .succeed(3)
+            .latency(Duration::from_millis(200))
+            .fail(2)
+            .time_out(1);
+        let latency = cb.metrics().latency_by_outcome;
+        let bucket_of = |outcome| {
+            latency
+                .get(outcome)
+                .unwrap()
+                .buckets()
+                .filter(|&(_, count)| count > 0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            bucket_of(CallOutcome::Success),
+            [(Some(Duration::from_millis(5)), 3)]
+        );
+        assert_eq!(
+            bucket_of(CallOutcome::Failure),
+            [(Some(Duration::from_millis(250)), 2)]
+        );
+        assert_eq!(
+            bucket_of(CallOutcome::Timeout),
+            [(Some(Duration::from_millis(2500)), 1)]
+        );
+        assert!(latency
+            .get(CallOutcome::Rejected(RejectionReason::Open))
+            .is_none());
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn test_measure_records_latency_only() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let elapsed = cb.measure(|| thread::sleep(Duration::from_millis(50)));
+        assert!(elapsed >= Duration::from_millis(50));
+        for _ in 0..5 {
+            cb.measure(|| Err::<(), _>("boom"));
+        }
+
+        assert!(cb.latency_percentile(100.0).unwrap() >= Duration::from_millis(50));
+        assert_eq!(cb.state(), State::Closed);
+        let metrics = cb.metrics();
+        assert_eq!(
+            (metrics.successes, metrics.failures, metrics.timeouts),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_close_on_first_success() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_millis(50))
+            .half_open_successes(1)
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        thread::sleep(Duration::from_millis(70));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.open_success_count, 0);
+    }
+
+    #[test]
+    fn test_open_breaker_serves_fresh_cached_success() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .recovery_time(Duration::from_secs(60))
+            .cache_last_success::<String>(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        let _ = cb.call(|| Ok::<_, ()>("v1".to_string()));
+        let _ = cb.call(|| Ok::<_, ()>("v2".to_string()));
+        cb.trip();
+
+        for _ in 0..2 {
+            let res = cb.call(|| Ok::<_, ()>("v3".to_string()));
+            assert!(matches!(res, Ok(Some(v)) if v == "v2"));
+        }
+        assert_eq!(cb.metrics().rejected, 2);
+        // Other result types are rejected as usual.
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+
+        clock.advance(Duration::from_secs(11));
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>("v3".to_string())),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[test]
+    fn test_cache_without_a_success_rejects() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .cache_last_success::<String>(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let _ = cb.call(|| Err::<String, _>("boom"));
+        let _ = cb.call(|| Err::<String, _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert!(matches!(
+            cb.call(|| Ok::<_, &str>("v1".to_string())),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[test]
+    fn test_default_when_open() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_secs(60))
+            .default_when_open(Vec::<u32>::new())
+            .build()
+            .unwrap();
+
+        assert!(matches!(cb.call(|| Ok::<_, ()>(vec![1, 2])), Ok(Some(v)) if v == [1, 2]));
+        trip(&mut cb);
+
+        for _ in 0..3 {
+            let res = cb.call(|| Ok::<_, ()>(vec![3u32]));
+            assert!(matches!(res, Ok(Some(v)) if v.is_empty()));
+        }
+        // Other result types are rejected as usual.
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[test]
+    fn test_orphaned_workers_counts_abandoned_calls() {
+        const N: usize = 3;
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        let release = Arc::new(std::sync::Barrier::new(N + 1));
+
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(Some(()))));
+        for i in 0..N {
+            let release = Arc::clone(&release);
+            let res = cb.call(move || {
+                release.wait();
+                Ok::<_, ()>(())
+            });
+            assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+            assert_eq!(cb.orphaned_workers(), i + 1);
+            cb.reset();
+        }
+
+        release.wait();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while cb.orphaned_workers() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(cb.orphaned_workers(), 0);
+        assert_eq!(cb.orphans.threads().len(), N);
+        cb.reap_finished();
+        assert!(cb.orphans.threads().is_empty());
+    }
+
+    #[test]
+    fn test_in_flight_counts_running_workers() {
+        const N: usize = 4;
+        let mut cb = CircuitBreaker::new(5, Duration::from_millis(20), Duration::ZERO, 1);
+        let release = Arc::new(std::sync::Barrier::new(N + 1));
+
+        // Each call times out and leaves its worker blocked; with no recovery
+        // time the next call moves the breaker to half-open for another try.
+        for i in 0..N {
+            if i > 0 {
+                assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+            }
+            let release = Arc::clone(&release);
+            let res = cb.call(move || {
+                release.wait();
+                Ok::<_, ()>(())
+            });
+            assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+            assert_eq!(cb.in_flight(), i + 1);
+        }
+        assert_eq!(cb.in_flight(), N);
+
+        release.wait();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while cb.in_flight() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(cb.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_on_half_open_fires_once_per_transition() {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fired);
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_millis(30))
+            .half_open_successes(2)
+            .on_half_open(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+
+        for round in 1..=2 {
+            trip(&mut cb);
+            thread::sleep(Duration::from_millis(40));
+            assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+            assert_eq!(cb.state(), State::HalfOpen);
+            assert_eq!(fired.load(Ordering::SeqCst), round);
+
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(cb.state(), State::HalfOpen);
+            assert_eq!(fired.load(Ordering::SeqCst), round);
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(cb.state(), State::Closed);
+        }
+    }
+
+    #[test]
+    fn test_on_open_and_on_closed_fire_on_entry() {
+        use std::sync::Mutex;
+
+        let entered = Arc::new(Mutex::new(Vec::new()));
+        let hook = |name: &'static str| {
+            let entered = Arc::clone(&entered);
+            move || entered.lock().unwrap().push(name)
+        };
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_millis(30))
+            .half_open_successes(1)
+            .on_open(hook("open"))
+            .on_closed(hook("closed"))
+            .on_half_open(hook("half-open"))
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        thread::sleep(Duration::from_millis(40));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(*entered.lock().unwrap(), ["open", "half-open", "closed"]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum ServiceError {
+        Unavailable,
+        AuthRevoked,
+        NotFound,
+    }
+
+    #[test]
+    fn test_fatal_error_trips_immediately() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(3)
+            .timeout(Duration::from_millis(50))
+            .is_fatal(|e: &ServiceError| *e == ServiceError::AuthRevoked)
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>(ServiceError::Unavailable));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failure_count, 1);
+
+        let res = cb.call(|| Err::<(), _>(ServiceError::AuthRevoked));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::FunctionError(
+                ServiceError::AuthRevoked
+            ))
+        ));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.last_trip_reason(), Some(TripReason::FatalError));
+    }
+
+    #[test]
+    fn test_last_trip_reason() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut errors = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(1)
+            .build_with_clock(clock.clone())
+            .unwrap();
+        assert_eq!(errors.last_trip_reason(), None);
+        while errors.state() != State::Open {
+            let _ = errors.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(
+            errors.last_trip_reason(),
+            Some(TripReason::FailureThreshold)
+        );
+
+        // A failed probe reopens it for a different reason, which outlives
+        // the recovery.
+        clock.advance(Duration::from_secs(1));
+        let _ = errors.call(|| Ok::<_, ()>(()));
+        let _ = errors.call(|| Err::<(), _>("boom"));
+        assert_eq!(errors.last_trip_reason(), Some(TripReason::ProbeFailed));
+        clock.advance(Duration::from_secs(60));
+        let _ = errors.call(|| Ok::<_, ()>(()));
+        let _ = errors.call(|| Ok::<_, ()>(()));
+        assert_eq!(errors.state(), State::Closed);
+        assert_eq!(errors.last_trip_reason(), Some(TripReason::ProbeFailed));
+
+        let mut timeouts = CircuitBreaker::builder()
+            .failure_threshold(5)
+            .timeout(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let _ = timeouts.call(|| {
+            thread::sleep(Duration::from_millis(50));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(timeouts.state(), State::Open);
+        assert_eq!(timeouts.last_trip_reason(), Some(TripReason::Timeout));
+    }
+
+    #[test]
+    fn test_restart_recovery_reimposes_the_full_wait() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .recovery_time(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        cb.restart_recovery();
+        assert_eq!(cb.time_until_retry(), None);
+
+        cb.trip();
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(2)));
+        cb.restart_recovery();
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(8));
+        assert!(cb.call(|| Ok::<_, ()>(())).is_err());
+    }
+
+    #[test]
+    fn test_poll_recovery() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .recovery_time(Duration::from_secs(10))
+            .transition_guard(|_, to| to != State::HalfOpen)
+            .build_with_clock(clock.clone())
+            .unwrap();
+        assert!(!cb.poll_recovery());
+        cb.trip();
+        clock.advance(Duration::from_secs(9));
+        assert!(!cb.poll_recovery());
+        clock.advance(Duration::from_secs(1));
+        assert!(!cb.poll_recovery(), "the guard vetoes it");
+        assert_eq!(cb.state(), State::Open);
+
+        let mut cb = CircuitBreaker::builder()
+            .recovery_time(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        cb.trip();
+        clock.advance(Duration::from_secs(10));
+        assert!(cb.poll_recovery());
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert_eq!(cb.metrics().rejected, 0);
+        assert!(!cb.poll_recovery());
+    }
+
+    #[test]
+    fn test_fatal_error_counts_like_other_failures() {
+        use std::sync::Mutex;
+
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let seen = warnings.clone();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(4)
+            .timeout(Duration::from_millis(50))
+            .is_fatal(|e: &ServiceError| *e == ServiceError::AuthRevoked)
+            .on_threshold_warning(25, move |count, threshold| {
+                seen.lock().unwrap().push((count, threshold));
+            })
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>(ServiceError::AuthRevoked));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.last_trip_reason(), Some(TripReason::FatalError));
+        assert_eq!(*warnings.lock().unwrap(), [(1, 4)]);
+        assert_eq!(cb.metrics().failures, 1);
+    }
+
+    #[test]
+    fn test_non_fatal_errors_count_towards_threshold() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .timeout(Duration::from_millis(50))
+            .is_fatal(|e: &ServiceError| *e == ServiceError::AuthRevoked)
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>(ServiceError::Unavailable));
+            assert_eq!(cb.state(), State::Closed);
+        }
+        let _ = cb.call(|| Err::<(), _>(ServiceError::Unavailable));
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_is_failure_ignores_errors() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .is_failure(|e: &ServiceError| *e != ServiceError::NotFound)
+            .build()
+            .unwrap();
+
+        for _ in 0..5 {
+            let res = cb.call(|| Err::<(), _>(ServiceError::NotFound));
+            assert!(matches!(
+                res,
+                Err(CircuitBreakerError::FunctionError(ServiceError::NotFound))
+            ));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failures, 0);
+    }
+
+    #[test]
+    fn test_call_labeled_tags_failures() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(5)
+            .timeout(Duration::from_millis(20))
+            .recent_failures(2)
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        let _ = cb.call_labeled("get_user", || Err::<(), _>("boom"));
+        let _ = cb.call_labeled("list_orders", || {
+            thread::sleep(Duration::from_millis(100));
+            Ok::<_, ()>(())
+        });
+
+        let failures = cb.recent_failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].label.as_deref(), Some("get_user"));
+        assert_eq!(failures[0].kind, FailureKind::Error);
+        assert_eq!(failures[1].label.as_deref(), Some("list_orders"));
+        assert_eq!(failures[1].kind, FailureKind::Timeout);
+    }
+
+    #[test]
+    fn test_transition_history() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_millis(20))
+            .half_open_successes(1)
+            .transition_history(4)
+            .build()
+            .unwrap();
+        assert!(cb.transition_history().is_empty());
+
+        for _ in 0..2 {
+            trip(&mut cb);
+            thread::sleep(Duration::from_millis(30));
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(cb.state(), State::Closed);
+        }
+
+        let history = cb.transition_history();
+        let steps: Vec<_> = history.iter().map(|t| (t.from, t.to)).collect();
+        assert_eq!(
+            steps,
+            [
+                (State::HalfOpen, State::Closed),
+                (State::Closed, State::Open),
+                (State::Open, State::HalfOpen),
+                (State::HalfOpen, State::Closed),
+            ]
+        );
+        assert!(history.windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[test]
+    fn test_transition_log_writes_json_lines() {
+        use std::io;
+        use std::sync::Mutex;
+
+        use crate::FakeClock;
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let clock = FakeClock::new();
+        let buffer = Buffer::default();
+        let mut cb = CircuitBreaker::builder()
+            .name("payments")
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(1)
+            .transition_log(buffer.clone())
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        cb.trip();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let steps: Vec<_> = lines
+            .iter()
+            .map(|line| {
+                assert_eq!(line["breaker"], "payments");
+                assert!(line["at"].as_u64().unwrap() > 0);
+                (
+                    line["from"].as_str().unwrap(),
+                    line["to"].as_str().unwrap(),
+                    line["reason"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("closed", "open", Some("failure_threshold")),
+                ("open", "half_open", None),
+                ("half_open", "closed", None),
+                ("closed", "open", Some("manual")),
+            ]
+        );
+
+        #[cfg(feature = "serde")]
+        for line in output.lines() {
+            let record: TransitionRecord = serde_json::from_str(line).unwrap();
+            assert_eq!(serde_json::to_string(&record).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn test_inline_when_closed_or_without_timeout() {
+        let caller = thread::current().id();
+        let on_caller = |cb: &mut CircuitBreaker| matches!(cb.call(|| Ok::<_, ()>(thread::current().id())), Ok(Some(id)) if id == caller);
+
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .inline_when_closed(true)
+            .build()
+            .unwrap();
+        assert!(on_caller(&mut cb));
+        let res = cb.call(|| {
+            thread::sleep(Duration::from_millis(40));
+            Ok::<_, ()>(())
+        });
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::MAX)
+            .build()
+            .unwrap();
+        assert!(on_caller(&mut cb));
+
+        let mut cb = CircuitBreaker::builder().build().unwrap();
+        assert!(!on_caller(&mut cb));
+    }
+
+    #[test]
+    fn test_inline_probes_run_on_caller_thread() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_millis(20))
+            .half_open_successes(2)
+            .inline_probes(true)
+            .build()
+            .unwrap();
+        let caller = thread::current().id();
+
+        let res = cb.call(|| Ok::<_, ()>(thread::current().id()));
+        assert!(matches!(res, Ok(Some(id)) if id != caller));
+
+        trip(&mut cb);
+        thread::sleep(Duration::from_millis(30));
+        let _ = cb.call(|| Ok::<_, ()>(thread::current().id()));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        let res = cb.call(|| Ok::<_, ()>(thread::current().id()));
+        assert!(matches!(res, Ok(Some(id)) if id == caller));
+
+        // An overrunning probe still completes, but counts as a timeout.
+        trip(&mut cb);
+        thread::sleep(Duration::from_millis(30));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let res = cb.call(|| {
+            thread::sleep(Duration::from_millis(80));
+            Ok::<_, ()>(())
+        });
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_windowed_policy_trips_on_alternating_failures() {
+        let alternate = |cb: &mut CircuitBreaker| {
+            for _ in 0..4 {
+                let _ = cb.call(|| Err::<(), _>("boom"));
+                let _ = cb.call(|| Ok::<_, &str>(()));
+            }
+        };
+        let builder = CircuitBreaker::builder()
+            .failure_threshold(3)
+            .timeout(Duration::from_millis(50));
+
+        let mut consecutive = builder.clone().build().unwrap();
+        alternate(&mut consecutive);
+        assert_eq!(consecutive.state(), State::Closed);
+
+        let mut windowed = builder
+            .trip_policy(TripPolicy::Windowed {
+                window: Duration::from_secs(10),
+            })
+            .build()
+            .unwrap();
+        alternate(&mut windowed);
+        assert_eq!(windowed.state(), State::Open);
+    }
+
+    #[test]
+    fn test_decaying_policy_forgets_old_failures() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(3)
+            .trip_policy(TripPolicy::Decaying {
+                half_life: Duration::from_secs(10),
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+        for _ in 0..3 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.failure_score(), 3.0);
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.failure_score(), 3.0);
+
+        // Two half-lives of quiet bring the burst down to a quarter.
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cb.failure_score(), 0.75);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Closed);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Closed);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.failure_score(), 0.0);
+    }
+
+    #[test]
+    fn test_consecutive_or_windowed_policy() {
+        use crate::FakeClock;
+
+        let breaker = |clock: &FakeClock| {
+            CircuitBreaker::builder()
+                .failure_threshold(2)
+                .trip_policy(TripPolicy::ConsecutiveOrWindowed {
+                    window: Duration::from_secs(60),
+                    total: 4,
+                })
+                .build_with_clock(clock.clone())
+                .unwrap()
+        };
+        let fail = |cb: &mut CircuitBreaker<FakeClock>| {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        };
+        let succeed = |cb: &mut CircuitBreaker<FakeClock>| {
+            let _ = cb.call(|| Ok::<_, &str>(()));
+        };
+
+        // A streak of three trips it long before the window fills.
+        let clock = FakeClock::new();
+        let mut cb = breaker(&clock);
+        (0..3).for_each(|_| fail(&mut cb));
+        assert_eq!(cb.state(), State::Open);
+
+        // So do five failures within a minute, none of them consecutive.
+        let mut cb = breaker(&clock);
+        for _ in 0..4 {
+            fail(&mut cb);
+            succeed(&mut cb);
+            clock.advance(Duration::from_secs(10));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        fail(&mut cb);
+        assert_eq!(cb.state(), State::Open);
+
+        // Short streaks spread out over more than the window trip neither.
+        let mut cb = breaker(&clock);
+        for _ in 0..10 {
+            fail(&mut cb);
+            fail(&mut cb);
+            succeed(&mut cb);
+            clock.advance(Duration::from_secs(40));
+        }
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_separate_error_and_timeout_thresholds() {
+        let breaker = || {
+            CircuitBreaker::builder()
+                .failure_threshold(2)
+                .timeout_threshold(1)
+                .timeout(Duration::from_millis(20))
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap()
+        };
+        let fail = |cb: &mut CircuitBreaker| {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        };
+        let time_out = |cb: &mut CircuitBreaker| {
+            let res = cb.call(|| {
+                thread::sleep(Duration::from_millis(60));
+                Ok::<_, &str>(())
+            });
+            assert!(res.is_err());
+        };
+
+        let mut errors = breaker();
+        (0..2).for_each(|_| fail(&mut errors));
+        assert_eq!(errors.state(), State::Closed);
+        fail(&mut errors);
+        assert_eq!(errors.state(), State::Open);
+
+        let mut timeouts = breaker();
+        time_out(&mut timeouts);
+        assert_eq!(timeouts.state(), State::Closed);
+        time_out(&mut timeouts);
+        assert_eq!(timeouts.state(), State::Open);
+
+        // Two errors and one timeout are each within their own limit.
+        let mut mixed = breaker();
+        fail(&mut mixed);
+        time_out(&mut mixed);
+        fail(&mut mixed);
+        assert_eq!(mixed.state(), State::Closed);
+        time_out(&mut mixed);
+        assert_eq!(mixed.state(), State::Open);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "open without a last failure time")]
+    fn test_invariants_catch_a_mis_set_state() {
+        let mut cb = CircuitBreaker::builder().build().unwrap();
+        cb.check_invariants();
+
+        // What a handler that opened the breaker but forgot to record the
+        // failure would leave behind.
+        cb.state = State::Open;
+        cb.last_failure_time = None;
+        cb.check_invariants();
+    }
+
+    #[test]
+    fn test_different_operations_share_one_budget() {
+        #[derive(Debug)]
+        struct QuotaExceeded;
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let lookup = |cb: &mut CircuitBreaker| cb.call(|| Err::<String, _>(QuotaExceeded));
+        let upload = |cb: &mut CircuitBreaker| cb.call(|| Err::<u64, _>("429 Too Many Requests"));
+
+        assert!(matches!(
+            lookup(&mut cb),
+            Err(CircuitBreakerError::FunctionError(QuotaExceeded))
+        ));
+        assert!(matches!(
+            upload(&mut cb),
+            Err(CircuitBreakerError::FunctionError(_))
+        ));
+        assert_eq!(cb.state(), State::Closed);
+        let _ = lookup(&mut cb);
+        assert_eq!(cb.state(), State::Open);
+        assert!(matches!(
+            upload(&mut cb),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[test]
+    fn test_windowed_policy_forgets_old_failures() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .trip_policy(TripPolicy::Windowed {
+                window: Duration::from_millis(30),
+            })
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        thread::sleep(Duration::from_millis(50));
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failure_count, 1);
+    }
+
+    #[test]
+    fn test_zero_recovery_time_half_opens_on_next_call() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        assert!(cb.would_admit());
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_record_failure_and_success() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .half_open_successes(1)
+            .recovery_time(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        cb.record_failure();
+        cb.record_failure();
+        assert_eq!(cb.state(), State::Closed);
+        cb.record_success();
+        assert_eq!(cb.metrics().failure_count, 0);
+
+        for _ in 0..3 {
+            cb.record_failure();
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().failures, 5);
+
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+        cb.record_success();
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_call_with_state() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let (res, state) = cb.call_with_state(|| Ok::<_, &str>(1));
+        assert!(matches!(res, Ok(Some(1))));
+        assert_eq!(state, State::Closed);
+        assert_eq!(state, cb.state());
+
+        let _ = cb.call_with_state(|| Err::<(), _>("boom"));
+        let (res, state) = cb.call_with_state(|| Err::<(), _>("boom"));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::FunctionError("boom"))
+        ));
+        assert_eq!(state, State::Open);
+        assert_eq!(state, cb.state());
+    }
+
+    #[test]
+    fn test_thread_limit_rejects_instead_of_spawning() {
+        use std::sync::Mutex;
+
+        let limit = ThreadLimit::new(2);
+        let mut cb = CircuitBreaker::builder()
+            .timeout_threshold(10)
+            .timeout(Duration::from_millis(20))
+            .thread_limit(limit.clone())
+            .build()
+            .unwrap();
+
+        // Two workers stay stuck after their calls time out.
+        let (release, held) = mpsc::channel::<()>();
+        let held = Arc::new(Mutex::new(held));
+        for _ in 0..2 {
+            let held = Arc::clone(&held);
+            let res = cb.call(move || {
+                let _ = held.lock().unwrap().recv();
+                Ok::<_, ()>(())
+            });
+            assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        }
+        assert_eq!(limit.running(), 2);
+
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let res = cb.call({
+            let spawned = Arc::clone(&spawned);
+            move || {
+                spawned.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>(())
+            }
+        });
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(
+                RejectionReason::ResourceExhausted
+            ))
+        ));
+        assert_eq!(spawned.load(Ordering::SeqCst), 0);
+        assert_eq!(limit.running(), 2);
+        assert_eq!(cb.metrics().rejected, 1);
+
+        // Threads count until they exit.
+        drop(release);
+        while limit.running() > 0 {
+            thread::yield_now();
+        }
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+    }
+
+    #[test]
+    fn test_worker_pool_reuses_threads() {
+        let pool = WorkerPool::new(1);
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(100))
+            .worker_pool(pool.clone())
+            .build()
+            .unwrap();
+        let mut other = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(100))
+            .worker_pool(pool)
+            .build()
+            .unwrap();
+
+        let first = cb.call(|| Ok::<_, ()>(thread::current().id()));
+        let second = other.call(|| Ok::<_, ()>(thread::current().id()));
+        let (Ok(Some(first)), Ok(Some(second))) = (first, second) else {
+            panic!("pooled calls should succeed");
+        };
+        assert_eq!(first, second);
+        assert_ne!(first, thread::current().id());
+
+        let res = cb.call(|| Err::<(), _>("boom"));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::FunctionError("boom"))
+        ));
+        assert_eq!(cb.metrics().successes, 1);
+    }
+
+    #[derive(Debug)]
+    struct Response {
+        status: u16,
+    }
+
+    #[test]
+    fn test_evaluate_counts_ok_values_as_failures() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .evaluate(|r: &Response| r.status >= 500)
+            .build()
+            .unwrap();
+
+        let res = cb.call(|| Ok::<_, ()>(Response { status: 200 }));
+        assert!(matches!(res, Ok(Some(Response { status: 200 }))));
+        assert_eq!(cb.metrics().failures, 0);
+
+        for _ in 0..2 {
+            let res = cb.call(|| Ok::<_, ()>(Response { status: 503 }));
+            // The caller still gets the value.
+            assert!(matches!(res, Ok(Some(Response { status: 503 }))));
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.recent_failures()[0].kind, FailureKind::Value);
+    }
+
+    #[test]
+    fn test_call_and_then_demotes_empty_bodies() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(500))
+            .build()
+            .unwrap();
+        let non_empty = |body: String| match body.len() {
+            0 => Err("empty body"),
+            n => Ok(n),
+        };
+
+        let res = cb.call_and_then(|| Ok("items".to_string()), non_empty);
+        assert!(matches!(res, Ok(Some(5))));
+        for _ in 0..2 {
+            let res = cb.call_and_then(|| Ok(String::new()), non_empty);
+            assert!(matches!(
+                res,
+                Err(CircuitBreakerError::FunctionError("empty body"))
+            ));
+        }
+        assert_eq!(cb.state(), State::Open);
+        let metrics = cb.metrics();
+        assert_eq!((metrics.successes, metrics.failures), (1, 2));
+    }
+
+    #[test]
+    fn test_debug_output_is_readable() {
+        let mut cb = CircuitBreaker::builder()
+            .name("payments")
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .recovery_time(Duration::from_secs(30))
+            .is_fatal(|_: &&str| false)
+            .build()
+            .unwrap();
+
+        let closed = format!("{:?}", cb);
+        assert!(closed.contains("state: Closed"));
+        assert!(closed.contains("failures: 0/1"));
+        assert!(closed.contains("time_until_retry: None"));
+        assert!(closed.contains("hooks: [\"is_fatal\"]"));
+
+        trip(&mut cb);
+        let open = format!("{:?}", cb);
+        assert!(open.contains("state: Open"));
+        assert!(open.contains("time_until_retry: Some(29."));
+        assert!(cb.time_until_retry().unwrap() <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_worker_panic_is_internal_error() {
+        use std::sync::Mutex;
+
+        use crate::CallOutcome;
+
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_secs(1))
+            .on_result({
+                let outcomes = Arc::clone(&outcomes);
+                move |result| outcomes.lock().unwrap().push(result.outcome)
+            })
+            .build()
+            .unwrap();
+
+        let res = cb.call(|| -> Result<(), ()> { panic!("worker blew up") });
+        assert!(matches!(res, Err(CircuitBreakerError::Internal(_))));
+        assert_eq!(cb.state(), State::Closed);
+        let metrics = cb.metrics();
+        assert_eq!(metrics.failures, 0);
+        assert_eq!(metrics.latency_by_outcome.failure.count(), 0);
+        assert_eq!(*outcomes.lock().unwrap(), [CallOutcome::Internal]);
+    }
+
+    #[test]
+    fn test_on_recovery_failure_fires_after_repeated_failed_probes() {
+        use std::sync::Mutex;
+
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(1)
+            .on_recovery_failure(3, {
+                let reported = Arc::clone(&reported);
+                move |cycles| reported.lock().unwrap().push(cycles)
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+        let probe = |cb: &mut CircuitBreaker<FakeClock>, res: Result<(), &'static str>| {
+            clock.advance(Duration::from_secs(3600));
+            let _ = cb.call(|| Ok::<_, &str>(()));
+            assert_eq!(cb.state(), State::HalfOpen);
+            let _ = cb.call(move || res);
+        };
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        for _ in 0..4 {
+            probe(&mut cb, Err("boom"));
+        }
+        assert_eq!(cb.failed_recovery_cycles(), 4);
+        assert_eq!(*reported.lock().unwrap(), [3, 4]);
+
+        // A recovery starts the count over.
+        probe(&mut cb, Ok(()));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.failed_recovery_cycles(), 0);
+        assert_eq!(reported.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_max_probe_attempts_stops_probing() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(20))
+            .recovery_time(Duration::from_millis(20))
+            .max_probe_attempts(2)
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        for cycle in 1..=2 {
+            assert!(!cb.probes_exhausted());
+            thread::sleep(Duration::from_millis(30));
+            assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+            let _ = cb.call(|| {
+                thread::sleep(Duration::from_millis(50));
+                Ok::<_, ()>(())
+            });
+            assert_eq!(cb.state(), State::Open);
+            assert_eq!(cb.failed_probe_cycles, cycle);
+        }
+
+        assert!(cb.probes_exhausted());
+        thread::sleep(Duration::from_millis(30));
+        assert!(!cb.would_admit());
+        let res = cb.call(|| Ok::<_, ()>(()));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(
+                RejectionReason::ProbesExhausted
+            ))
+        ));
+        assert_eq!(cb.state(), State::Open);
+
+        cb.reset();
+        assert_eq!(cb.state(), State::Closed);
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+    }
+
+    #[test]
+    fn test_call_with_fallback() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        assert_eq!(cb.call_with_fallback(|| Ok::<_, ()>(1), || 0), 1);
+        assert_eq!(cb.call_with_fallback(|| Err::<i32, _>("boom"), || 0), 0);
+        trip(&mut cb);
+        assert_eq!(cb.call_with_fallback(|| Ok::<_, ()>(1), || 0), 0);
+    }
+
+    #[test]
+    fn test_call_fast_fallback_returns_at_once_when_open() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(500))
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let res = cb.call_fast_fallback(|| Err::<i32, _>("boom"), || 0);
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::FunctionError("boom"))
+        ));
+
+        trip(&mut cb);
+        let start = Instant::now();
+        let res = cb.call_fast_fallback(
+            || {
+                thread::sleep(Duration::from_millis(400));
+                Ok::<_, ()>(1)
+            },
+            || 0,
+        );
+        assert!(matches!(res, Ok(0)));
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_call_accepts_boxed_closures() {
+        type Op = Box<dyn FnOnce() -> Result<u32, String> + Send>;
+        let ops: Vec<Op> = vec![Box::new(|| Ok(1)), Box::new(|| Err("boom".to_string()))];
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let results: Vec<_> = ops.into_iter().map(|op| cb.call(op)).collect();
+        assert!(matches!(results[0], Ok(Some(1))));
+        assert!(matches!(&results[1], Err(CircuitBreakerError::FunctionError(e)) if e == "boom"));
+    }
+
+    #[test]
+    fn test_metrics_count_timeouts_apart_from_failures() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(5)
+            .timeout(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.metrics().failure_count, 1);
+        let _ = cb.call(|| {
+            thread::sleep(Duration::from_millis(60));
+            Ok::<_, ()>(())
+        });
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.failures, 1);
+        assert_eq!(metrics.timeouts, 1);
+        assert_eq!(metrics.state, State::Open);
+    }
+
+    #[test]
+    fn test_call_replayable_probes_with_last_failed_call() {
+        use std::sync::atomic::AtomicBool;
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_millis(20))
+            .half_open_successes(1)
+            .build()
+            .unwrap();
+        let healthy = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let op = {
+            let healthy = Arc::clone(&healthy);
+            let attempts = Arc::clone(&attempts);
+            move || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                if healthy.load(Ordering::SeqCst) {
+                    Ok(1)
+                } else {
+                    Err("down")
+                }
+            }
+        };
+
+        while cb.state() != State::Open {
+            let _ = cb.call_replayable(op.clone());
+        }
+        let failed_attempts = attempts.load(Ordering::SeqCst);
+
+        healthy.store(true, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(40));
+        let res = cb.call_replayable(|| Ok::<_, &str>(2));
+
+        assert!(matches!(res, Ok(Some(2))));
+        assert_eq!(attempts.load(Ordering::SeqCst), failed_attempts + 1);
+        assert_eq!(cb.state(), State::Closed);
+        assert!(format!("{:?}", cb).contains("replay_pending: false"));
+    }
+
+    #[test]
+    fn test_initial_state_open_waits_for_recovery() {
+        let mut cb = CircuitBreaker::builder()
+            .initial_state(State::Open)
+            .recovery_time(Duration::from_millis(30))
+            .half_open_successes(1)
+            .build()
+            .unwrap();
+
+        assert_eq!(cb.state(), State::Open);
+        assert!(cb.time_until_retry().is_some_and(|d| d > Duration::ZERO));
+        assert!(cb.call(|| Ok::<_, ()>(1)).is_err());
+
+        thread::sleep(Duration::from_millis(40));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_restore_open_partway_through_recovery() {
+        use std::time::SystemTime;
+
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .initial_state(State::Open)
+            .recovery_time(Duration::from_secs(30))
+            .last_failure_ago(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(20)));
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cb.time_until_retry(), Some(Duration::ZERO));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        let cb = CircuitBreaker::builder()
+            .initial_state(State::Open)
+            .recovery_time(Duration::from_secs(30))
+            .last_failure_at(SystemTime::now() - Duration::from_secs(25))
+            .build_with_clock(FakeClock::new())
+            .unwrap();
+        let left = cb.time_until_retry().unwrap();
+        assert!(left <= Duration::from_secs(5) && left > Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_late_results_record_slow_successes() {
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .late_results(4)
+            .build()
+            .unwrap();
+
+        let res = cb.call(|| {
+            thread::sleep(Duration::from_millis(60));
+            Ok::<_, ()>(1)
+        });
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        assert!(cb.drain_late_results().is_empty());
+
+        while cb.in_flight() > 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let late = cb.drain_late_results();
+        assert_eq!(late.len(), 1);
+        assert!(late[0].succeeded);
+        assert!(late[0].elapsed >= Duration::from_millis(60));
+        assert!(cb.drain_late_results().is_empty());
+    }
+
+    #[test]
+    fn test_warning_threshold_enters_and_leaves_degraded() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(3)
+            .warning_threshold(1)
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Closed);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Degraded);
+        assert!(cb.would_admit());
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Closed);
+
+        trip(&mut cb);
+        let states: Vec<_> = cb.transition_history().iter().map(|t| t.to).collect();
+        assert_eq!(
+            states,
+            vec![State::Degraded, State::Closed, State::Degraded, State::Open]
+        );
+    }
+
+    #[test]
+    fn test_threshold_warning_fires_before_trip() {
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(5)
+            .on_threshold_warning(80, {
+                let events = Arc::clone(&events);
+                move |count, threshold| {
+                    events
+                        .lock()
+                        .unwrap()
+                        .push(format!("warning {count}/{threshold}"))
+                }
+            })
+            .on_open({
+                let events = Arc::clone(&events);
+                move || events.lock().unwrap().push("open".to_string())
+            })
+            .build()
+            .unwrap();
+        let fail = |cb: &mut CircuitBreaker| {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        };
+
+        // Dropping back below the level and crossing it again warns again.
+        (0..4).for_each(|_| fail(&mut cb));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        (0..6).for_each(|_| fail(&mut cb));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["warning 4/5", "warning 4/5", "open"]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_joins_workers_and_sends_final_event() {
+        use std::cell::RefCell;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Mutex;
+
+        /// Set once the worker's thread exits, which only a join waits for.
+        struct SetOnExit(Arc<AtomicBool>);
+
+        impl Drop for SetOnExit {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        thread_local! {
+            static ON_EXIT: RefCell<Option<SetOnExit>> = const { RefCell::new(None) };
+        }
+
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let last_state = Arc::new(Mutex::new(None));
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .on_state_change({
+                let transitions = Arc::clone(&transitions);
+                move |t| transitions.lock().unwrap().push((t.from, t.to))
+            })
+            .on_shutdown({
+                let last_state = Arc::clone(&last_state);
+                move |state| *last_state.lock().unwrap() = Some(state)
+            })
+            .build()
+            .unwrap();
+
+        let exited = Arc::new(AtomicBool::new(false));
+        let _ = cb.call({
+            let exited = Arc::clone(&exited);
+            move || {
+                ON_EXIT.with(|on_exit| *on_exit.borrow_mut() = Some(SetOnExit(exited)));
+                thread::sleep(Duration::from_millis(80));
+                Ok::<_, ()>(())
+            }
+        });
+        assert_eq!(cb.orphaned_workers(), 1);
+
+        assert_eq!(cb.shutdown(Duration::from_secs(5)), 0);
+        assert!(exited.load(Ordering::SeqCst));
+        assert_eq!(*transitions.lock().unwrap(), [(State::Closed, State::Open)]);
+        assert_eq!(*last_state.lock().unwrap(), Some(State::Open));
+    }
+
+    #[test]
+    fn test_shutdown_gives_up_after_grace() {
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(10))
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| {
+            thread::sleep(Duration::from_millis(500));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(cb.shutdown(Duration::from_millis(20)), 1);
+    }
+
+    #[test]
+    fn test_retry_after_from_error_overrides_recovery_time() {
+        #[derive(Debug)]
+        struct Throttled(Duration);
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .retry_after_from_error(|e: &Throttled| Some(e.0))
+            .build()
+            .unwrap();
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>(Throttled(Duration::from_millis(30))));
+        }
+        assert!(cb
+            .time_until_retry()
+            .is_some_and(|d| d <= Duration::from_millis(30)));
+
+        thread::sleep(Duration::from_millis(40));
+        assert!(matches!(cb.call(|| Ok::<_, Throttled>(1)), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+    }
+
+    #[test]
+    fn test_fake_clock_drives_recovery() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb: CircuitBreaker<FakeClock> = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(30))
+            .half_open_successes(1)
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(30)));
+
+        clock.advance(Duration::from_secs(29));
+        assert!(!cb.would_admit());
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(cb.would_admit());
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_failure_predicates_combine() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(10)
+            .is_failure(|e: &&str| e.starts_with('5'))
+            .add_failure_predicate(|e: &&str| *e == "connection reset")
+            .add_fatal_predicate(|e: &&str| *e == "revoked")
+            .build()
+            .unwrap();
+        let class = |cb: &mut CircuitBreaker, e: &'static str| {
+            cb.call_classified(move || Err::<(), _>(e)).1
+        };
+        assert_eq!(class(&mut cb, "503"), Classification::CountedFailure);
+        assert_eq!(
+            class(&mut cb, "connection reset"),
+            Classification::CountedFailure
+        );
+        assert_eq!(class(&mut cb, "404"), Classification::IgnoredFailure);
+        // No predicate is for `i32` errors, so they count.
+        assert_eq!(
+            cb.call_classified(|| Err::<(), _>(1)).1,
+            Classification::CountedFailure
+        );
+        assert_eq!(cb.state(), State::Closed);
+        let _ = class(&mut cb, "revoked");
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_dedupe_errors_counts_a_run_once() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .dedupe_errors(Duration::from_secs(1), |e: &&str| *e)
+            .build_with_clock(clock.clone())
+            .unwrap();
+        let class = |cb: &mut CircuitBreaker<FakeClock>, e: &'static str| {
+            cb.call_classified(move || Err::<(), _>(e)).1
+        };
+        assert_eq!(class(&mut cb, "refused"), Classification::CountedFailure);
+        for _ in 0..5 {
+            assert_eq!(class(&mut cb, "refused"), Classification::IgnoredFailure);
+        }
+        assert_eq!(cb.metrics().failures, 1);
+        assert_eq!(cb.failure_count, 1);
+
+        // A success ends the run.
+        let _ = cb.call(|| Ok::<_, &str>(()));
+        assert_eq!(class(&mut cb, "refused"), Classification::CountedFailure);
+        // So does the window running out.
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(class(&mut cb, "refused"), Classification::CountedFailure);
+        assert_eq!(class(&mut cb, "reset"), Classification::CountedFailure);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_call_classified() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .is_failure(|e: &&str| *e != "not found")
+            .build()
+            .unwrap();
+
+        let (res, class) = cb.call_classified(|| Err::<(), _>("not found"));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::FunctionError("not found"))
+        ));
+        assert_eq!(class, Classification::IgnoredFailure);
+
+        let (_, class) = cb.call_classified(|| Ok::<_, &str>(1));
+        assert_eq!(class, Classification::Success);
+
+        let (_, class) = cb.call_classified(|| Err::<(), _>("down"));
+        assert_eq!(class, Classification::CountedFailure);
+
+        trip(&mut cb);
+        let (_, class) = cb.call_classified(|| Ok::<_, &str>(1));
+        assert_eq!(class, Classification::NotRun);
+    }
+
+    #[test]
+    fn test_to_dot_shows_configured_thresholds() {
+        let mut cb = CircuitBreaker::builder()
+            .name("payments")
+            .failure_threshold(3)
+            .timeout(Duration::from_secs(2))
+            .recovery_time(Duration::from_secs(30))
+            .half_open_successes(2)
+            .build()
+            .unwrap();
+        let dot = cb.to_dot();
+        assert!(dot.starts_with("digraph circuit_breaker {\n    label=\"payments\";\n"));
+        assert!(dot.contains("    closed [shape=circle, style=bold];\n"));
+        assert!(dot.contains("    closed -> open [label=\"more than 3 failures in a row\"];\n"));
+        assert!(dot.contains("    closed -> open [label=\"a call over 2s\"];\n"));
+        assert!(dot.contains("    open -> half_open [label=\"after 30s\"];\n"));
+        assert!(dot.contains("    half_open -> closed [label=\"2 successes in a row\"];\n"));
+        assert!(!dot.contains("degraded"));
+        assert!(dot.ends_with("}\n"));
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert!(cb
+            .to_dot()
+            .contains("    open [shape=circle, style=bold];\n"));
+    }
+
+    #[test]
+    fn test_status_line() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .name("payments")
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        assert_eq!(
+            cb.status_line(),
+            "cb[payments] state=closed failures=0/1 admitted=0 rejected=0"
+        );
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(2));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(
+            cb.status_line(),
+            "cb[payments] state=open failures=2/1 retry_in=8s admitted=2 rejected=1"
+        );
+    }
+
+    #[test]
+    fn test_self_test_flags_broken_config() {
+        let cb = CircuitBreaker::builder()
+            .recovery_time(Duration::from_secs(30))
+            .min_open_duration(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let report = cb.self_test();
+        assert_eq!(report.state, State::Closed);
+        assert!(report.clock_ok);
+        assert!(report.problems.is_empty());
+        assert!(report.is_ok());
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .min_open_duration(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        trip(&mut cb);
+        let before = (cb.metrics().admitted, cb.metrics().failures);
+        let report = cb.self_test();
+        assert_eq!(report.state, State::Open);
+        assert!(report.clock_ok);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].contains("min_open_duration"));
+        assert!(!report.is_ok());
+        assert_eq!((cb.metrics().admitted, cb.metrics().failures), before);
+    }
+
+    #[test]
+    fn test_min_open_duration_floors_recovery() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_millis(10))
+            .min_open_duration(Duration::from_secs(5))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!cb.would_admit());
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(4)));
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(())),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(cb.time_until_retry(), Some(Duration::ZERO));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+    }
+
+    #[test]
+    fn test_half_open_success_window() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::ZERO)
+            .half_open_successes(3)
+            .half_open_success_window(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        // Probes that succeed further apart than the window never add up.
+        for _ in 0..5 {
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            clock.advance(Duration::from_secs(11));
+        }
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert_eq!(cb.open_success_count, 1);
+
+        // Three within the window close it.
+        for _ in 0..3 {
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            clock.advance(Duration::from_secs(4));
+        }
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_half_open_timeout_grace() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(20))
+            .recovery_time(Duration::ZERO)
+            .half_open_successes(3)
+            .half_open_timeout_grace(1)
+            .build()
+            .unwrap();
+        let slow = || {
+            thread::sleep(Duration::from_millis(60));
+            Ok::<_, ()>(())
+        };
+
+        trip(&mut cb);
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        let _ = cb.call(slow);
+        assert_eq!(cb.state(), State::HalfOpen);
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(slow);
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        let _ = cb.call(slow);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_reconfigure_applies_new_threshold() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(5)
+            .build()
+            .unwrap();
+        for _ in 0..4 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+
+        let mut config = cb.config();
+        config.failure_threshold = 2;
+        cb.reconfigure(config).unwrap();
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failure_count, 2);
+
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.config(), config);
+
+        config.timeout = Duration::ZERO;
+        assert_eq!(cb.reconfigure(config), Err(BuildError::ZeroTimeout));
+        assert_eq!(cb.config().timeout, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_transition_guard_vetoes_closing() {
+        use std::sync::atomic::AtomicBool;
+
+        let maintenance = Arc::new(AtomicBool::new(true));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::ZERO)
+            .half_open_successes(1)
+            .transition_guard({
+                let maintenance = Arc::clone(&maintenance);
+                move |_, to| !(to == State::Closed && maintenance.load(Ordering::SeqCst))
+            })
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        maintenance.store(false, Ordering::SeqCst);
+        let _ = cb.call(|| Ok::<_, ()>(1));
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_state_store_override() {
+        use std::sync::Mutex;
+
+        use crate::{Override, StateStore};
+
+        #[derive(Default)]
+        struct MockStore(Mutex<Override>);
+
+        impl StateStore for Arc<MockStore> {
+            fn read_override(&self, name: Option<&str>) -> Override {
+                assert_eq!(name, Some("payments"));
+                *self.0.lock().unwrap()
+            }
+        }
+
+        let store = Arc::new(MockStore::default());
+        let mut cb = CircuitBreaker::builder()
+            .name("payments")
+            .failure_threshold(1)
+            .state_store(Arc::clone(&store))
+            .build()
+            .unwrap();
+
+        *store.0.lock().unwrap() = Override::ForceOpen;
+        assert!(!cb.would_admit());
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::ForcedOpen))
+        ));
+        assert_eq!(cb.state(), State::Closed);
+
+        *store.0.lock().unwrap() = Override::ForceClosed;
+        for _ in 0..3 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.state(), State::Closed);
+
+        *store.0.lock().unwrap() = Override::Auto;
+        trip(&mut cb);
+        assert_eq!(cb.state(), State::Open);
+        *store.0.lock().unwrap() = Override::ForceClosed;
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_backoff_saturates_at_max() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let max = Duration::from_secs(3600);
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .exponential_backoff(max)
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.time_until_retry(), Some(Duration::from_secs(1)));
+
+        let mut windows = Vec::new();
+        for _ in 0..100 {
+            let window = cb.time_until_retry().unwrap();
+            windows.push(window);
+            clock.advance(window);
+            let _ = cb.call(|| Ok::<_, &str>(()));
+            assert_eq!(cb.state(), State::HalfOpen);
+            let _ = cb.call(|| Err::<(), _>("boom"));
+            assert_eq!(cb.state(), State::Open);
+        }
+
+        assert_eq!(windows[1], Duration::from_secs(2));
+        assert_eq!(windows[2], Duration::from_secs(4));
+        assert_eq!(windows[3], Duration::from_secs(8));
+        assert_eq!(cb.time_until_retry(), Some(max));
+        assert_eq!(cb.effective_recovery_time(), max);
+        assert_eq!(cb.config().recovery_time, Duration::from_secs(1));
+        assert!(windows.iter().all(|&w| w <= max));
+    }
+
+    #[test]
+    fn test_rejection_ratio() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .build()
+            .unwrap();
+        assert_eq!(cb.rejection_ratio(), 0.0);
+
+        trip(&mut cb);
+        for _ in 0..2 {
+            assert!(cb.call(|| Ok::<_, &str>(())).is_err());
+        }
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.admitted, 2);
+        assert_eq!(metrics.rejected, 2);
+        assert_eq!(cb.rejection_ratio(), 0.5);
+    }
+
+    #[test]
+    fn test_spawned_workers_are_named() {
+        let mut named = CircuitBreaker::builder().name("payments").build().unwrap();
+        let res = named.call(|| Ok::<_, ()>(thread::current().name().map(str::to_owned)));
+        assert_eq!(res.unwrap().unwrap().as_deref(), Some("cb-payments-worker"));
+
+        let mut unnamed = CircuitBreaker::builder().build().unwrap();
+        let res = unnamed.call(|| Ok::<_, ()>(thread::current().name().map(str::to_owned)));
+        assert_eq!(res.unwrap().unwrap().as_deref(), Some("cb-worker"));
+    }
+
+    #[test]
+    fn test_reset_on_half_open() {
+        for (reset, expected) in [(true, 1), (false, 4)] {
+            let mut cb = CircuitBreaker::builder()
+                .failure_threshold(2)
+                .recovery_time(Duration::ZERO)
+                .reset_on_half_open(reset)
+                .build()
+                .unwrap();
+
+            trip(&mut cb);
+            assert_eq!(cb.metrics().failure_count, 3);
+            let _ = cb.call(|| Ok::<_, &str>(()));
+            assert_eq!(cb.state(), State::HalfOpen);
+            let _ = cb.call(|| Err::<(), _>("boom"));
+
+            assert_eq!(cb.state(), State::Open);
+            assert_eq!(cb.metrics().failure_count, expected, "reset: {reset}");
+        }
+    }
+
+    #[test]
+    fn test_call_with_deadline_is_cooperative() {
+        use std::rc::Rc;
+
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        // Rc is !Send, so this only compiles because nothing moves threads.
+        let value = Rc::new(7);
+        let res = cb.call_with_deadline(|_| Ok::<_, ()>(*value));
+        assert!(matches!(res, Ok(Some(7))));
+
+        let res = cb.call_with_deadline(|deadline| {
+            let mut polls = 0u64;
+            while !deadline.expired() {
+                polls += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+            Ok::<_, ()>(polls)
+        });
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        assert_eq!(cb.metrics().timeouts, 1);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_call_with_deadline_ignored_still_times_out() {
+        use std::sync::atomic::AtomicBool;
+
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        let ran_to_end = Arc::new(AtomicBool::new(false));
+        let res = cb.call_with_deadline(|_| {
+            thread::sleep(Duration::from_millis(60));
+            ran_to_end.store(true, Ordering::SeqCst);
+            Ok::<_, ()>(())
+        });
+        assert!(ran_to_end.load(Ordering::SeqCst));
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+        assert_eq!(cb.metrics().timeouts, 1);
+        assert_eq!(cb.metrics().successes, 0);
+    }
+
+    #[test]
+    fn test_flush_window_starts_fresh() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .trip_policy(TripPolicy::Windowed {
+                window: Duration::from_secs(60),
+            })
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.recent_failures().len(), 2);
+
+        cb.flush_window();
+        assert_eq!(cb.state(), State::Closed);
+        assert!(cb.recent_failures().is_empty());
+        assert_eq!(cb.metrics().failure_count, 0);
+
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().failures, 5);
+    }
+
+    #[test]
+    fn test_max_call_duration_fails_late_successes() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_secs(1))
+            .slow_call_threshold(Duration::from_millis(20))
+            .max_call_duration(Duration::from_millis(30))
+            .build()
+            .unwrap();
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        for _ in 0..3 {
+            let res = cb.call(|| {
+                thread::sleep(Duration::from_millis(50));
+                Ok::<_, ()>(1)
+            });
+            assert!(matches!(res, Err(CircuitBreakerError::DeadlineExceeded)));
+        }
+
+        // The dependency answered, so the breaker saw slow successes.
+        assert_eq!(cb.state(), State::Closed);
+        let metrics = cb.metrics();
+        assert_eq!(
+            (metrics.successes, metrics.slow_calls, metrics.failures),
+            (4, 3, 0)
+        );
+    }
+
+    #[test]
+    fn test_first_probe_gets_lenient_timeout() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(20))
+            .slow_call_threshold(Duration::from_millis(10))
+            .recovery_time(Duration::from_secs(10))
+            .half_open_successes(2)
+            .lenient_probes(1, Duration::from_secs(1), Duration::from_millis(500))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        let cold = || {
+            thread::sleep(Duration::from_millis(50));
+            Ok::<_, ()>(1)
+        };
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(10));
+        let _ = cb.call(|| Ok::<_, ()>(0));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        // The first probe may be slow, the next one may not.
+        assert!(matches!(cb.call(cold), Ok(Some(1))));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert_eq!(cb.metrics().slow_calls, 0);
+        assert!(matches!(
+            cb.call(cold),
+            Err(CircuitBreakerError::TimeoutError)
+        ));
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_on_slow_call_reports_duration() {
+        use std::sync::Mutex;
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut cb = CircuitBreaker::builder()
+            .slow_call_threshold(Duration::from_millis(20))
+            .on_slow_call({
+                let reported = Arc::clone(&reported);
+                move |elapsed| reported.lock().unwrap().push(elapsed)
+            })
+            .build()
+            .unwrap();
+
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(|| {
+            thread::sleep(Duration::from_millis(40));
+            Ok::<_, ()>(())
+        });
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0] >= Duration::from_millis(40));
+        assert_eq!(cb.metrics().slow_calls, 1);
+        assert_eq!(cb.state(), State::Closed);
+    }
+
+    #[test]
+    fn test_rejection_reasons() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        trip(&mut cb);
+        let res = cb.call(|| Ok::<_, ()>(()));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::ZERO)
+            .transition_guard(|from, to| !(from == State::Open && to == State::HalfOpen))
+            .build()
+            .unwrap();
+        trip(&mut cb);
+        let res = cb.call(|| Ok::<_, ()>(()));
+        assert!(matches!(
+            res,
+            Err(CircuitBreakerError::Rejected(RejectionReason::Vetoed))
+        ));
+        assert_eq!(cb.metrics().rejected, 1);
+        assert!(res.unwrap_err().to_string().contains("vetoed"));
+    }
+
+    #[test]
+    fn test_count_panics_records_message() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_secs(1))
+            .count_panics(true)
+            .build()
+            .unwrap();
+
+        let res = cb.call_labeled("flaky", || -> Result<(), ()> { panic!("worker blew up") });
+        assert!(
+            matches!(res, Err(CircuitBreakerError::Internal(msg)) if msg.contains("worker blew up"))
+        );
+        let _ = cb.call(|| -> Result<(), ()> { std::panic::panic_any(42) });
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().failures, 2);
+
+        let failures = cb.recent_failures();
+        assert_eq!(failures[0].kind, FailureKind::Panic);
+        assert_eq!(failures[0].label.as_deref(), Some("flaky"));
+        assert_eq!(failures[0].message.as_deref(), Some("worker blew up"));
+        assert_eq!(failures[1].kind, FailureKind::Panic);
+        assert_eq!(failures[1].message, None);
+    }
+
+    #[test]
+    fn test_call_shapes_classified_alike() {
+        let breaker = || {
+            CircuitBreaker::builder()
+                .failure_threshold(100)
+                .timeout(Duration::from_secs(1))
+                .count_panics(true)
+                .build()
+                .unwrap()
+        };
+        let blow_up = || -> Result<u32, &str> { panic!("blew up") };
+
+        // On a worker, inline and under a deadline, each shape is counted
+        // the same way.
+        for inline in [false, true] {
+            let cb = breaker();
+            let (outcome, _) = cb.runner().run(inline, || Ok::<_, &str>(1));
+            assert!(matches!(outcome, Outcome::Ok(1)));
+            let (outcome, _) = cb.runner().run(inline, || Err::<u32, _>("boom"));
+            assert!(matches!(outcome, Outcome::Err("boom")));
+            let (outcome, _) = cb.runner().run(inline, blow_up);
+            assert!(matches!(outcome, Outcome::Panic(Some(msg)) if msg == "blew up"));
+        }
+
+        let mut cb = breaker();
+        assert!(matches!(
+            cb.call_with_deadline(|_| Ok::<_, &str>(1)),
+            Ok(Some(1))
+        ));
+        assert!(matches!(
+            cb.call_with_deadline(|_| Err::<u32, _>("boom")),
+            Err(CircuitBreakerError::FunctionError("boom"))
+        ));
+        assert!(matches!(
+            cb.call_with_deadline(|_| blow_up()),
+            Err(CircuitBreakerError::Internal(msg)) if msg.contains("blew up")
+        ));
+        let metrics = cb.metrics();
+        assert_eq!((metrics.successes, metrics.failures), (1, 2));
+
+        // A function that cannot fail only fails by panicking.
+        let mut cb = breaker();
+        assert!(matches!(cb.call_infallible(|| 1), Ok(Some(1))));
+        assert!(matches!(
+            cb.call_infallible(|| -> u32 { panic!("blew up") }),
+            Err(CircuitBreakerError::Internal(_))
+        ));
+        let metrics = cb.metrics();
+        assert_eq!((metrics.successes, metrics.failures), (1, 1));
+    }
+
+    #[test]
+    fn test_disabled_breaker_lets_every_call_through() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        cb.set_enabled(false);
+        assert!(!cb.is_enabled());
+
+        for _ in 0..3 {
+            let res = cb.call(|| Err::<(), _>("boom"));
+            assert!(matches!(res, Err(CircuitBreakerError::FunctionError(_))));
+        }
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.metrics().failures, 3);
+
+        cb.set_enabled(true);
+        assert_eq!(cb.metrics().failure_count, 0);
+        trip(&mut cb);
+        assert_eq!(cb.state(), State::Open);
+        assert!(!cb.would_admit());
+
+        // Disabling again bypasses the open breaker without closing it.
+        cb.set_enabled(false);
+        assert!(matches!(cb.call(|| Ok::<_, ()>(7)), Ok(Some(7))));
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().rejected, 0);
+    }
+
+    #[test]
+    fn test_sub_millisecond_timings() {
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_micros(500))
+            .build()
+            .unwrap();
+        let res = cb.call(|| {
+            thread::sleep(Duration::from_millis(5));
+            Ok::<_, ()>(())
+        });
+        assert!(matches!(res, Err(CircuitBreakerError::TimeoutError)));
+
+        let mut cb = CircuitBreaker::builder()
+            .slow_call_threshold(Duration::from_micros(100))
+            .build()
+            .unwrap();
+        let res = cb.call_with_deadline(|_| {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_micros(200) {}
+            Ok::<_, ()>(())
+        });
+        assert!(matches!(res, Ok(Some(()))));
+        assert_eq!(cb.metrics().slow_calls, 1);
+    }
+
+    #[test]
+    fn test_half_open_idle_policies() {
+        use crate::FakeClock;
+
+        let half_open = |policy| {
+            let clock = FakeClock::new();
+            let mut cb = CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(30))
+                .half_open_successes(3)
+                .half_open_idle(policy)
+                .build_with_clock(clock.clone())
+                .unwrap();
+            while cb.state() != State::Open {
+                let _ = cb.call(|| Err::<(), _>("boom"));
+            }
+            clock.advance(Duration::from_secs(30));
+            assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+            assert_eq!(cb.state(), State::HalfOpen);
+            (cb, clock)
+        };
+        let after = Duration::from_secs(60);
+
+        let (mut cb, clock) = half_open(HalfOpenIdle::Stay);
+        clock.advance(Duration::from_secs(3600));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        // One good probe, then silence: close instead of waiting for two more.
+        let (mut cb, clock) = half_open(HalfOpenIdle::Close { after });
+        let _ = cb.call(|| Ok::<_, ()>(1));
+        clock.advance(Duration::from_secs(59));
+        let _ = cb.call(|| Ok::<_, ()>(1));
+        assert_eq!(cb.state(), State::HalfOpen);
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(cb.state(), State::Closed);
+
+        // No probe at all within the window: reopen and wait again.
+        let (mut cb, clock) = half_open(HalfOpenIdle::Reopen { after });
+        clock.advance(after);
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+        assert_eq!(cb.state(), State::Open);
+        assert!(cb.time_until_retry().is_some());
+
+        // A probe that did arrive keeps the breaker half-open.
+        let (mut cb, clock) = half_open(HalfOpenIdle::Reopen { after });
+        let _ = cb.call(|| Ok::<_, ()>(1));
+        clock.advance(after);
+        let _ = cb.call(|| Ok::<_, ()>(1));
+        assert_eq!(cb.state(), State::HalfOpen);
+    }
+
+    #[test]
+    fn test_time_in_state() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(30))
+            .half_open_successes(1)
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        clock.advance(Duration::from_secs(10));
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(30));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+        clock.advance(Duration::from_secs(5));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::Closed);
+        clock.advance(Duration::from_secs(15));
+
+        let time = cb.metrics().time_in_state;
+        assert_eq!(time.closed, Duration::from_secs(25));
+        assert_eq!(time.open, Duration::from_secs(30));
+        assert_eq!(time.half_open, Duration::from_secs(5));
+        assert_eq!(time.degraded, Duration::ZERO);
+        assert_eq!(time.fraction(State::Open), 0.5);
+    }
+
+    #[test]
+    fn test_soft_open_throttles_then_opens() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .half_open_successes(100)
+            .soft_open(25)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.state(), State::SoftOpen);
+
+        let mut ran = 0;
+        for _ in 0..100 {
+            match cb.call(|| Ok::<_, ()>(())) {
+                Ok(Some(())) => ran += 1,
+                Err(CircuitBreakerError::Rejected(RejectionReason::Throttled)) => {}
+                other => panic!("unexpected result {other:?}"),
+            }
+        }
+        assert_eq!(ran, 25);
+        assert_eq!(cb.metrics().rejected, 75);
+        assert_eq!(cb.state(), State::SoftOpen);
+
+        // An admitted call that fails again opens the breaker for real.
+        while cb.state() == State::SoftOpen {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_close_ramp_admits_more_over_the_window() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(5))
+            .half_open_successes(1)
+            .close_ramp(Duration::from_secs(10), 20)
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(5));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::Closed);
+
+        let ran = |cb: &mut CircuitBreaker<FakeClock>| {
+            (0..10)
+                .filter(|_| cb.call(|| Ok::<_, ()>(())).is_ok())
+                .count()
+        };
+        assert_eq!(ran(&mut cb), 2);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(ran(&mut cb), 6);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(ran(&mut cb), 10);
+        assert_eq!(cb.metrics().rejected, 12);
+
+        let err = CircuitBreaker::builder()
+            .close_ramp(Duration::ZERO, 20)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::InvalidCloseRamp);
+    }
+
+    #[test]
+    fn test_clock_going_backwards_is_reported_not_fatal() {
+        use std::sync::Mutex;
+
+        /// A clock that reads from whatever offset it was set to last.
+        #[derive(Clone)]
+        struct Settable(Instant, Arc<Mutex<Duration>>);
+
+        impl Clock for Settable {
+            fn now(&self) -> Instant {
+                self.0 + *self.1.lock().unwrap()
+            }
+        }
+
+        let clock = Settable(Instant::now(), Arc::new(Mutex::new(Duration::ZERO)));
+        let set = |secs| *clock.1.lock().unwrap() = Duration::from_secs(secs);
+        let anomalies = Arc::new(Mutex::new(Vec::new()));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(5))
+            .trip_policy(TripPolicy::Windowed {
+                window: Duration::from_secs(60),
+            })
+            .on_clock_anomaly({
+                let anomalies = Arc::clone(&anomalies);
+                move |by| anomalies.lock().unwrap().push(by)
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        set(100);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        set(90);
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        assert_eq!(cb.state(), State::Open);
+        assert!(anomalies.lock().unwrap().contains(&Duration::from_secs(10)));
+
+        anomalies.lock().unwrap().clear();
+        set(80);
+        assert!(cb.call(|| Ok::<_, ()>(())).is_err());
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().time_in_state.open, Duration::ZERO);
+        assert!(!anomalies.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_soft_open_closes_after_successes() {
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .half_open_successes(2)
+            .soft_open(50)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert!(!cb.would_admit());
+        let results: Vec<_> = (0..4)
+            .map(|_| cb.call(|| Ok::<_, ()>(())).is_ok())
+            .collect();
+        assert_eq!(results, [false, true, false, true]);
+        assert_eq!(cb.state(), State::Closed);
+
+        let err = CircuitBreaker::builder()
+            .soft_open(100)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::InvalidSoftOpenPercent);
+    }
+
+    #[test]
+    fn test_rejection_policies() {
+        let open_breaker = |builder: CircuitBreakerBuilder| {
+            let mut cb = builder
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap();
+            trip(&mut cb);
+            cb
+        };
+
+        let mut cb = open_breaker(CircuitBreaker::builder());
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+
+        let mut cb =
+            open_breaker(CircuitBreaker::builder().rejection_policy(RejectionPolicy::ReturnNone));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(None)));
+        assert_eq!(cb.metrics().rejected, 1);
+
+        let mut cb = open_breaker(CircuitBreaker::builder().default_when_open(0));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(0))));
+
+        let cache = Arc::new(AtomicUsize::new(7));
+        let mut cb = open_breaker(CircuitBreaker::builder().fallback_when_open({
+            let cache = Arc::clone(&cache);
+            move || cache.load(Ordering::SeqCst)
+        }));
+        assert!(matches!(cb.call(|| Ok::<usize, ()>(1)), Ok(Some(7))));
+        cache.store(8, Ordering::SeqCst);
+        assert!(matches!(cb.call(|| Ok::<usize, ()>(1)), Ok(Some(8))));
+
+        // A default can be kept configured but switched off.
+        let mut cb = open_breaker(
+            CircuitBreaker::builder()
+                .default_when_open(0)
+                .rejection_policy(RejectionPolicy::Error),
+        );
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+    }
+
+    #[test]
+    fn test_effective_recovery_time_follows_backoff() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(10))
+            .half_open_successes(1)
+            .exponential_backoff(Duration::from_secs(60))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        assert_eq!(cb.effective_recovery_time(), Duration::from_secs(10));
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        for expected in [10, 20, 40, 60, 60] {
+            assert_eq!(cb.effective_recovery_time(), Duration::from_secs(expected));
+            clock.advance(cb.effective_recovery_time());
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+
+        clock.advance(cb.effective_recovery_time());
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.effective_recovery_time(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_data_reachable_from_hooks() {
+        #[derive(Debug, Default)]
+        struct Stats {
+            opened: u32,
+        }
+
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .data(Stats::default())
+            .on_state_change_with_data(|stats: &mut Stats, t| {
+                if t.to == State::Open {
+                    stats.opened += 1;
+                }
+            })
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        cb.reset();
+        trip(&mut cb);
+        assert_eq!(cb.data().opened, 2);
+        cb.data_mut().opened = 0;
+
+        let shared = crate::SharedCircuitBreaker::new(cb);
+        shared.reset();
+        shared.trip();
+        assert_eq!(shared.with_data(|stats| stats.opened), 1);
+        let _: &() = CircuitBreaker::builder().build().unwrap().data();
+    }
+
+    #[test]
+    fn test_data_hook_set_before_data_still_runs() {
+        let transitions = Arc::new(AtomicUsize::new(0));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .on_state_change_with_data({
+                let transitions = Arc::clone(&transitions);
+                move |_: &mut (), _| {
+                    transitions.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .data(0u32)
+            .build()
+            .unwrap();
+
+        trip(&mut cb);
+        assert_eq!(transitions.load(Ordering::SeqCst), 1);
+        assert_eq!(*cb.data(), 0);
+    }
+
+    #[test]
+    fn test_flapping_detected() {
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let flaps = Arc::new(AtomicUsize::new(0));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(1)
+            .flap_detection(4, Duration::from_secs(60))
+            .on_flap({
+                let flaps = Arc::clone(&flaps);
+                move |recent| {
+                    assert_eq!(recent, 5);
+                    flaps.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        let cycle = |cb: &mut CircuitBreaker<FakeClock>| {
+            while cb.state() != State::Open {
+                let _ = cb.call(|| Err::<(), _>("boom"));
+            }
+            clock.advance(Duration::from_secs(1));
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(cb.state(), State::Closed);
+        };
+
+        cycle(&mut cb);
+        assert!(!cb.is_flapping());
+        cycle(&mut cb);
+        assert!(cb.is_flapping());
+        cycle(&mut cb);
+        assert_eq!(flaps.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::from_secs(120));
+        assert!(!cb.is_flapping());
+    }
+
+    #[test]
+    fn test_on_result_fires_once_per_call() {
+        use std::sync::Mutex;
+
+        use crate::{CallOutcome, CallResult, FakeClock};
+
+        let clock = FakeClock::new();
+        let results = Arc::new(Mutex::new(Vec::<CallResult>::new()));
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .timeout(Duration::from_millis(20))
+            .recovery_time(Duration::from_secs(1))
+            .on_result({
+                let results = Arc::clone(&results);
+                move |result| results.lock().unwrap().push(*result)
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+
+        let _ = cb.call(|| Ok::<_, &str>(()));
+        let _ = cb.call(|| Err::<(), _>("boom"));
+        let _ = cb.call(|| {
+            thread::sleep(Duration::from_millis(60));
+            Ok::<_, &str>(())
+        });
+        let _ = cb.call(|| Ok::<_, &str>(()));
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call(|| Ok::<_, &str>(()));
+
+        let results = results.lock().unwrap();
+        let seen: Vec<_> = results.iter().map(|r| (r.outcome, r.state)).collect();
+        assert_eq!(
+            seen,
+            [
+                (CallOutcome::Success, State::Closed),
+                (CallOutcome::Failure, State::Closed),
+                (CallOutcome::Timeout, State::Open),
+                (CallOutcome::Rejected(RejectionReason::Open), State::Open),
+                (CallOutcome::Skipped, State::HalfOpen),
+            ]
+        );
+        assert!(results[2]
+            .latency
+            .is_some_and(|l| l >= Duration::from_millis(20)));
+        assert!(results[3].latency.is_none());
+    }
+}
@@ -0,0 +1,1199 @@
+use std::any::Any;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::config::validate_config;
+use crate::hooks::{
+    any_key, any_match, any_predicate, any_retry_hint, AnyKey, AnyMatch, AnyPredicate,
+    AnyRetryHint, DataHook, Hook, KeyEq,
+};
+use crate::{
+    BuildError, CallResult, CircuitBreaker, Clock, Config, HalfOpenIdle, InFlightAfterTrip,
+    Priority, RejectionPolicy, Semaphore, State, StateStore, StateTransition, SystemClock,
+    ThreadLimit, TimeoutStrategy, TripPolicy, WorkerPool,
+};
+
+/// Builds a [`CircuitBreaker`] with named settings instead of positional
+/// arguments. Obtained from [`CircuitBreaker::builder`]. `D` is the user
+/// data the breaker will carry, see [`data`](Self::data).
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerBuilder<D = ()> {
+    pub(crate) name: Option<String>,
+    pub(crate) metrics_label: Option<Hook<LabelFn>>,
+    pub(crate) failure_threshold: u32,
+    pub(crate) timeout: Duration,
+    pub(crate) recovery_time: Duration,
+    pub(crate) min_open_duration: Duration,
+    pub(crate) half_open_successes: u64,
+    pub(crate) default_when_open: Option<Hook<DefaultFn>>,
+    pub(crate) cache_last_success: Option<(Duration, Hook<CloneFn>)>,
+    pub(crate) on_half_open: Option<Hook<dyn Fn() + Send + Sync>>,
+    pub(crate) on_open: Option<Hook<dyn Fn() + Send + Sync>>,
+    pub(crate) on_closed: Option<Hook<dyn Fn() + Send + Sync>>,
+    pub(crate) on_state_change: Option<Hook<dyn Fn(StateTransition) + Send + Sync>>,
+    pub(crate) on_shutdown: Option<Hook<dyn Fn(State) + Send + Sync>>,
+    pub(crate) on_result: Option<Hook<ResultHook>>,
+    pub(crate) on_state_change_with_data: Option<Hook<DataHook<D>>>,
+    pub(crate) transition_log: Option<Hook<TransitionLog>>,
+    pub(crate) data: D,
+    pub(crate) flap_detection: Option<(usize, Duration)>,
+    pub(crate) on_flap: Option<Hook<dyn Fn(usize) + Send + Sync>>,
+    pub(crate) on_clock_anomaly: Option<Hook<dyn Fn(Duration) + Send + Sync>>,
+    pub(crate) recovery_failure: Option<(u32, Hook<RecoveryFailure>)>,
+    pub(crate) threshold_warning: Option<(u8, Hook<ThresholdWarning>)>,
+    pub(crate) batch_telemetry: Option<(u64, Duration)>,
+    pub(crate) half_open_max_concurrent: Option<u32>,
+    pub(crate) half_open_min_priority: Priority,
+    pub(crate) lenient_probes: Option<LenientProbes>,
+    pub(crate) concurrency_limits: Vec<(State, u32)>,
+    pub(crate) semaphore: Option<Arc<Semaphore>>,
+    pub(crate) timeout_threshold: Option<u32>,
+    pub(crate) timeout_coalescing: Option<Duration>,
+    pub(crate) half_open_queue: Option<(usize, Duration)>,
+    pub(crate) on_slow_call: Option<Hook<dyn Fn(Duration) + Send + Sync>>,
+    pub(crate) slow_call_threshold: Option<Duration>,
+    pub(crate) max_call_duration: Option<Duration>,
+    pub(crate) transition_guard: Option<Hook<TransitionGuard>>,
+    pub(crate) state_store: Option<Hook<dyn StateStore>>,
+    pub(crate) is_failure: Vec<Hook<AnyMatch>>,
+    pub(crate) is_fatal: Vec<Hook<AnyMatch>>,
+    pub(crate) evaluate: Option<Hook<AnyPredicate>>,
+    pub(crate) retry_after_from_error: Option<Hook<AnyRetryHint>>,
+    pub(crate) dedupe_errors: Option<(Duration, Hook<AnyKey>, KeyEq)>,
+    pub(crate) recent_failures: usize,
+    pub(crate) transition_history: usize,
+    pub(crate) inline_probes: bool,
+    pub(crate) inline_when_closed: bool,
+    pub(crate) count_panics: bool,
+    pub(crate) reset_on_half_open: bool,
+    pub(crate) trip_policy: TripPolicy,
+    pub(crate) worker_pool: Option<WorkerPool>,
+    pub(crate) timeout_strategy: Option<Hook<dyn TimeoutStrategy>>,
+    pub(crate) no_threads: bool,
+    pub(crate) thread_limit: Option<ThreadLimit>,
+    pub(crate) max_probe_attempts: Option<u32>,
+    pub(crate) initial_state: State,
+    pub(crate) last_failure_ago: Option<Duration>,
+    pub(crate) late_results: Option<usize>,
+    pub(crate) warning_threshold: Option<u32>,
+    pub(crate) half_open_timeout_grace: u32,
+    pub(crate) half_open_success_window: Option<Duration>,
+    pub(crate) half_open_idle: HalfOpenIdle,
+    pub(crate) soft_open: Option<u8>,
+    pub(crate) close_ramp: Option<(Duration, u8)>,
+    pub(crate) rejection_policy: RejectionPolicy,
+    pub(crate) in_flight_after_trip: InFlightAfterTrip,
+    pub(crate) backoff_max: Option<Duration>,
+}
+
+pub(crate) type DefaultFn = dyn Fn() -> Box<dyn Any + Send> + Send + Sync;
+pub(crate) type CloneFn = dyn Fn(&dyn Any) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync;
+pub(crate) type TransitionGuard = dyn Fn(State, State) -> bool + Send + Sync;
+pub(crate) type ResultHook = dyn Fn(&CallResult) + Send + Sync;
+pub(crate) type ThresholdWarning = dyn Fn(u32, u32) + Send + Sync;
+pub(crate) type RecoveryFailure = dyn Fn(u32) + Send + Sync;
+pub(crate) type LabelFn = dyn Fn(&str) -> String + Send + Sync;
+pub(crate) type TransitionLog = Mutex<dyn Write + Send>;
+
+/// See [`CircuitBreakerBuilder::lenient_probes`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LenientProbes {
+    pub(crate) probes: u32,
+    pub(crate) timeout: Duration,
+    pub(crate) slow_call_threshold: Duration,
+}
+
+impl Default for CircuitBreakerBuilder {
+    fn default() -> Self {
+        CircuitBreakerBuilder {
+            name: None,
+            metrics_label: None,
+            failure_threshold: 5,
+            timeout: Duration::from_secs(1),
+            recovery_time: Duration::from_secs(30),
+            min_open_duration: Duration::ZERO,
+            half_open_successes: 3,
+            default_when_open: None,
+            cache_last_success: None,
+            on_half_open: None,
+            on_open: None,
+            on_closed: None,
+            on_state_change: None,
+            on_shutdown: None,
+            on_result: None,
+            on_state_change_with_data: None,
+            transition_log: None,
+            data: (),
+            flap_detection: None,
+            on_flap: None,
+            on_clock_anomaly: None,
+            recovery_failure: None,
+            threshold_warning: None,
+            batch_telemetry: None,
+            half_open_max_concurrent: None,
+            half_open_min_priority: Priority::Low,
+            lenient_probes: None,
+            concurrency_limits: Vec::new(),
+            semaphore: None,
+            timeout_threshold: None,
+            timeout_coalescing: None,
+            half_open_queue: None,
+            on_slow_call: None,
+            slow_call_threshold: None,
+            max_call_duration: None,
+            transition_guard: None,
+            state_store: None,
+            is_failure: Vec::new(),
+            is_fatal: Vec::new(),
+            evaluate: None,
+            retry_after_from_error: None,
+            dedupe_errors: None,
+            recent_failures: 10,
+            transition_history: 32,
+            inline_probes: false,
+            inline_when_closed: false,
+            count_panics: false,
+            reset_on_half_open: true,
+            trip_policy: TripPolicy::Consecutive,
+            worker_pool: None,
+            timeout_strategy: None,
+            no_threads: false,
+            thread_limit: None,
+            max_probe_attempts: None,
+            initial_state: State::Closed,
+            last_failure_ago: None,
+            late_results: None,
+            warning_threshold: None,
+            half_open_timeout_grace: 0,
+            half_open_success_window: None,
+            half_open_idle: HalfOpenIdle::Stay,
+            soft_open: None,
+            close_ramp: None,
+            rejection_policy: RejectionPolicy::Error,
+            in_flight_after_trip: InFlightAfterTrip::Count,
+            backoff_max: None,
+        }
+    }
+}
+
+impl CircuitBreakerBuilder {
+    /// Attaches user data, e.g. a logger handle or a channel, that hooks and
+    /// callers can reach through the breaker instead of capturing their own
+    /// copy. The breaker then is a `CircuitBreaker<_, D>`; read the data
+    /// back with [`CircuitBreaker::data`]. No data is attached by default.
+    ///
+    /// An [`on_state_change_with_data`](Self::on_state_change_with_data)
+    /// hook set before this keeps running, without the data.
+    pub fn data<D>(self, data: D) -> CircuitBreakerBuilder<D> {
+        let on_state_change_with_data = self.on_state_change_with_data.map(|hook| {
+            Hook(
+                Arc::new(move |_: &mut D, transition| (hook.0)(&mut (), transition))
+                    as Arc<DataHook<D>>,
+            )
+        });
+        CircuitBreakerBuilder {
+            name: self.name,
+            metrics_label: self.metrics_label,
+            failure_threshold: self.failure_threshold,
+            timeout: self.timeout,
+            recovery_time: self.recovery_time,
+            min_open_duration: self.min_open_duration,
+            half_open_successes: self.half_open_successes,
+            default_when_open: self.default_when_open,
+            cache_last_success: self.cache_last_success,
+            on_half_open: self.on_half_open,
+            on_open: self.on_open,
+            on_closed: self.on_closed,
+            on_state_change: self.on_state_change,
+            on_shutdown: self.on_shutdown,
+            on_result: self.on_result,
+            transition_log: self.transition_log,
+            flap_detection: self.flap_detection,
+            on_flap: self.on_flap,
+            on_clock_anomaly: self.on_clock_anomaly,
+            recovery_failure: self.recovery_failure,
+            threshold_warning: self.threshold_warning,
+            batch_telemetry: self.batch_telemetry,
+            half_open_max_concurrent: self.half_open_max_concurrent,
+            half_open_min_priority: self.half_open_min_priority,
+            lenient_probes: self.lenient_probes,
+            concurrency_limits: self.concurrency_limits,
+            semaphore: self.semaphore,
+            timeout_threshold: self.timeout_threshold,
+            timeout_coalescing: self.timeout_coalescing,
+            half_open_queue: self.half_open_queue,
+            on_slow_call: self.on_slow_call,
+            slow_call_threshold: self.slow_call_threshold,
+            max_call_duration: self.max_call_duration,
+            transition_guard: self.transition_guard,
+            state_store: self.state_store,
+            is_failure: self.is_failure,
+            is_fatal: self.is_fatal,
+            evaluate: self.evaluate,
+            retry_after_from_error: self.retry_after_from_error,
+            dedupe_errors: self.dedupe_errors,
+            recent_failures: self.recent_failures,
+            transition_history: self.transition_history,
+            inline_probes: self.inline_probes,
+            inline_when_closed: self.inline_when_closed,
+            count_panics: self.count_panics,
+            reset_on_half_open: self.reset_on_half_open,
+            trip_policy: self.trip_policy,
+            worker_pool: self.worker_pool,
+            timeout_strategy: self.timeout_strategy,
+            no_threads: self.no_threads,
+            thread_limit: self.thread_limit,
+            max_probe_attempts: self.max_probe_attempts,
+            initial_state: self.initial_state,
+            last_failure_ago: self.last_failure_ago,
+            late_results: self.late_results,
+            warning_threshold: self.warning_threshold,
+            half_open_timeout_grace: self.half_open_timeout_grace,
+            half_open_success_window: self.half_open_success_window,
+            half_open_idle: self.half_open_idle,
+            soft_open: self.soft_open,
+            close_ramp: self.close_ramp,
+            rejection_policy: self.rejection_policy,
+            in_flight_after_trip: self.in_flight_after_trip,
+            backoff_max: self.backoff_max,
+            on_state_change_with_data,
+            data,
+        }
+    }
+}
+
+impl<D> CircuitBreakerBuilder<D> {
+    /// Names the breaker so it can be told apart in logs and metrics.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Maps the name to the `breaker` label telemetry is reported under,
+    /// e.g. [`sanitize_label`](crate::sanitize_label) so that a name like
+    /// `payments/v2` does not fail to register with a backend that only
+    /// accepts some characters. The name is used as is by default.
+    pub fn metrics_label(mut self, label: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        self.metrics_label = Some(Hook(Arc::new(label)));
+        self
+    }
+
+    /// Number of failures the breaker tolerates before opening. Timeouts
+    /// are not counted here, see
+    /// [`timeout_threshold`](Self::timeout_threshold).
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    /// Number of consecutive timeouts the breaker tolerates while closed
+    /// before opening, counted apart from the errors that
+    /// [`failure_threshold`](Self::failure_threshold) limits, since a slow
+    /// dependency and a failing one are different problems. A success
+    /// starts the count again. By default the first timeout opens the
+    /// breaker.
+    pub fn timeout_threshold(mut self, timeouts: u32) -> Self {
+        self.timeout_threshold = Some(timeouts);
+        self
+    }
+
+    /// Counts timeouts within `window` of the last counted one as the same
+    /// timeout, so a burst of calls timing out together on one slow spell
+    /// counts once towards [`timeout_threshold`](Self::timeout_threshold).
+    /// Every timeout still shows up in [`CircuitBreaker::metrics`]. Off by
+    /// default.
+    pub fn timeout_coalescing(mut self, window: Duration) -> Self {
+        self.timeout_coalescing = Some(window);
+        self
+    }
+
+    /// How long to wait for a call to complete. Timings use [`Instant`] at
+    /// full resolution, so sub-millisecond timeouts are honoured, down to
+    /// what the platform's timers can deliver.
+    ///
+    /// `Duration::MAX` disables the timeout. There is then nothing to
+    /// abandon a call for, so every call runs on the calling thread.
+    ///
+    /// [`Instant`]: std::time::Instant
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long the breaker stays open before probing.
+    ///
+    /// Zero is allowed and means the breaker never rejects: the first call
+    /// after it opens moves it straight to half-open. It may also be shorter
+    /// than the [`timeout`](Self::timeout).
+    pub fn recovery_time(mut self, recovery_time: Duration) -> Self {
+        self.recovery_time = recovery_time;
+        self
+    }
+
+    /// The least time the breaker stays open once it opens, however short
+    /// the recovery time or a retry hint is, so a very short
+    /// `recovery_time` cannot make it flap. Zero by default.
+    pub fn min_open_duration(mut self, min_open_duration: Duration) -> Self {
+        self.min_open_duration = min_open_duration;
+        self
+    }
+
+    /// Number of successful probes needed to close from half-open.
+    ///
+    /// Set this to `1` to close on the first successful probe, for
+    /// dependencies that recover quickly.
+    pub fn half_open_successes(mut self, half_open_successes: u64) -> Self {
+        self.half_open_successes = half_open_successes;
+        self
+    }
+
+    /// Value returned as `Ok(Some(value.clone()))` from `call` while the
+    /// breaker is open, instead of an error or `Ok(None)`. Sets the
+    /// [`RejectionPolicy::ReturnDefault`] policy.
+    ///
+    /// The value is cloned for every rejected call, hence the `Clone` bound.
+    /// It only applies to calls whose result type is `R`; calls returning
+    /// any other type are rejected as usual.
+    pub fn default_when_open<R>(self, value: R) -> Self
+    where
+        R: Clone + Send + Sync + 'static,
+    {
+        self.fallback_when_open(move || value.clone())
+    }
+
+    /// Like [`CircuitBreakerBuilder::default_when_open`], but calls
+    /// `fallback` for each call the breaker does not run, e.g. to read a
+    /// cached response.
+    pub fn fallback_when_open<R>(mut self, fallback: impl Fn() -> R + Send + Sync + 'static) -> Self
+    where
+        R: Send + 'static,
+    {
+        self.default_when_open = Some(Hook(Arc::new(move || {
+            Box::new(fallback()) as Box<dyn Any + Send>
+        })));
+        self.rejection_policy = RejectionPolicy::ReturnDefault;
+        self
+    }
+
+    /// Keeps a copy of the last successful result of type `R` and returns
+    /// it as `Ok(Some(value))` from `call` while the breaker is open, as
+    /// long as it is no older than `max_age`, e.g. to keep serving reads
+    /// from a dependency that is down. Without a fresh copy the call is
+    /// rejected, or answered per the [`RejectionPolicy`], as usual.
+    ///
+    /// Only results of type `R` are kept and served; calls returning any
+    /// other type are unaffected.
+    pub fn cache_last_success<R>(mut self, max_age: Duration) -> Self
+    where
+        R: Clone + Send + Sync + 'static,
+    {
+        self.cache_last_success = Some((
+            max_age,
+            Hook(Arc::new(|value: &dyn Any| {
+                let value = value.downcast_ref::<R>()?.clone();
+                Some(Box::new(value) as Box<dyn Any + Send + Sync>)
+            })),
+        ));
+        self
+    }
+
+    /// Chooses what `call` returns for calls the breaker does not run.
+    /// Defaults to [`RejectionPolicy::Error`], or
+    /// [`RejectionPolicy::ReturnDefault`] once a default value is set.
+    pub fn rejection_policy(mut self, policy: RejectionPolicy) -> Self {
+        self.rejection_policy = policy;
+        self
+    }
+
+    /// Chooses whether calls running when the breaker is
+    /// [tripped by hand](CircuitBreaker::trip) still count once they
+    /// finish. Defaults to [`InFlightAfterTrip::Count`].
+    pub fn in_flight_after_trip(mut self, policy: InFlightAfterTrip) -> Self {
+        self.in_flight_after_trip = policy;
+        self
+    }
+
+    /// Called once each time the breaker moves from open to half-open,
+    /// before any probe call runs. Useful for re-establishing connections so
+    /// the first probe does not fail just because they went cold.
+    pub fn on_half_open(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_half_open = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called once each time the breaker opens, from whichever state.
+    pub fn on_open(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_open = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called once each time the breaker closes, whether after successful
+    /// probes, on leaving degraded or on [`CircuitBreaker::reset`].
+    pub fn on_closed(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_closed = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Counts successful calls that take longer than `threshold` as slow,
+    /// in [`Metrics::slow_calls`](crate::Metrics::slow_calls). Slow calls
+    /// still count as successes and do not trip the breaker.
+    pub fn slow_call_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_call_threshold = Some(threshold);
+        self
+    }
+
+    /// Gives the first `probes` calls admitted each time the breaker goes
+    /// half-open `timeout` and `slow_call_threshold` instead of the usual
+    /// ones, so the first probe after a long outage can pay for cold caches
+    /// or reconnecting without reopening the breaker. Later probes, and
+    /// calls in any other state, get the normal ones. A probe counts as one
+    /// of the first if no more than `probes` had been admitted when it
+    /// started, for the timeout, and when it finished, for slowness.
+    pub fn lenient_probes(
+        mut self,
+        probes: u32,
+        timeout: Duration,
+        slow_call_threshold: Duration,
+    ) -> Self {
+        self.lenient_probes = Some(LenientProbes {
+            probes,
+            timeout,
+            slow_call_threshold,
+        });
+        self
+    }
+
+    /// Fails successful calls that took longer than `max` for the caller,
+    /// with [`CircuitBreakerError::DeadlineExceeded`](crate::CircuitBreakerError::DeadlineExceeded),
+    /// e.g. when a late answer is as useless as none. Unlike the timeout,
+    /// the call is waited for; the breaker still records it as the success
+    /// it was, slow if past the
+    /// [`slow_call_threshold`](Self::slow_call_threshold). Off by default.
+    pub fn max_call_duration(mut self, max: Duration) -> Self {
+        self.max_call_duration = Some(max);
+        self
+    }
+
+    /// Called with the measured duration of every slow call, see
+    /// [`CircuitBreakerBuilder::slow_call_threshold`], e.g. to log which
+    /// operation was slow.
+    pub fn on_slow_call(mut self, hook: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.on_slow_call = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called once after every call, whether it ran or was turned away, with
+    /// its outcome, latency and the breaker's resulting state. A catch-all
+    /// for telemetry that would otherwise need several of the more specific
+    /// hooks.
+    ///
+    /// A call replayed by
+    /// [`call_replayable`](CircuitBreaker::call_replayable) is reported
+    /// separately from the call that triggered it.
+    pub fn on_result(mut self, hook: impl Fn(&CallResult) + Send + Sync + 'static) -> Self {
+        self.on_result = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Asked before every transition with the current and the next state;
+    /// returning `false` cancels the transition and the breaker stays as it
+    /// is. For example, a guard can keep the breaker from closing during a
+    /// maintenance window.
+    ///
+    /// A guard that keeps refusing can wedge the breaker open or closed for
+    /// good, so it should only veto while an external condition holds.
+    /// [`CircuitBreaker::reset`] is not subject to the guard.
+    pub fn transition_guard(
+        mut self,
+        guard: impl Fn(State, State) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.transition_guard = Some(Hook(Arc::new(guard)));
+        self
+    }
+
+    /// Lets an external controller override the breaker: the store is read
+    /// before every call, ahead of the breaker's own logic and of
+    /// [`set_enabled`](CircuitBreaker::set_enabled).
+    ///
+    /// [`Override::ForceOpen`](crate::Override::ForceOpen) rejects every
+    /// call and [`Override::ForceClosed`](crate::Override::ForceClosed) runs
+    /// every call without its outcome moving the breaker, like a disabled
+    /// one. Neither changes the state, so on
+    /// [`Override::Auto`](crate::Override::Auto) the breaker carries on from
+    /// where it was.
+    pub fn state_store(mut self, store: impl StateStore + 'static) -> Self {
+        self.state_store = Some(Hook(Arc::new(store)));
+        self
+    }
+
+    /// Called with every state transition, once per transition.
+    ///
+    /// Transitions happen while the breaker is borrowed mutably, or locked
+    /// when it is a [`SharedCircuitBreaker`](crate::SharedCircuitBreaker),
+    /// and a transition to the state the breaker is already in is ignored.
+    /// So when many threads fail at once, the hook still fires exactly once
+    /// for the move to open, and transitions are seen in the order they
+    /// happened. The hook must not call back into the breaker.
+    pub fn on_state_change(
+        mut self,
+        hook: impl Fn(StateTransition) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called once with the final state when the breaker is
+    /// [`shut down`](CircuitBreaker::shutdown), after its workers are
+    /// joined, so subscribers can tell the end of the breaker from a quiet
+    /// spell.
+    pub fn on_shutdown(mut self, hook: impl Fn(State) + Send + Sync + 'static) -> Self {
+        self.on_shutdown = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Like [`CircuitBreakerBuilder::on_state_change`], but the hook also
+    /// gets the breaker's [`data`](CircuitBreakerBuilder::data), and may
+    /// update it.
+    pub fn on_state_change_with_data(
+        mut self,
+        hook: impl Fn(&mut D, StateTransition) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change_with_data = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Writes every transition to `writer` as a line of JSON, e.g. for a
+    /// log-shipping pipeline:
+    ///
+    /// ```text
+    /// {"breaker":"payments","from":"closed","to":"open","at":1760400000000,"reason":"failure_threshold"}
+    /// ```
+    ///
+    /// See [`TransitionRecord`](crate::TransitionRecord) for the fields.
+    /// Lines are written and flushed while the breaker is locked, like the
+    /// [`on_state_change`](Self::on_state_change) hook, so `writer` should
+    /// be quick. Write errors are ignored.
+    pub fn transition_log(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.transition_log = Some(Hook(Arc::new(Mutex::new(writer))));
+        self
+    }
+
+    /// Reports the breaker as flapping, see [`CircuitBreaker::is_flapping`],
+    /// while it makes more than `max_transitions` state transitions within
+    /// `window`. Every transition counts, so one trip and recovery through
+    /// half-open is three. Off by default.
+    pub fn flap_detection(mut self, max_transitions: usize, window: Duration) -> Self {
+        self.flap_detection = Some((max_transitions, window));
+        self
+    }
+
+    /// Called with the number of recent transitions when the breaker starts
+    /// flapping. It fires once per episode: the breaker has to settle below
+    /// the limit before it can fire again. Needs
+    /// [`flap_detection`](CircuitBreakerBuilder::flap_detection).
+    pub fn on_flap(mut self, hook: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_flap = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called with how far the breaker's clock went back whenever it reads
+    /// earlier than a time it read before, e.g. because a custom [`Clock`]
+    /// is not monotonic. The breaker counts such a stretch as no time
+    /// passed rather than panicking, with or without this hook.
+    pub fn on_clock_anomaly(mut self, hook: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.on_clock_anomaly = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Called with the number of consecutive failed recoveries, where a
+    /// probe failed and reopened the breaker, once there have been `after`
+    /// of them and again on every further one, so a human can look at a
+    /// breaker stuck bouncing between open and half-open. Closing resets
+    /// the count, see
+    /// [`CircuitBreaker::failed_recovery_cycles`](crate::CircuitBreaker::failed_recovery_cycles).
+    /// Backoff on the recovery time spaces the calls out.
+    pub fn on_recovery_failure(
+        mut self,
+        after: u32,
+        hook: impl Fn(u32) + Send + Sync + 'static,
+    ) -> Self {
+        self.recovery_failure = Some((after, Hook(Arc::new(hook))));
+        self
+    }
+
+    /// Called with the failure count and the failure threshold once the
+    /// count reaches `percent` of the threshold, so an alert can go out
+    /// before the breaker trips. It fires once per crossing: the count has
+    /// to drop back below that level, or the breaker has to open, before it
+    /// can fire again. `percent` must be between 1 and 100.
+    pub fn on_threshold_warning(
+        mut self,
+        percent: u8,
+        hook: impl Fn(u32, u32) + Send + Sync + 'static,
+    ) -> Self {
+        self.threshold_warning = Some((percent, Hook(Arc::new(hook))));
+        self
+    }
+
+    /// With the `metrics` feature, adds up per-call counts on the breaker
+    /// and reports them with one counter update per outcome once
+    /// `max_pending` calls are held back or `interval` has passed since the
+    /// first of them, instead of updating the recorder on every call.
+    ///
+    /// The counts are held in one buffer per breaker, not per thread: the
+    /// breaker already serializes its calls, so the buffer adds no
+    /// contention, and what it saves is the recorder's own per-call work.
+    /// It does not batch the counters a
+    /// [`SharedCircuitBreaker`](crate::SharedCircuitBreaker) publishes for
+    /// [`metrics_relaxed`](crate::SharedCircuitBreaker::metrics_relaxed),
+    /// which stay exact.
+    ///
+    /// Counts become visible to the recorder up to `interval` late. The
+    /// interval is checked when a call is counted and by a shared breaker's
+    /// [`recovery timer`](crate::SharedCircuitBreaker::spawn_recovery_timer);
+    /// without either, an idle breaker holds on to a partial batch until
+    /// [`flush_telemetry`](CircuitBreaker::flush_telemetry) or drop. State
+    /// and transition metrics are always reported straight away.
+    pub fn batch_telemetry(mut self, max_pending: u64, interval: Duration) -> Self {
+        self.batch_telemetry = Some((max_pending, interval));
+        self
+    }
+
+    /// Decides whether an error returned by the protected function counts
+    /// against the breaker. Errors it returns `false` for are still returned
+    /// to the caller but neither count as failures nor reset the count.
+    ///
+    /// Only errors of type `E` are checked; all other errors count.
+    /// Replaces any predicates given before, see
+    /// [`add_failure_predicate`](Self::add_failure_predicate) to combine
+    /// several.
+    pub fn is_failure<E: 'static>(
+        mut self,
+        predicate: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_failure = vec![any_match(predicate)];
+        self
+    }
+
+    /// Adds a predicate to those of [`is_failure`](Self::is_failure), so
+    /// separate rules, e.g. from different libraries, can each mark errors
+    /// as failures.
+    ///
+    /// An error counts as soon as one predicate for its type returns
+    /// `true`, trying them in the order they were added. It does not count
+    /// if all predicates for its type return `false`, and counts if none
+    /// is for its type.
+    pub fn add_failure_predicate<E: 'static>(
+        mut self,
+        predicate: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_failure.push(any_match(predicate));
+        self
+    }
+
+    /// Marks errors that open the breaker at once, regardless of the failure
+    /// threshold, e.g. revoked credentials. Other errors count towards the
+    /// threshold as usual.
+    ///
+    /// Only errors of type `E` are checked; all other errors are not fatal.
+    /// Replaces any predicates given before, see
+    /// [`add_fatal_predicate`](Self::add_fatal_predicate) to combine several.
+    pub fn is_fatal<E: 'static>(
+        mut self,
+        predicate: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_fatal = vec![any_match(predicate)];
+        self
+    }
+
+    /// Adds a predicate to those of [`is_fatal`](Self::is_fatal). An error
+    /// is fatal as soon as one of them returns `true`, trying them in the
+    /// order they were added; [`is_failure`](Self::is_failure) is only
+    /// asked about errors that are not.
+    pub fn add_fatal_predicate<E: 'static>(
+        mut self,
+        predicate: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_fatal.push(any_match(predicate));
+        self
+    }
+
+    /// Counts a run of identical errors, as told apart by `key`, as one
+    /// failure while they keep coming within `window` of the one that was
+    /// counted, e.g. for a retrying client that surfaces the same
+    /// connection error many times over. Repeats are still returned to the
+    /// caller, like errors [`is_failure`](Self::is_failure) ignores; a
+    /// success or a different error ends the run.
+    ///
+    /// Only applies while closed or degraded, so a failed probe always
+    /// counts. Only errors of type `E` are checked; all others always count.
+    pub fn dedupe_errors<E: 'static, K: Eq + Send + Sync + 'static>(
+        mut self,
+        window: Duration,
+        key: impl Fn(&E) -> K + Send + Sync + 'static,
+    ) -> Self {
+        let (key, eq) = any_key(key);
+        self.dedupe_errors = Some((window, key, eq));
+        self
+    }
+
+    /// Reads a retry hint, such as an HTTP `Retry-After` value, from an
+    /// error returned by the protected function. When the error leaves the
+    /// breaker open and the hint is `Some`, the breaker stays open for that
+    /// long instead of `recovery_time`.
+    ///
+    /// Only errors of type `E` are checked; all other errors give no hint.
+    pub fn retry_after_from_error<E: 'static>(
+        mut self,
+        hint: impl Fn(&E) -> Option<Duration> + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_after_from_error = Some(any_retry_hint(hint));
+        self
+    }
+
+    /// Inspects successful results and returns `true` for those that should
+    /// count as failures, e.g. a response with a 5xx status. The value is
+    /// still returned to the caller either way.
+    ///
+    /// Only results of type `R` are checked; all others count as successes.
+    pub fn evaluate<R: 'static>(
+        mut self,
+        predicate: impl Fn(&R) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.evaluate = Some(any_predicate(predicate, false));
+        self
+    }
+
+    /// How many of the most recent failures to keep for
+    /// [`CircuitBreaker::recent_failures`]. Defaults to 10.
+    pub fn recent_failures(mut self, capacity: usize) -> Self {
+        self.recent_failures = capacity;
+        self
+    }
+
+    /// How many of the most recent state transitions to keep for
+    /// [`CircuitBreaker::transition_history`]. Defaults to 32.
+    pub fn transition_history(mut self, capacity: usize) -> Self {
+        self.transition_history = capacity;
+        self
+    }
+
+    /// Runs half-open probes on the calling thread instead of spawning a
+    /// worker for each one.
+    ///
+    /// The timeout becomes a soft one: a blocking probe cannot be
+    /// interrupted, so it runs to completion and is counted as a timeout
+    /// afterwards if it took longer than `timeout`.
+    pub fn inline_probes(mut self, inline: bool) -> Self {
+        self.inline_probes = inline;
+        self
+    }
+
+    /// Runs calls on the calling thread while the breaker is closed or
+    /// degraded, which is the common, healthy case, instead of handing each
+    /// one to a worker. Saves a thread hand-off per call, at the cost of
+    /// the same soft timeout as [`inline_probes`](Self::inline_probes).
+    pub fn inline_when_closed(mut self, inline: bool) -> Self {
+        self.inline_when_closed = inline;
+        self
+    }
+
+    /// How closed-state failures are counted. Defaults to
+    /// [`TripPolicy::Consecutive`].
+    pub fn trip_policy(mut self, trip_policy: TripPolicy) -> Self {
+        self.trip_policy = trip_policy;
+        self
+    }
+
+    /// Whether the failure count starts from zero when the breaker
+    /// half-opens, which is the default. With `false` the count carries
+    /// over, so a failed probe reopens the breaker with the earlier failures
+    /// still counted and [`Metrics::failure_count`](crate::Metrics::failure_count)
+    /// shows how badly it has been failing overall.
+    pub fn reset_on_half_open(mut self, reset: bool) -> Self {
+        self.reset_on_half_open = reset;
+        self
+    }
+
+    /// Runs calls on `pool` instead of spawning a thread for each one.
+    pub fn worker_pool(mut self, pool: WorkerPool) -> Self {
+        self.worker_pool = Some(pool);
+        self
+    }
+
+    /// Never starts a thread: every call runs on the calling thread, for
+    /// environments where threads cannot be spawned, such as WASM. This
+    /// disables the timeout, since nothing could abandon a call that
+    /// overran it, so building fails with
+    /// [`BuildError::TimeoutWithoutThreads`] if a timeout is set afterwards,
+    /// here or through [`reconfigure`](CircuitBreaker::reconfigure).
+    pub fn no_threads(mut self) -> Self {
+        self.no_threads = true;
+        self.timeout = Duration::MAX;
+        self
+    }
+
+    /// Runs calls and enforces their timeout with `strategy` instead of on
+    /// the breaker's own worker threads, which then leaves no work for a
+    /// [`worker_pool`](Self::worker_pool) or [`thread_limit`](Self::thread_limit).
+    /// Calls that run inline are not affected.
+    pub fn timeout_strategy(mut self, strategy: impl TimeoutStrategy + 'static) -> Self {
+        self.timeout_strategy = Some(Hook(Arc::new(strategy)));
+        self
+    }
+
+    /// Spawns a thread for a call only while `limit` has room, rejecting
+    /// the call otherwise, see [`ThreadLimit`]. Has no effect with a
+    /// [`worker_pool`](Self::worker_pool), whose threads are fixed, or on
+    /// calls run inline.
+    pub fn thread_limit(mut self, limit: ThreadLimit) -> Self {
+        self.thread_limit = Some(limit);
+        self
+    }
+
+    /// Stops probing after `attempts` consecutive half-open cycles have
+    /// failed. The breaker then stays open until
+    /// [`CircuitBreaker::reset`] is called, instead of cycling between open
+    /// and half-open forever. Unlimited by default.
+    pub fn max_probe_attempts(mut self, attempts: u32) -> Self {
+        self.max_probe_attempts = Some(attempts);
+        self
+    }
+
+    /// The state the breaker starts in. Closed by default.
+    ///
+    /// Starting [`State::Open`] gives a cold start: calls are rejected until
+    /// the recovery time has passed since the breaker was built, and traffic
+    /// is only admitted once half-open probes succeed.
+    pub fn initial_state(mut self, state: State) -> Self {
+        self.initial_state = state;
+        self
+    }
+
+    /// How long ago the breaker last failed, for restoring a breaker that
+    /// was open before a restart. With an open
+    /// [`initial_state`](Self::initial_state) the recovery time is counted
+    /// from then rather than from when the breaker was built, so a breaker
+    /// 10s into a 30s recovery time starts with 20s to go.
+    pub fn last_failure_ago(mut self, ago: Duration) -> Self {
+        self.last_failure_ago = Some(ago);
+        self
+    }
+
+    /// Like [`last_failure_ago`](Self::last_failure_ago), from the wall-clock
+    /// time of the failure, e.g. as persisted before a restart. A time in
+    /// the future counts as now.
+    pub fn last_failure_at(self, at: SystemTime) -> Self {
+        let ago = SystemTime::now().duration_since(at).unwrap_or_default();
+        self.last_failure_ago(ago)
+    }
+
+    /// Counts a panic in the protected function as a failure and keeps its
+    /// message in [`CircuitBreaker::recent_failures`]. The call still returns
+    /// [`CircuitBreakerError::Internal`](crate::CircuitBreakerError::Internal).
+    /// Off by default, in which case a panic is reported the same way but
+    /// not counted.
+    pub fn count_panics(mut self, enabled: bool) -> Self {
+        self.count_panics = enabled;
+        self
+    }
+
+    /// Keeps up to `capacity` results of calls that timed out but whose
+    /// worker finished later, oldest dropped first, for
+    /// [`CircuitBreaker::drain_late_results`]. Seeing that timed-out calls
+    /// would have succeeded shortly after helps tune the timeout. Off by
+    /// default.
+    pub fn late_results(mut self, capacity: usize) -> Self {
+        self.late_results = Some(capacity);
+        self
+    }
+
+    /// Doubles the recovery time after each failed half-open probe cycle, up
+    /// to `max`, so a dependency that keeps failing is probed less and less
+    /// often. The doubling saturates at `max` instead of overflowing, however
+    /// long the breaker keeps failing. A closed breaker starts again from
+    /// `recovery_time`.
+    pub fn exponential_backoff(mut self, max: Duration) -> Self {
+        self.backoff_max = Some(max);
+        self
+    }
+
+    /// Lets up to `timeouts` probe calls time out while half-open before the
+    /// breaker reopens, since a recovering service is often slow at first.
+    /// A successful probe starts the count again. Errors are not covered
+    /// and still reopen the breaker at once. Zero by default.
+    pub fn half_open_timeout_grace(mut self, timeouts: u32) -> Self {
+        self.half_open_timeout_grace = timeouts;
+        self
+    }
+
+    /// Counts only probe successes from the last `window` towards
+    /// [`half_open_successes`](Self::half_open_successes), like
+    /// [`TripPolicy::Windowed`] does for closed-state failures, so probes
+    /// that trickle in over a long half-open spell do not close the breaker
+    /// on their own. Applies to soft-open as well. Off by default.
+    pub fn half_open_success_window(mut self, window: Duration) -> Self {
+        self.half_open_success_window = Some(window);
+        self
+    }
+
+    /// What a half-open breaker does when probes stop arriving, e.g. in a
+    /// quiet period. Defaults to [`HalfOpenIdle::Stay`].
+    pub fn half_open_idle(mut self, policy: HalfOpenIdle) -> Self {
+        self.half_open_idle = policy;
+        self
+    }
+
+    /// Runs at most `probes` half-open probes at once, rejecting further
+    /// calls with [`RejectionReason::ProbesBusy`](crate::RejectionReason::ProbesBusy)
+    /// until one finishes, so a recovering dependency is not hit by a whole
+    /// burst of callers. Only matters for a [`SharedCircuitBreaker`](crate::SharedCircuitBreaker)
+    /// or [`Permit`](crate::Permit)s. Unlimited by default.
+    pub fn half_open_max_concurrent(mut self, probes: u32) -> Self {
+        self.half_open_max_concurrent = Some(probes);
+        self
+    }
+
+    /// Uses only calls of at least `priority` as half-open probes,
+    /// rejecting others with
+    /// [`RejectionReason::LowPriority`](crate::RejectionReason::LowPriority)
+    /// while the breaker recovers, so the few probe slots go to important
+    /// traffic. With [`Priority::High`], plain calls are turned away too;
+    /// see [`CircuitBreaker::call_with_priority`]. Every call may probe by
+    /// default.
+    pub fn half_open_min_priority(mut self, priority: Priority) -> Self {
+        self.half_open_min_priority = priority;
+        self
+    }
+
+    /// Runs at most `calls` calls at once that were admitted while the
+    /// breaker was in `state`, rejecting further calls in that state with
+    /// [`RejectionReason::ConcurrencyLimit`](crate::RejectionReason::ConcurrencyLimit),
+    /// e.g. to stay generous while closed but cautious while soft-open.
+    /// Closed and degraded are limited separately. For half-open this is
+    /// [`half_open_max_concurrent`](Self::half_open_max_concurrent), and an
+    /// open breaker runs nothing anyway. Like that limit, it only matters
+    /// for calls that can overlap. Unlimited by default.
+    pub fn max_concurrent(mut self, state: State, calls: u32) -> Self {
+        if state == State::HalfOpen {
+            return self.half_open_max_concurrent(calls);
+        }
+        self.concurrency_limits.retain(|&(s, _)| s != state);
+        self.concurrency_limits.push((state, calls));
+        self
+    }
+
+    /// Takes a permit from `semaphore` for every call that runs, rejecting
+    /// calls with
+    /// [`RejectionReason::SemaphoreExhausted`](crate::RejectionReason::SemaphoreExhausted)
+    /// while none is left. Giving several breakers the same semaphore
+    /// bounds their calls together, see [`Semaphore`].
+    pub fn semaphore(mut self, semaphore: Arc<Semaphore>) -> Self {
+        self.semaphore = Some(semaphore);
+        self
+    }
+
+    /// Lets up to `capacity` blocking calls on a [`SharedCircuitBreaker`](crate::SharedCircuitBreaker)
+    /// wait up to `max_wait` for a probe slot once
+    /// [`half_open_max_concurrent`](Self::half_open_max_concurrent) is
+    /// reached, instead of being rejected at once. Calls beyond the queue,
+    /// and calls still waiting when `max_wait` runs out, are rejected.
+    ///
+    /// Async calls never wait, since that would block the runtime.
+    pub fn half_open_queue(mut self, capacity: usize, max_wait: Duration) -> Self {
+        self.half_open_queue = Some((capacity, max_wait));
+        self
+    }
+
+    /// Instead of opening when failures exceed the threshold, moves to
+    /// [`State::SoftOpen`] and admits `percent` of calls, rejecting the rest
+    /// with [`RejectionReason::Throttled`](crate::RejectionReason::Throttled).
+    /// A failure while soft-open opens the breaker for real, and
+    /// `half_open_successes` successes in a row close it again, so a false
+    /// trip costs most, not all, of the traffic. Timeouts and fatal errors
+    /// still open the breaker at once.
+    ///
+    /// `percent` must be between 1 and 99.
+    pub fn soft_open(mut self, percent: u8) -> Self {
+        self.soft_open = Some(percent);
+        self
+    }
+
+    /// After closing from half-open, admits only `from_percent` of calls at
+    /// first, rising evenly to all of them over `window`, so a dependency
+    /// that just recovered is not hit with full traffic at once. The rest are
+    /// rejected with [`RejectionReason::Throttled`](crate::RejectionReason::Throttled).
+    /// Failures count as usual throughout and can trip the breaker again.
+    ///
+    /// `from_percent` must be between 1 and 99, and `window` non-zero.
+    pub fn close_ramp(mut self, window: Duration, from_percent: u8) -> Self {
+        self.close_ramp = Some((window, from_percent));
+        self
+    }
+
+    /// Moves a closed breaker to [`State::Degraded`] once its failure count
+    /// exceeds `threshold`, and back to closed when the count drops to
+    /// `threshold` or below. Degraded is informational only: calls are
+    /// admitted as when closed. Must be below `failure_threshold`.
+    pub fn warning_threshold(mut self, threshold: u32) -> Self {
+        self.warning_threshold = Some(threshold);
+        self
+    }
+
+    /// Validates the configuration and builds the breaker.
+    pub fn build(self) -> Result<CircuitBreaker<SystemClock, D>, BuildError> {
+        self.build_with_clock(SystemClock)
+    }
+
+    /// Like [`CircuitBreakerBuilder::build`], but the breaker reads the time
+    /// from `clock`. Tests can pass a
+    /// [`FakeClock`](crate::FakeClock) (with the `test-util` feature) to
+    /// step through the recovery time without sleeping.
+    pub fn build_with_clock<C: Clock>(self, clock: C) -> Result<CircuitBreaker<C, D>, BuildError> {
+        let config = Config {
+            failure_threshold: self.failure_threshold,
+            timeout: self.timeout,
+            recovery_time: self.recovery_time,
+            half_open_successes: self.half_open_successes,
+        };
+        validate_config(&config, self.no_threads, self.warning_threshold)?;
+        if let TripPolicy::Windowed { window }
+        | TripPolicy::ConsecutiveOrWindowed { window, .. }
+        | TripPolicy::Decaying { half_life: window } = self.trip_policy
+        {
+            if window.is_zero() {
+                return Err(BuildError::ZeroFailureWindow);
+            }
+        }
+        if self.half_open_success_window == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroSuccessWindow);
+        }
+        if self
+            .soft_open
+            .is_some_and(|percent| !(1..100).contains(&percent))
+        {
+            return Err(BuildError::InvalidSoftOpenPercent);
+        }
+        if self
+            .close_ramp
+            .is_some_and(|(window, percent)| window.is_zero() || !(1..100).contains(&percent))
+        {
+            return Err(BuildError::InvalidCloseRamp);
+        }
+        if self
+            .threshold_warning
+            .as_ref()
+            .is_some_and(|(percent, _)| !(1..=100).contains(percent))
+        {
+            return Err(BuildError::InvalidWarningPercent);
+        }
+        if self.half_open_max_concurrent == Some(0) {
+            return Err(BuildError::ZeroHalfOpenConcurrency);
+        }
+        if self.concurrency_limits.iter().any(|&(_, calls)| calls == 0) {
+            return Err(BuildError::ZeroConcurrencyLimit);
+        }
+        Ok(CircuitBreaker::from_builder(self, clock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_rejects_zero_failure_threshold() {
+        let err = CircuitBreaker::builder()
+            .failure_threshold(0)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroFailureThreshold);
+        assert!(err.to_string().contains("failure_threshold"));
+    }
+
+    #[test]
+    fn test_no_threads_runs_inline_and_rejects_a_timeout() {
+        let mut cb = CircuitBreaker::builder().no_threads().build().unwrap();
+        let caller = std::thread::current().id();
+        let ran_on = cb.call(|| Ok::<_, ()>(std::thread::current().id()));
+        assert!(matches!(ran_on, Ok(Some(id)) if id == caller));
+
+        let err = CircuitBreaker::builder()
+            .no_threads()
+            .timeout(Duration::from_secs(1))
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::TimeoutWithoutThreads);
+        let mut config = cb.config();
+        config.timeout = Duration::from_secs(1);
+        assert_eq!(
+            cb.reconfigure(config),
+            Err(BuildError::TimeoutWithoutThreads)
+        );
+    }
+
+    #[test]
+    fn test_build_rejects_zero_half_open_successes() {
+        let err = CircuitBreaker::builder()
+            .half_open_successes(0)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroHalfOpenSuccesses);
+        assert!(err.to_string().contains("half_open_successes"));
+    }
+
+    #[test]
+    fn test_build_rejects_zero_timeout() {
+        let err = CircuitBreaker::builder()
+            .timeout(Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroTimeout);
+        assert!(err.to_string().contains("timeout"));
+    }
+
+    #[test]
+    fn test_build_rejects_zero_failure_window() {
+        let err = CircuitBreaker::builder()
+            .trip_policy(TripPolicy::Windowed {
+                window: Duration::ZERO,
+            })
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroFailureWindow);
+
+        let err = CircuitBreaker::builder()
+            .half_open_success_window(Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroSuccessWindow);
+    }
+
+    #[test]
+    fn test_build_rejects_warning_threshold_at_failure_threshold() {
+        let err = CircuitBreaker::builder()
+            .failure_threshold(3)
+            .warning_threshold(3)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::WarningThresholdTooHigh);
+    }
+
+    #[test]
+    fn test_build_accepts_defaults() {
+        assert!(CircuitBreaker::builder().build().is_ok());
+    }
+
+    #[test]
+    fn test_build_accepts_zero_recovery_and_timeout_past_it() {
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_secs(5))
+            .recovery_time(Duration::ZERO)
+            .build()
+            .unwrap();
+        let mut config = cb.config();
+        config.recovery_time = Duration::from_secs(1);
+        assert_eq!(cb.reconfigure(config), Ok(()));
+    }
+}
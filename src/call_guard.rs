@@ -0,0 +1,186 @@
+use std::convert::Infallible;
+use std::time::Instant;
+use std::{fmt, mem, thread};
+
+use crate::breaker::{Admission, InFlightGuard, Outcome, Runner};
+use crate::{CircuitBreakerError, Clock, SharedCircuitBreaker, State, SystemClock};
+
+impl<C: Clock, D> SharedCircuitBreaker<C, D> {
+    /// Starts a call that the caller runs itself, for code that cannot be
+    /// put in a closure, e.g. because it holds a resource across several
+    /// statements.
+    ///
+    /// Returns `None` if a `call` made now would not run, counting the
+    /// rejection as `call` would. Otherwise the call is in flight until the
+    /// returned [`CallGuard`] is marked with how it went, or dropped.
+    pub fn start_call(&self) -> Option<CallGuard<C, D>> {
+        let mut breaker = self.lock();
+        match breaker.admit::<(), ()>() {
+            Ok(Admission::Run {
+                slots, admitted_in, ..
+            }) => {
+                let runner = breaker.runner();
+                Some(CallGuard {
+                    breaker: Some(self.clone()),
+                    in_flight: Some(runner.enter()),
+                    runner,
+                    slots,
+                    admitted_in,
+                    started: Instant::now(),
+                })
+            }
+            Ok(Admission::Skip(_)) | Err(_) => None,
+        }
+    }
+}
+
+/// The error recorded for a [`CallGuard`] dropped without an outcome.
+#[derive(Debug)]
+struct Unmarked;
+
+/// A call started with [`SharedCircuitBreaker::start_call`] and run by the
+/// caller on its own thread.
+///
+/// Mark it with [`success`](Self::success) or [`failure`](Self::failure)
+/// once the call is done. It then counts as an inline call would: one that
+/// took longer than the timeout counts as timed out. Dropping the guard
+/// without marking it counts as a failure, or as a panic if the thread is
+/// unwinding; [`cancel`](Self::cancel) hands the admission back instead.
+#[must_use = "dropping a call guard records the call as failed"]
+pub struct CallGuard<C: Clock = SystemClock, D = ()> {
+    /// Taken once the guard is marked, so `drop` knows not to record it.
+    breaker: Option<SharedCircuitBreaker<C, D>>,
+    in_flight: Option<InFlightGuard>,
+    runner: Runner,
+    slots: Vec<InFlightGuard>,
+    admitted_in: Option<State>,
+    started: Instant,
+}
+
+impl<C: Clock, D> CallGuard<C, D> {
+    /// Records the call as succeeded, returning what `call` would have.
+    pub fn success(mut self) -> Result<(), CircuitBreakerError<Infallible>> {
+        self.finish(Ok(()))
+    }
+
+    /// Records the call as failed with `error`, which the breaker's
+    /// predicates see as they would a `call`'s error, and hands it back.
+    pub fn failure<E: 'static>(mut self, error: E) -> CircuitBreakerError<E> {
+        match self.finish(Err(error)) {
+            Ok(()) => unreachable!("a failed call never completes as a success"),
+            Err(e) => e,
+        }
+    }
+
+    /// Hands the admission back without recording anything, as if the call
+    /// had never been started.
+    pub fn cancel(mut self) {
+        if let Some(breaker) = self.breaker.take() {
+            self.in_flight = None;
+            breaker.lock().release_admission(self.admitted_in);
+            breaker.release_slots(mem::take(&mut self.slots));
+        }
+    }
+
+    fn finish<E: 'static>(&mut self, res: Result<(), E>) -> Result<(), CircuitBreakerError<E>> {
+        let outcome = match res {
+            _ if self.started.elapsed() > self.runner.timeout() => Outcome::Timeout,
+            Ok(()) => Outcome::Ok(()),
+            Err(e) => Outcome::Err(e),
+        };
+        self.record(outcome)
+    }
+
+    fn record<E: 'static>(
+        &mut self,
+        outcome: Outcome<(), E>,
+    ) -> Result<(), CircuitBreakerError<E>> {
+        let breaker = self.breaker.take().expect("call guard marked twice");
+        self.in_flight = None;
+        let res = breaker
+            .lock()
+            .complete(outcome, self.started.elapsed(), self.admitted_in, None);
+        breaker.release_slots(mem::take(&mut self.slots));
+        res
+    }
+}
+
+impl<C: Clock, D> Drop for CallGuard<C, D> {
+    fn drop(&mut self) {
+        if self.breaker.is_none() {
+            return;
+        }
+        if thread::panicking() {
+            let _ = self.record(Outcome::<(), Unmarked>::Panic(None));
+        } else {
+            let _ = self.finish(Err(Unmarked));
+        }
+    }
+}
+
+impl<C: Clock, D> fmt::Debug for CallGuard<C, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallGuard")
+            .field("admitted_in", &self.admitted_in)
+            .field("elapsed", &self.started.elapsed())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::CircuitBreaker;
+
+    fn breaker() -> SharedCircuitBreaker {
+        SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_marked_success() {
+        let cb = breaker();
+        let guard = cb.start_call().expect("closed breaker admits");
+        assert_eq!(cb.in_flight(), 1);
+        assert!(guard.success().is_ok());
+        let metrics = cb.metrics();
+        assert_eq!((metrics.admitted, metrics.successes), (1, 1));
+        assert_eq!(cb.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_marked_failure_returns_the_error() {
+        let cb = breaker();
+        for _ in 0..2 {
+            let guard = cb.start_call().unwrap();
+            assert!(matches!(
+                guard.failure("boom"),
+                CircuitBreakerError::FunctionError("boom")
+            ));
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(cb.metrics().failures, 2);
+        assert!(cb.start_call().is_none());
+        assert_eq!(cb.metrics().rejected, 1);
+    }
+
+    #[test]
+    fn test_unmarked_drop_counts_as_failure() {
+        let cb = breaker();
+        drop(cb.start_call().unwrap());
+        assert_eq!(cb.metrics().failures, 1);
+
+        cb.start_call().unwrap().cancel();
+        let metrics = cb.metrics();
+        assert_eq!((metrics.admitted, metrics.failures), (1, 1));
+        assert_eq!(cb.state(), State::Closed);
+        assert_eq!(cb.in_flight(), 0);
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(any(test, feature = "test-util"))]
+use std::time::Duration;
+use std::time::Instant;
+
+/// Where a [`CircuitBreaker`](crate::CircuitBreaker) reads the time when it
+/// decides whether the recovery time has passed and which failures are
+/// still inside a trip window.
+///
+/// The breaker is generic over its clock rather than boxing it, so the
+/// default [`SystemClock`] costs nothing over calling `Instant::now`. Call
+/// timeouts and latencies always use real time, since they wait on real
+/// work.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+/// The real clock, used unless another one is given to
+/// [`CircuitBreakerBuilder::build_with_clock`](crate::CircuitBreakerBuilder::build_with_clock).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, so tests can step through the
+/// recovery time without sleeping. Clones share the same time.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    start: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl FakeClock {
+    pub fn new() -> Self {
+        FakeClock {
+            start: Instant::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock, and every clone of it, forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.offset.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for FakeClock {
+    fn default() -> Self {
+        FakeClock::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + *self.offset.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
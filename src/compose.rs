@@ -0,0 +1,107 @@
+use crate::{CircuitBreaker, Clock, SharedCircuitBreaker};
+
+/// Something that can say whether a call made now would be admitted, such
+/// as a breaker or a combination of breakers.
+///
+/// Like [`CircuitBreaker::would_admit`], the answer can be stale by the
+/// time the call runs.
+pub trait Admit {
+    fn would_admit(&self) -> bool;
+}
+
+impl<C: Clock, D> Admit for CircuitBreaker<C, D> {
+    fn would_admit(&self) -> bool {
+        CircuitBreaker::would_admit(self)
+    }
+}
+
+impl<C: Clock, D> Admit for SharedCircuitBreaker<C, D> {
+    fn would_admit(&self) -> bool {
+        SharedCircuitBreaker::would_admit(self)
+    }
+}
+
+impl<T: Admit + ?Sized> Admit for &T {
+    fn would_admit(&self) -> bool {
+        (**self).would_admit()
+    }
+}
+
+/// Admits a call only if every breaker would, for a call that needs all of
+/// its downstreams. An empty `All` admits everything.
+#[derive(Debug, Clone)]
+pub struct All<T>(pub Vec<T>);
+
+impl<T: Admit> Admit for All<T> {
+    fn would_admit(&self) -> bool {
+        self.0.iter().all(Admit::would_admit)
+    }
+}
+
+/// Admits a call if any breaker would, for a call that can go to whichever
+/// downstream is healthy. An empty `Any` admits nothing.
+#[derive(Debug, Clone)]
+pub struct Any<T>(pub Vec<T>);
+
+impl<T: Admit> Any<T> {
+    /// The first breaker that would admit a call, to route the call to.
+    pub fn first_admitting(&self) -> Option<&T> {
+        self.0.iter().find(|breaker| breaker.would_admit())
+    }
+}
+
+impl<T: Admit> Admit for Any<T> {
+    fn would_admit(&self) -> bool {
+        self.first_admitting().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .build()
+            .unwrap()
+    }
+
+    fn open_breaker() -> CircuitBreaker {
+        let mut cb = breaker();
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        cb
+    }
+
+    #[test]
+    fn test_all_rejects_if_either_is_open() {
+        let (closed, open) = (breaker(), open_breaker());
+        assert!(All(vec![&closed, &breaker()]).would_admit());
+        assert!(!All(vec![&closed, &open]).would_admit());
+        assert!(!All(vec![&open, &closed]).would_admit());
+        assert!(All(Vec::<&CircuitBreaker>::new()).would_admit());
+    }
+
+    #[test]
+    fn test_any_admits_if_either_is_closed() {
+        let (closed, open) = (
+            SharedCircuitBreaker::new(breaker()),
+            SharedCircuitBreaker::new(open_breaker()),
+        );
+        let any = Any(vec![open.clone(), closed.clone()]);
+        assert!(any.would_admit());
+        let routed = any.first_admitting().unwrap();
+        assert!(matches!(routed.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(closed.metrics().successes, 1);
+
+        assert!(!Any(vec![open.clone(), open]).would_admit());
+
+        // Combinators nest.
+        assert!(All(vec![Any(vec![closed.clone()]), Any(vec![closed])]).would_admit());
+    }
+}
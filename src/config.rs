@@ -0,0 +1,133 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::BuildError;
+
+/// The settings of a breaker that can be changed while it runs, see
+/// [`CircuitBreaker::reconfigure`](crate::CircuitBreaker::reconfigure).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    pub failure_threshold: u32,
+    pub timeout: Duration,
+    pub recovery_time: Duration,
+    pub half_open_successes: u64,
+}
+
+impl Config {
+    /// The settings that differ between `self` and `other`, in field order,
+    /// e.g. to spot drift between the same breaker in two environments.
+    pub fn diff(&self, other: &Config) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+        let mut compare = |field, this: &dyn fmt::Debug, that: &dyn fmt::Debug| {
+            let (this, other) = (format!("{this:?}"), format!("{that:?}"));
+            if this != other {
+                diffs.push(FieldDiff { field, this, other });
+            }
+        };
+        compare(
+            "failure_threshold",
+            &self.failure_threshold,
+            &other.failure_threshold,
+        );
+        compare("timeout", &self.timeout, &other.timeout);
+        compare("recovery_time", &self.recovery_time, &other.recovery_time);
+        compare(
+            "half_open_successes",
+            &self.half_open_successes,
+            &other.half_open_successes,
+        );
+        diffs
+    }
+}
+
+/// The checks the builder and [`CircuitBreaker::reconfigure`](crate::CircuitBreaker::reconfigure)
+/// both run on these settings, given the builder's `no_threads` and
+/// `warning_threshold`, which `reconfigure` cannot change.
+pub(crate) fn validate_config(
+    config: &Config,
+    no_threads: bool,
+    warning_threshold: Option<u32>,
+) -> Result<(), BuildError> {
+    if config.failure_threshold == 0 {
+        return Err(BuildError::ZeroFailureThreshold);
+    }
+    if config.half_open_successes == 0 {
+        return Err(BuildError::ZeroHalfOpenSuccesses);
+    }
+    if config.timeout.is_zero() {
+        return Err(BuildError::ZeroTimeout);
+    }
+    if no_threads && config.timeout != Duration::MAX {
+        return Err(BuildError::TimeoutWithoutThreads);
+    }
+    if warning_threshold.is_some_and(|warning| warning >= config.failure_threshold) {
+        return Err(BuildError::WarningThresholdTooHigh);
+    }
+    Ok(())
+}
+
+/// A setting that differs between two [`Config`]s, see [`Config::diff`],
+/// with both values formatted for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub this: String,
+    pub other: String,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} != {}", self.field, self.this, self.other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staging() -> Config {
+        Config {
+            failure_threshold: 5,
+            timeout: Duration::from_secs(2),
+            recovery_time: Duration::from_secs(30),
+            half_open_successes: 3,
+        }
+    }
+
+    #[test]
+    fn test_diff_lists_differing_fields() {
+        let prod = Config {
+            failure_threshold: 10,
+            recovery_time: Duration::from_millis(1500),
+            ..staging()
+        };
+
+        assert!(staging().diff(&staging()).is_empty());
+        let diffs = staging().diff(&prod);
+        assert_eq!(
+            diffs,
+            [
+                FieldDiff {
+                    field: "failure_threshold",
+                    this: "5".to_string(),
+                    other: "10".to_string(),
+                },
+                FieldDiff {
+                    field: "recovery_time",
+                    this: "30s".to_string(),
+                    other: "1.5s".to_string(),
+                },
+            ]
+        );
+        assert_eq!(diffs[1].to_string(), "recovery_time: 30s != 1.5s");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config_round_trips_through_serde() {
+        let json = serde_json::to_string(&staging()).unwrap();
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), staging());
+    }
+}
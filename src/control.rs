@@ -0,0 +1,31 @@
+/// A command from an external controller, read by the breaker before every
+/// call, see [`StateStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Override {
+    /// No override: the breaker follows its own logic.
+    #[default]
+    Auto,
+    /// Reject every call with
+    /// [`RejectionReason::ForcedOpen`](crate::RejectionReason::ForcedOpen).
+    ForceOpen,
+    /// Run every call, as if the breaker were disabled.
+    ForceClosed,
+}
+
+/// Where a breaker reads overrides pushed by an external controller, such
+/// as a remote control API or a feature-flag service, see
+/// [`state_store`](crate::CircuitBreakerBuilder::state_store).
+///
+/// It is read on every call, so it should answer from memory, e.g. from a
+/// value a background task keeps up to date, rather than go to the network.
+pub trait StateStore: Send + Sync {
+    /// The override for the breaker called `name`, or for an unnamed
+    /// breaker if `None`.
+    fn read_override(&self, name: Option<&str>) -> Override;
+}
+
+impl<F: Fn(Option<&str>) -> Override + Send + Sync> StateStore for F {
+    fn read_override(&self, name: Option<&str>) -> Override {
+        self(name)
+    }
+}
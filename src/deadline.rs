@@ -0,0 +1,135 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The deadline of a call made with
+/// [`CircuitBreaker::call_with_deadline`](crate::CircuitBreaker::call_with_deadline).
+///
+/// The call runs on the caller's thread and nothing interrupts it: the
+/// closure has to poll [`DeadlineGuard::expired`] at convenient points and
+/// give up once it returns `true`. A call that ignores its deadline runs to
+/// completion and is only counted as timed out afterwards.
+///
+/// `expired` reads a flag that one shared timer thread sets when the
+/// deadline passes, so polling it in a tight loop is cheap.
+#[derive(Debug)]
+pub struct DeadlineGuard {
+    deadline: Option<Instant>,
+    expired: Arc<AtomicBool>,
+}
+
+impl DeadlineGuard {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        let expired = Arc::new(AtomicBool::new(false));
+        let deadline = Instant::now().checked_add(timeout);
+        // A deadline too far off to represent never expires.
+        if let Some(at) = deadline {
+            // Only fails if the timer thread is gone, in which case the
+            // deadline is still enforced once the call returns.
+            let _ = timer().send(Entry {
+                at,
+                expired: Arc::clone(&expired),
+            });
+        }
+        DeadlineGuard { deadline, expired }
+    }
+
+    /// Whether the deadline has passed and the call should stop.
+    pub fn expired(&self) -> bool {
+        self.expired.load(Ordering::Acquire)
+    }
+
+    /// Time left until the deadline.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.map_or(Duration::MAX, |at| {
+            at.saturating_duration_since(Instant::now())
+        })
+    }
+}
+
+struct Entry {
+    at: Instant,
+    expired: Arc<AtomicBool>,
+}
+
+// Ordered so the `BinaryHeap` pops the earliest deadline first.
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.at.cmp(&self.at)
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at
+    }
+}
+
+impl Eq for Entry {}
+
+/// The timer thread's queue, started on first use and shared by every
+/// breaker.
+fn timer() -> &'static mpsc::Sender<Entry> {
+    static TIMER: OnceLock<mpsc::Sender<Entry>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("cb-timer".to_string())
+            .spawn(move || run_timer(rx))
+            .expect("failed to spawn deadline timer thread");
+        tx
+    })
+}
+
+fn run_timer(rx: mpsc::Receiver<Entry>) {
+    let mut pending = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while pending.peek().is_some_and(|e: &Entry| e.at <= now) {
+            if let Some(entry) = pending.pop() {
+                entry.expired.store(true, Ordering::Release);
+            }
+        }
+        let next = match pending.peek() {
+            Some(entry) => rx.recv_timeout(entry.at - now),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok(entry) => pending.push(entry),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_guard_expires() {
+        let later = DeadlineGuard::new(Duration::from_secs(60));
+        let soon = DeadlineGuard::new(Duration::from_millis(20));
+        assert!(!soon.expired());
+
+        let start = Instant::now();
+        while !soon.expired() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(15));
+        assert!(!later.expired());
+        assert!(later.remaining() > Duration::from_secs(50));
+        assert!(!DeadlineGuard::new(Duration::MAX).expired());
+    }
+}
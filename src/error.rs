@@ -0,0 +1,243 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by [`CircuitBreaker::call`](crate::CircuitBreaker::call).
+#[derive(Debug)]
+pub enum CircuitBreakerError<E> {
+    /// The protected function returned an error.
+    FunctionError(E),
+    /// The call did not finish within the timeout.
+    TimeoutError,
+    /// The call succeeded, but took longer than the
+    /// [`max_call_duration`](crate::CircuitBreakerBuilder::max_call_duration),
+    /// so its value was dropped.
+    DeadlineExceeded,
+    /// The breaker did not run the call, for the given reason.
+    Rejected(RejectionReason),
+    /// The breaker itself failed to run the call, e.g. the worker thread
+    /// panicked before returning a result. Does not count against the
+    /// protected service.
+    Internal(String),
+}
+
+impl<E> CircuitBreakerError<E> {
+    /// The protected function's error, if that is what this is, borrowed so
+    /// the whole error can still be propagated afterwards.
+    pub fn function_error(&self) -> Option<&E> {
+        match self {
+            CircuitBreakerError::FunctionError(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether the call timed out.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, CircuitBreakerError::TimeoutError)
+    }
+}
+
+/// Why the breaker rejected a call, see [`CircuitBreakerError::Rejected`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// The breaker is open and the recovery time has not elapsed yet; retry
+    /// after [`time_until_retry`](crate::CircuitBreaker::time_until_retry).
+    Open,
+    /// The breaker used up its
+    /// [`max_probe_attempts`](crate::CircuitBreakerBuilder::max_probe_attempts)
+    /// and stays open until reset.
+    ProbesExhausted,
+    /// The [`transition_guard`](crate::CircuitBreakerBuilder::transition_guard)
+    /// refused to let the open breaker half-open.
+    Vetoed,
+    /// The breaker is soft-open, or ramping up after closing, and the call
+    /// was not among the share it admits, see
+    /// [`soft_open`](crate::CircuitBreakerBuilder::soft_open) and
+    /// [`close_ramp`](crate::CircuitBreakerBuilder::close_ramp).
+    Throttled,
+    /// The breaker is half-open and already running as many probes as
+    /// [`half_open_max_concurrent`](crate::CircuitBreakerBuilder::half_open_max_concurrent)
+    /// allows.
+    ProbesBusy,
+    /// The breaker is half-open and the call's priority is below the
+    /// [`half_open_min_priority`](crate::CircuitBreakerBuilder::half_open_min_priority).
+    LowPriority,
+    /// The breaker is already running as many calls as
+    /// [`max_concurrent`](crate::CircuitBreakerBuilder::max_concurrent)
+    /// allows in its current state.
+    ConcurrencyLimit,
+    /// The call needed a new worker thread, but the breaker's
+    /// [`ThreadLimit`](crate::ThreadLimit) is reached.
+    ResourceExhausted,
+    /// Every permit of the breaker's shared
+    /// [`Semaphore`](crate::Semaphore) is taken by running calls.
+    SemaphoreExhausted,
+    /// The [`Guard`](crate::Guard)'s rate limiter had no token for the
+    /// call, so the breaker never saw it; retry after
+    /// [`time_until_token`](crate::Guard::time_until_token).
+    RateLimited,
+    /// An external controller forced the breaker open through its
+    /// [`state_store`](crate::CircuitBreakerBuilder::state_store).
+    ForcedOpen,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectionReason::Open => write!(f, "breaker is open"),
+            RejectionReason::ProbesExhausted => write!(f, "probe attempts exhausted"),
+            RejectionReason::Vetoed => write!(f, "half-open vetoed by transition guard"),
+            RejectionReason::Throttled => write!(f, "throttled while soft-open or ramping up"),
+            RejectionReason::ProbesBusy => write!(f, "half-open probe limit reached"),
+            RejectionReason::LowPriority => write!(f, "priority too low to probe"),
+            RejectionReason::ConcurrencyLimit => write!(f, "concurrency limit reached"),
+            RejectionReason::ResourceExhausted => write!(f, "worker thread limit reached"),
+            RejectionReason::SemaphoreExhausted => {
+                write!(f, "shared semaphore has no permits left")
+            }
+            RejectionReason::RateLimited => write!(f, "rate limit exceeded"),
+            RejectionReason::ForcedOpen => write!(f, "forced open by controller"),
+        }
+    }
+}
+
+/// The name [`CircuitBreakerError`] had before it was made public.
+#[deprecated(note = "renamed to `CircuitBreakerError`")]
+pub type MyError<E> = CircuitBreakerError<E>;
+
+impl<E: fmt::Debug> fmt::Display for CircuitBreakerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CircuitBreakerError::FunctionError(e) => write!(f, "FunctionError: {:?}", e),
+            CircuitBreakerError::TimeoutError => write!(f, "TimeoutError"),
+            CircuitBreakerError::DeadlineExceeded => write!(f, "DeadlineExceeded"),
+            CircuitBreakerError::Rejected(reason) => write!(f, "Rejected: {}", reason),
+            CircuitBreakerError::Internal(msg) => write!(f, "Internal: {}", msg),
+        }
+    }
+}
+
+/// Error returned by [`CircuitBreakerBuilder::build`](crate::CircuitBreakerBuilder::build)
+/// when the configuration cannot work as intended.
+///
+/// A zero `recovery_time`, and a `timeout` longer than the `recovery_time`,
+/// are not errors: the first half-opens on the next call, and the second
+/// only means a call can outlast the open period it tripped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// `failure_threshold` is zero, so the first failure would trip the
+    /// breaker.
+    ZeroFailureThreshold,
+    /// `half_open_successes` is zero, so the breaker could never require a
+    /// successful probe before closing.
+    ZeroHalfOpenSuccesses,
+    /// `timeout` is zero, so every call would time out.
+    ZeroTimeout,
+    /// A `timeout` is set together with
+    /// [`no_threads`](crate::CircuitBreakerBuilder::no_threads), which has
+    /// no thread to enforce it on.
+    TimeoutWithoutThreads,
+    /// A [`TripPolicy::Windowed`](crate::TripPolicy::Windowed) or
+    /// [`TripPolicy::ConsecutiveOrWindowed`](crate::TripPolicy::ConsecutiveOrWindowed)
+    /// window, or a [`TripPolicy::Decaying`](crate::TripPolicy::Decaying)
+    /// half-life, is zero, so no two failures would ever count together.
+    ZeroFailureWindow,
+    /// The
+    /// [`half_open_success_window`](crate::CircuitBreakerBuilder::half_open_success_window)
+    /// is zero, so no two probe successes would ever count together.
+    ZeroSuccessWindow,
+    /// `warning_threshold` is not below `failure_threshold`, so the breaker
+    /// would trip before it could become degraded.
+    WarningThresholdTooHigh,
+    /// The [`soft_open`](crate::CircuitBreakerBuilder::soft_open) share is
+    /// not between 1 and 99 percent.
+    InvalidSoftOpenPercent,
+    /// The [`close_ramp`](crate::CircuitBreakerBuilder::close_ramp) window
+    /// is zero, or its starting share is not between 1 and 99 percent.
+    InvalidCloseRamp,
+    /// The
+    /// [`on_threshold_warning`](crate::CircuitBreakerBuilder::on_threshold_warning)
+    /// percentage is not between 1 and 100.
+    InvalidWarningPercent,
+    /// `half_open_max_concurrent` is zero, so no probe could ever run.
+    ZeroHalfOpenConcurrency,
+    /// A [`max_concurrent`](crate::CircuitBreakerBuilder::max_concurrent)
+    /// limit is zero, so no call could ever run in that state.
+    ZeroConcurrencyLimit,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::ZeroFailureThreshold => {
+                write!(f, "failure_threshold must be at least 1")
+            }
+            BuildError::ZeroHalfOpenSuccesses => write!(
+                f,
+                "half_open_successes must be at least 1; use 1 to close on the first successful probe"
+            ),
+            BuildError::ZeroTimeout => {
+                write!(f, "timeout must be greater than zero or every call times out")
+            }
+            BuildError::TimeoutWithoutThreads => {
+                write!(f, "a timeout cannot be enforced with no_threads")
+            }
+            BuildError::ZeroFailureWindow => {
+                write!(f, "the windowed trip policy needs a non-zero window")
+            }
+            BuildError::ZeroSuccessWindow => {
+                write!(f, "half_open_success_window must be greater than zero")
+            }
+            BuildError::WarningThresholdTooHigh => {
+                write!(f, "warning_threshold must be below failure_threshold")
+            }
+            BuildError::InvalidSoftOpenPercent => {
+                write!(f, "soft_open must admit between 1 and 99 percent of calls")
+            }
+            BuildError::InvalidCloseRamp => write!(
+                f,
+                "close_ramp needs a non-zero window and must start between 1 and 99 percent"
+            ),
+            BuildError::InvalidWarningPercent => {
+                write!(f, "on_threshold_warning must warn at 1 to 100 percent")
+            }
+            BuildError::ZeroConcurrencyLimit => {
+                write!(f, "max_concurrent must allow at least 1 call")
+            }
+            BuildError::ZeroHalfOpenConcurrency => {
+                write!(f, "half_open_max_concurrent must be at least 1")
+            }
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_without_consuming() {
+        fn fetch() -> Result<u32, CircuitBreakerError<String>> {
+            Err(CircuitBreakerError::FunctionError("refused".to_string()))
+        }
+        fn log_and_propagate(logged: &mut Vec<String>) -> Result<u32, CircuitBreakerError<String>> {
+            fetch().inspect_err(|e| {
+                if let Some(inner) = e.function_error() {
+                    logged.push(inner.clone());
+                }
+            })
+        }
+
+        let mut logged = Vec::new();
+        let err = log_and_propagate(&mut logged).unwrap_err();
+        assert_eq!(logged, ["refused"]);
+        assert!(!err.is_timeout());
+        assert!(matches!(err, CircuitBreakerError::FunctionError(e) if e == "refused"));
+
+        let timeout = CircuitBreakerError::<String>::TimeoutError;
+        assert!(timeout.is_timeout());
+        assert_eq!(timeout.function_error(), None);
+    }
+}
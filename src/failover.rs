@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::breaker::Admission;
+use crate::{CircuitBreaker, CircuitBreakerError, Clock, Metrics, RunsOnWorkerThread, SystemClock};
+
+/// A primary breaker with a standby, for active-passive failover: calls go
+/// to the primary target while its breaker admits them, and to the
+/// secondary target while it is open.
+///
+/// A call the primary admits is not retried on the secondary if it fails;
+/// the failure counts against the primary and is returned, and once enough
+/// of them open it, traffic moves over. The call that finds the primary's
+/// recovery time elapsed, which moves it to half-open without running
+/// anything, goes to the secondary too.
+pub struct FailoverBreaker<C: Clock = SystemClock> {
+    primary: CircuitBreaker<C>,
+    secondary: CircuitBreaker<C>,
+    served_by_primary: u64,
+    served_by_secondary: u64,
+}
+
+/// A snapshot of a [`FailoverBreaker`], see [`FailoverBreaker::metrics`].
+#[derive(Debug, Clone)]
+pub struct FailoverMetrics {
+    pub primary: Metrics,
+    pub secondary: Metrics,
+    /// Total calls the primary target ran, whatever their outcome.
+    pub served_by_primary: u64,
+    /// Total calls the secondary target ran because the primary was open.
+    pub served_by_secondary: u64,
+}
+
+impl<C: Clock> FailoverBreaker<C> {
+    pub fn new(primary: CircuitBreaker<C>, secondary: CircuitBreaker<C>) -> Self {
+        FailoverBreaker {
+            primary,
+            secondary,
+            served_by_primary: 0,
+            served_by_secondary: 0,
+        }
+    }
+
+    /// Runs `primary` through the primary breaker, or `secondary` through
+    /// the secondary one if the primary does not admit the call. Returns
+    /// what that breaker's [`CircuitBreaker::call`] does, so a call is only
+    /// rejected if both are open.
+    pub fn call<F, G, R, E>(
+        &mut self,
+        primary: F,
+        secondary: G,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        G: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        if let Ok(Admission::Run {
+            inline,
+            slots: _slots,
+            admitted_in,
+        }) = self.primary.admit::<R, E>()
+        {
+            self.served_by_primary += 1;
+            let (outcome, elapsed) = self.primary.runner().run(inline, primary);
+            return self
+                .primary
+                .complete(outcome, elapsed, admitted_in, None)
+                .map(Some);
+        }
+        let admitted = self.secondary.metrics().admitted;
+        let res = self.secondary.call(secondary);
+        if self.secondary.metrics().admitted > admitted {
+            self.served_by_secondary += 1;
+        }
+        res
+    }
+
+    pub fn primary(&self) -> &CircuitBreaker<C> {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &CircuitBreaker<C> {
+        &self.secondary
+    }
+
+    pub fn metrics(&self) -> FailoverMetrics {
+        FailoverMetrics {
+            primary: self.primary.metrics(),
+            secondary: self.secondary.metrics(),
+            served_by_primary: self.served_by_primary,
+            served_by_secondary: self.served_by_secondary,
+        }
+    }
+}
+
+impl<C: Clock> fmt::Debug for FailoverBreaker<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FailoverBreaker")
+            .field("primary", &self.primary.state())
+            .field("secondary", &self.secondary.state())
+            .field("served_by_primary", &self.served_by_primary)
+            .field("served_by_secondary", &self.served_by_secondary)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{FakeClock, RejectionReason, State};
+
+    fn breaker(clock: &FakeClock) -> CircuitBreaker<FakeClock> {
+        CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(10))
+            .build_with_clock(clock.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_open_primary_routes_to_secondary() {
+        let clock = FakeClock::new();
+        let mut failover = FailoverBreaker::new(breaker(&clock), breaker(&clock));
+        let call = |failover: &mut FailoverBreaker<FakeClock>| {
+            failover.call(|| Ok::<_, &str>("primary"), || Ok("secondary"))
+        };
+        assert!(matches!(call(&mut failover), Ok(Some("primary"))));
+
+        // Failures on the primary are returned, not retried elsewhere.
+        while failover.primary().state() != State::Open {
+            let res = failover.call(|| Err::<&str, _>("boom"), || Ok("secondary"));
+            assert!(matches!(
+                res,
+                Err(CircuitBreakerError::FunctionError("boom"))
+            ));
+        }
+        assert!(matches!(call(&mut failover), Ok(Some("secondary"))));
+        assert!(matches!(call(&mut failover), Ok(Some("secondary"))));
+
+        // Recovery moves the primary to half-open and the probe back to it.
+        clock.advance(Duration::from_secs(10));
+        assert!(matches!(call(&mut failover), Ok(Some("secondary"))));
+        assert_eq!(failover.primary().state(), State::HalfOpen);
+        assert!(matches!(call(&mut failover), Ok(Some("primary"))));
+
+        let metrics = failover.metrics();
+        assert_eq!(
+            (metrics.served_by_primary, metrics.served_by_secondary),
+            (4, 3)
+        );
+        assert_eq!(metrics.primary.rejected, 2);
+        assert_eq!(metrics.secondary.successes, 3);
+    }
+
+    #[test]
+    fn test_rejected_when_both_are_open() {
+        let clock = FakeClock::new();
+        let mut failover = FailoverBreaker::new(breaker(&clock), breaker(&clock));
+        while failover.primary().state() != State::Open {
+            let _ = failover.call(|| Err::<(), _>("boom"), || Ok(()));
+        }
+        while failover.secondary().state() != State::Open {
+            let _ = failover.call(|| Ok(()), || Err::<(), _>("boom"));
+        }
+        let served = failover.metrics().served_by_secondary;
+        assert!(matches!(
+            failover.call(|| Ok::<_, ()>(()), || Ok(())),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+        assert_eq!(failover.metrics().served_by_secondary, served);
+    }
+}
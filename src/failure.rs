@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{RejectionReason, State};
+
+/// Why a call counted as a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The protected function returned an error.
+    Error,
+    /// The protected function did not finish within the timeout.
+    Timeout,
+    /// The protected function returned `Ok`, but
+    /// [`evaluate`](crate::CircuitBreakerBuilder::evaluate) classified the
+    /// value as a failure.
+    Value,
+    /// The protected function panicked, see
+    /// [`count_panics`](crate::CircuitBreakerBuilder::count_panics).
+    Panic,
+}
+
+/// How the breaker counted a call, returned by
+/// [`CircuitBreaker::call_classified`](crate::CircuitBreaker::call_classified).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    /// The call ran and counted as a success.
+    Success,
+    /// The call failed or timed out and counted against the breaker.
+    CountedFailure,
+    /// The call ran, but the breaker did not count it, e.g. an error that
+    /// [`is_failure`](crate::CircuitBreakerBuilder::is_failure) ignored.
+    IgnoredFailure,
+    /// The call was rejected or skipped without running.
+    NotRun,
+}
+
+/// What happened to a call, as reported to
+/// [`on_result`](crate::CircuitBreakerBuilder::on_result).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CallOutcome {
+    /// The call ran and returned a value the breaker accepted.
+    Success,
+    /// The call returned an error, panicked, or returned a value that
+    /// [`evaluate`](crate::CircuitBreakerBuilder::evaluate) classified as a
+    /// failure. Errors that the breaker ignores are reported here too.
+    Failure,
+    /// The call did not finish within the timeout.
+    Timeout,
+    /// The call was lost to a problem in the breaker rather than the
+    /// service, e.g. a worker thread that exited without handing back a
+    /// result. It is not counted against the breaker.
+    Internal,
+    /// The breaker turned the call away without running it.
+    Rejected(RejectionReason),
+    /// The call was not run but not rejected either, e.g. the one that moves
+    /// an open breaker to half-open.
+    Skipped,
+}
+
+/// A call that just completed or was turned away, see
+/// [`on_result`](crate::CircuitBreakerBuilder::on_result).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallResult {
+    pub outcome: CallOutcome,
+    /// How long the call ran, or `None` if it did not run.
+    pub latency: Option<Duration>,
+    /// The breaker's state once the call was accounted for.
+    pub state: State,
+}
+
+/// A failed call kept in the breaker's recent-failure buffer, see
+/// [`CircuitBreaker::recent_failures`](crate::CircuitBreaker::recent_failures).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureRecord {
+    /// The label passed to
+    /// [`call_labeled`](crate::CircuitBreaker::call_labeled), if any.
+    pub label: Option<String>,
+    pub kind: FailureKind,
+    /// The panic message, for a [`FailureKind::Panic`] whose payload was a
+    /// string.
+    pub message: Option<String>,
+    pub at: Instant,
+}
+
+/// A call that timed out but whose worker finished afterwards, see
+/// [`CircuitBreakerBuilder::late_results`](crate::CircuitBreakerBuilder::late_results).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LateResult {
+    /// Whether the protected function returned `Ok`.
+    pub succeeded: bool,
+    /// How long the call actually took, timeout included.
+    pub elapsed: Duration,
+    pub at: Instant,
+}
+
+/// Bounded queue of late results, shared with the worker threads.
+#[derive(Debug)]
+pub(crate) struct LateResults {
+    capacity: usize,
+    queue: Mutex<VecDeque<LateResult>>,
+}
+
+impl LateResults {
+    pub(crate) fn new(capacity: usize) -> Self {
+        LateResults {
+            capacity,
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Adds `result`, dropping the oldest one if the queue is full.
+    pub(crate) fn push(&self, result: LateResult) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        if queue.len() == self.capacity {
+            queue.pop_front();
+        }
+        queue.push_back(result);
+    }
+
+    pub(crate) fn drain(&self) -> Vec<LateResult> {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.drain(..).collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
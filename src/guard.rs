@@ -0,0 +1,204 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::breaker::Admission;
+use crate::{
+    CircuitBreaker, CircuitBreakerError, Clock, RejectionReason, RunsOnWorkerThread, SystemClock,
+};
+
+/// A token bucket: holds up to `burst` tokens, gains `per_second` of them
+/// every second, and lets a call through for each token it has.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Starts full, so the first `burst` calls go through at once.
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        RateLimiter {
+            per_second: f64::from(per_second),
+            burst: f64::from(burst),
+            tokens: f64::from(burst),
+            refilled_at: None,
+        }
+    }
+
+    /// Whole tokens left as of the last call.
+    pub fn available(&self) -> u32 {
+        self.tokens as u32
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(at) = self.refilled_at {
+            let elapsed = now.saturating_duration_since(at);
+            self.tokens = (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.burst);
+        }
+        self.refilled_at = Some(now);
+    }
+
+    /// Takes a token, or returns how long until there is one.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if self.per_second == 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.per_second,
+        ))
+    }
+
+    fn give_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.burst);
+    }
+}
+
+/// A rate limiter and a breaker in front of one dependency: a call runs
+/// only if the limiter has a token for it and the breaker admits it.
+///
+/// The limiter is asked first, and rejects with
+/// [`RejectionReason::RateLimited`] without the breaker seeing the call.
+/// A call the breaker then rejects gets its token back, so an open
+/// breaker does not use up the rate. Both read the breaker's clock.
+pub struct Guard<C: Clock = SystemClock> {
+    limiter: RateLimiter,
+    breaker: CircuitBreaker<C>,
+    rate_limited: u64,
+}
+
+impl<C: Clock> Guard<C> {
+    pub fn new(limiter: RateLimiter, breaker: CircuitBreaker<C>) -> Self {
+        Guard {
+            limiter,
+            breaker,
+            rate_limited: 0,
+        }
+    }
+
+    /// Runs `func` like [`CircuitBreaker::call`] if the limiter lets it
+    /// through.
+    pub fn call<F, R, E>(&mut self, func: F) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        if self.limiter.try_take(self.breaker.clock().now()).is_err() {
+            self.rate_limited += 1;
+            return Err(CircuitBreakerError::Rejected(RejectionReason::RateLimited));
+        }
+        match self.breaker.admit::<R, E>() {
+            Ok(Admission::Run {
+                inline,
+                slots: _slots,
+                admitted_in,
+            }) => {
+                let (outcome, elapsed) = self.breaker.runner().run(inline, func);
+                self.breaker
+                    .complete(outcome, elapsed, admitted_in, None)
+                    .map(Some)
+            }
+            Ok(Admission::Skip(value)) => {
+                self.limiter.give_back();
+                Ok(value)
+            }
+            Err(e) => {
+                self.limiter.give_back();
+                Err(e)
+            }
+        }
+    }
+
+    /// How long until the limiter has a token again, zero if it has one.
+    pub fn time_until_token(&mut self) -> Duration {
+        match self.limiter.try_take(self.breaker.clock().now()) {
+            Ok(()) => {
+                self.limiter.give_back();
+                Duration::ZERO
+            }
+            Err(wait) => wait,
+        }
+    }
+
+    /// Total calls the limiter rejected.
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limited
+    }
+
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker<C> {
+        &self.breaker
+    }
+}
+
+impl<C: Clock> fmt::Debug for Guard<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Guard")
+            .field("limiter", &self.limiter)
+            .field("state", &self.breaker.state())
+            .field("rate_limited", &self.rate_limited)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeClock, State};
+
+    fn guard(clock: &FakeClock) -> Guard<FakeClock> {
+        let breaker = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(60))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        Guard::new(RateLimiter::new(10, 2), breaker)
+    }
+
+    #[test]
+    fn test_rate_limiter_rejects_before_the_breaker() {
+        let clock = FakeClock::new();
+        let mut guard = guard(&clock);
+        for _ in 0..2 {
+            assert!(matches!(guard.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        }
+        assert!(matches!(
+            guard.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::RateLimited))
+        ));
+        assert_eq!(guard.time_until_token(), Duration::from_millis(100));
+        assert_eq!(guard.rate_limited(), 1);
+        assert_eq!(guard.breaker().metrics().rejected, 0);
+
+        clock.advance(Duration::from_millis(100));
+        assert!(matches!(guard.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(guard.breaker().metrics().successes, 3);
+    }
+
+    #[test]
+    fn test_open_breaker_rejects_and_keeps_the_token() {
+        let clock = FakeClock::new();
+        let mut guard = guard(&clock);
+        while guard.breaker().state() != State::Open {
+            clock.advance(Duration::from_secs(1));
+            let _ = guard.call(|| Err::<(), _>("boom"));
+        }
+        let tokens = guard.limiter().available();
+        assert!(matches!(
+            guard.call(|| Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(RejectionReason::Open))
+        ));
+        assert_eq!(guard.limiter().available(), tokens);
+        assert_eq!(guard.rate_limited(), 0);
+    }
+}
@@ -0,0 +1,123 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::StateTransition;
+
+/// A user-supplied closure stored on the builder and the breaker.
+///
+/// Shared behind an `Arc` so the builder stays `Clone`, and printed as a
+/// placeholder so both can keep deriving `Debug`.
+pub(crate) struct Hook<T: ?Sized>(pub(crate) Arc<T>);
+
+impl<T: ?Sized> Clone for Hook<T> {
+    fn clone(&self) -> Self {
+        Hook(Arc::clone(&self.0))
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Hook<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("<hook>")
+    }
+}
+
+/// A state-change hook that also gets the breaker's user data.
+pub(crate) type DataHook<D> = dyn Fn(&mut D, StateTransition) + Send + Sync;
+
+/// A predicate over a call's error or result, erased so the breaker does not
+/// have to be generic over either type.
+pub(crate) type AnyPredicate = dyn Fn(&dyn Any) -> bool + Send + Sync;
+
+/// An [`AnyPredicate`] that returns `None` for values of another type than
+/// the one it was written for, so several can be combined with [`any_of`].
+pub(crate) type AnyMatch = dyn Fn(&dyn Any) -> Option<bool> + Send + Sync;
+
+/// Wraps `f` as an [`AnyMatch`].
+pub(crate) fn any_match<E, F>(f: F) -> Hook<AnyMatch>
+where
+    E: 'static,
+    F: Fn(&E) -> bool + Send + Sync + 'static,
+{
+    Hook(Arc::new(move |e: &dyn Any| e.downcast_ref::<E>().map(&f)))
+}
+
+/// Combines `predicates` into one that is `true` as soon as one of them is,
+/// trying them in order. Values none of them checks get `otherwise`.
+pub(crate) fn any_of(
+    predicates: Vec<Hook<AnyMatch>>,
+    otherwise: bool,
+) -> Option<Hook<AnyPredicate>> {
+    if predicates.is_empty() {
+        return None;
+    }
+    Some(Hook(Arc::new(move |e: &dyn Any| {
+        let mut checked = false;
+        for predicate in &predicates {
+            match (predicate.0)(e) {
+                Some(true) => return true,
+                Some(false) => checked = true,
+                None => {}
+            }
+        }
+        otherwise && !checked
+    })))
+}
+
+/// Wraps `f` as an [`AnyPredicate`]. Values that are not an `E` get
+/// `otherwise` instead of running `f`.
+pub(crate) fn any_predicate<E, F>(f: F, otherwise: bool) -> Hook<AnyPredicate>
+where
+    E: 'static,
+    F: Fn(&E) -> bool + Send + Sync + 'static,
+{
+    Hook(Arc::new(move |e: &dyn Any| match e.downcast_ref::<E>() {
+        Some(e) => f(e),
+        None => otherwise,
+    }))
+}
+
+/// A key one [`AnyKey`] gave, with its type erased.
+pub(crate) type ErrorKey = Box<dyn Any + Send + Sync>;
+
+/// Extracts the key a call's error is told apart by, erased like
+/// [`AnyPredicate`].
+pub(crate) type AnyKey = dyn Fn(&dyn Any) -> Option<ErrorKey> + Send + Sync;
+
+/// Compares two keys given by the same [`AnyKey`].
+pub(crate) type KeyEq = fn(&dyn Any, &dyn Any) -> bool;
+
+/// Wraps `f` as an [`AnyKey`], with how to compare its keys. Errors that
+/// are not an `E` have no key.
+pub(crate) fn any_key<E, K, F>(f: F) -> (Hook<AnyKey>, KeyEq)
+where
+    E: 'static,
+    K: Eq + Send + Sync + 'static,
+    F: Fn(&E) -> K + Send + Sync + 'static,
+{
+    let key = Hook(Arc::new(
+        move |e: &dyn Any| Some(Box::new(f(e.downcast_ref::<E>()?)) as ErrorKey),
+    ) as Arc<AnyKey>);
+    (key, key_eq::<K>)
+}
+
+fn key_eq<K: Eq + 'static>(a: &dyn Any, b: &dyn Any) -> bool {
+    a.downcast_ref::<K>()
+        .is_some_and(|a| b.downcast_ref::<K>() == Some(a))
+}
+
+/// Extracts a retry hint from a call's error, erased like [`AnyPredicate`].
+pub(crate) type AnyRetryHint = dyn Fn(&dyn Any) -> Option<Duration> + Send + Sync;
+
+/// Wraps `f` as an [`AnyRetryHint`]. Errors that are not an `E` give no
+/// hint.
+pub(crate) fn any_retry_hint<E, F>(f: F) -> Hook<AnyRetryHint>
+where
+    E: 'static,
+    F: Fn(&E) -> Option<Duration> + Send + Sync + 'static,
+{
+    Hook(Arc::new(move |e: &dyn Any| {
+        e.downcast_ref::<E>().and_then(&f)
+    }))
+}
@@ -0,0 +1,118 @@
+use reqwest::{Error, RequestBuilder, Response};
+
+use crate::{BuildError, CircuitBreaker, CircuitBreakerError, Clock, Config};
+
+/// Builds a breaker for HTTP calls made with [`reqwest`], to be used with
+/// [`CircuitBreaker::call_reqwest`].
+///
+/// Connection errors and timeouts count as failures, as do responses with
+/// a 5xx status. Other errors, such as a request that could not be built,
+/// and 4xx responses are the caller's problem rather than the server's, so
+/// they do not count against the breaker.
+pub fn reqwest_breaker(config: Config) -> Result<CircuitBreaker, BuildError> {
+    CircuitBreaker::builder()
+        .failure_threshold(config.failure_threshold)
+        .timeout(config.timeout)
+        .recovery_time(config.recovery_time)
+        .half_open_successes(config.half_open_successes)
+        .is_failure(|e: &Error| e.is_connect() || e.is_timeout())
+        .evaluate(|response: &Response| response.status().is_server_error())
+        .build()
+}
+
+impl<C: Clock, D> CircuitBreaker<C, D> {
+    /// Sends `request` through [`CircuitBreaker::call_async`].
+    ///
+    /// A 5xx response is still returned as `Ok`, so the caller can read its
+    /// body; the breaker only counts it as a failure if it was built by
+    /// [`reqwest_breaker`] or with a matching
+    /// [`evaluate`](crate::CircuitBreakerBuilder::evaluate) hook.
+    pub async fn call_reqwest(
+        &mut self,
+        request: RequestBuilder,
+    ) -> Result<Option<Response>, CircuitBreakerError<Error>> {
+        self.call_async(|| request.send()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::State;
+
+    /// Serves one response per status in `statuses`, in order, on a free
+    /// local port, and returns the server's base URL.
+    fn mock_server(statuses: Vec<u16>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn config() -> Config {
+        Config {
+            failure_threshold: 1,
+            timeout: Duration::from_secs(5),
+            recovery_time: Duration::from_secs(60),
+            half_open_successes: 1,
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_reqwest_breaker_classifies_responses() {
+        let url = mock_server(vec![404, 400, 503, 500]);
+        let client = reqwest::Client::new();
+        let mut cb = reqwest_breaker(config()).unwrap();
+
+        for expected in [404, 400] {
+            let res = cb.call_reqwest(client.get(&url)).await;
+            assert_eq!(res.unwrap().unwrap().status().as_u16(), expected);
+        }
+        assert_eq!(cb.metrics().failure_count, 0);
+
+        for expected in [503, 500] {
+            let res = cb.call_reqwest(client.get(&url)).await;
+            assert_eq!(res.unwrap().unwrap().status().as_u16(), expected);
+        }
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_reqwest_breaker_counts_connection_errors() {
+        // Bind and drop a listener to get a port nothing is listening on.
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let client = reqwest::Client::new();
+        let mut cb = reqwest_breaker(config()).unwrap();
+
+        for _ in 0..2 {
+            let res = cb.call_reqwest(client.get(&url)).await;
+            assert!(matches!(res, Err(CircuitBreakerError::FunctionError(e)) if e.is_connect()));
+        }
+        assert_eq!(cb.state(), State::Open);
+
+        let res = cb.call_reqwest(client.get(&url)).await;
+        assert!(matches!(res, Err(CircuitBreakerError::Rejected(_))));
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{
+    BuildError, CircuitBreakerBuilder, CircuitBreakerError, Clock, RunsOnWorkerThread,
+    SharedCircuitBreaker, State, SystemClock,
+};
+
+/// Independent breakers for the keys of one dependency, e.g. one per
+/// tenant, so a noisy key cannot trip the breaker for everyone else.
+///
+/// Each key gets its own [`SharedCircuitBreaker`], built from the same
+/// builder the first time the key is called. A breaker that has not been
+/// called for `idle_after` is dropped, and so is the least recently used
+/// one when a new key would exceed [`max_keys`](Self::max_keys). A key
+/// that comes back starts closed, so only breakers with nothing to lose
+/// are dropped: closed ones with no failures counted and no call in
+/// flight. Any other breaker is kept, even past `max_keys`.
+pub struct KeyedBreaker<K, C = SystemClock> {
+    builder: CircuitBreakerBuilder,
+    clock: C,
+    idle_after: Duration,
+    max_keys: Option<usize>,
+    inner: Mutex<Keys<K, C>>,
+}
+
+struct Keys<K, C> {
+    breakers: HashMap<K, (SharedCircuitBreaker<C>, Instant)>,
+    last_sweep: Instant,
+}
+
+impl<K: Hash + Eq + Clone> KeyedBreaker<K> {
+    /// Validates `builder` once, so building a breaker for a new key cannot
+    /// fail later.
+    pub fn new(builder: CircuitBreakerBuilder, idle_after: Duration) -> Result<Self, BuildError> {
+        Self::with_clock(builder, idle_after, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq + Clone, C: Clock + Clone> KeyedBreaker<K, C> {
+    /// Like [`KeyedBreaker::new`], but every breaker reads the time from
+    /// `clock`, and so does the idle eviction.
+    pub fn with_clock(
+        builder: CircuitBreakerBuilder,
+        idle_after: Duration,
+        clock: C,
+    ) -> Result<Self, BuildError> {
+        builder.clone().build_with_clock(clock.clone())?;
+        let now = clock.now();
+        Ok(KeyedBreaker {
+            builder,
+            clock,
+            idle_after,
+            max_keys: None,
+            inner: Mutex::new(Keys {
+                breakers: HashMap::new(),
+                last_sweep: now,
+            }),
+        })
+    }
+
+    /// Keeps breakers for at most `max` keys, dropping the least recently
+    /// used one that can be dropped when a new key arrives. Unbounded by
+    /// default.
+    pub fn max_keys(mut self, max: usize) -> Self {
+        self.max_keys = Some(max);
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Keys<K, C>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drops the breakers not used for `idle_after`, unless they still
+    /// have state worth keeping. [`KeyedBreaker::call`] does this at most
+    /// once per `idle_after` on its own.
+    pub fn evict_idle(&self) {
+        let now = self.clock.now();
+        self.sweep(&mut self.lock(), now);
+    }
+
+    fn sweep(&self, keys: &mut Keys<K, C>, now: Instant) {
+        let idle_after = self.idle_after;
+        keys.breakers.retain(|_, (breaker, used)| {
+            now.saturating_duration_since(*used) < idle_after || !evictable(breaker)
+        });
+        keys.last_sweep = now;
+    }
+
+    /// Calls `func` through the breaker for `key`, creating it if needed.
+    /// See [`CircuitBreaker::call`](crate::CircuitBreaker::call).
+    pub fn call<F, R, E>(&self, key: &K, func: F) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.breaker(key).call(func)
+    }
+
+    /// The breaker for `key`, created if needed, e.g. to check its state or
+    /// make a call variant other than `call`. Counts as using the key.
+    pub fn breaker(&self, key: &K) -> SharedCircuitBreaker<C> {
+        let now = self.clock.now();
+        let mut keys = self.lock();
+        if now.saturating_duration_since(keys.last_sweep) >= self.idle_after {
+            self.sweep(&mut keys, now);
+        }
+        if let Some((breaker, used)) = keys.breakers.get_mut(key) {
+            *used = now;
+            return breaker.clone();
+        }
+        let breaker = SharedCircuitBreaker::new(
+            self.builder
+                .clone()
+                .build_with_clock(self.clock.clone())
+                .expect("builder was validated in KeyedBreaker::new"),
+        );
+        if self.max_keys.is_some_and(|max| keys.breakers.len() >= max) {
+            let lru = keys
+                .breakers
+                .iter()
+                .filter(|(_, (breaker, _))| evictable(breaker))
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                keys.breakers.remove(&lru);
+            }
+        }
+        keys.breakers.insert(key.clone(), (breaker.clone(), now));
+        breaker
+    }
+
+    /// The breaker for `key` if it exists, without creating it or counting
+    /// as a use.
+    pub fn get(&self, key: &K) -> Option<SharedCircuitBreaker<C>> {
+        self.lock()
+            .breakers
+            .get(key)
+            .map(|(breaker, _)| breaker.clone())
+    }
+
+    /// How many keys currently have a breaker.
+    pub fn len(&self) -> usize {
+        self.lock().breakers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Whether dropping `breaker` loses nothing, see [`KeyedBreaker`]. An open
+/// breaker is kept even once its recovery time has passed, so that its key
+/// still goes through half-open probing instead of starting at full
+/// traffic.
+fn evictable<C: Clock>(breaker: &SharedCircuitBreaker<C>) -> bool {
+    let metrics = breaker.metrics();
+    metrics.state == State::Closed && metrics.failure_count == 0 && breaker.in_flight() == 0
+}
+
+impl<K, C> fmt::Debug for KeyedBreaker<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("KeyedBreaker")
+            .field("keys", &keys.breakers.len())
+            .field("idle_after", &self.idle_after)
+            .field("max_keys", &self.max_keys)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CircuitBreaker, FakeClock};
+
+    // The breakers below run calls inline, so that a call is no longer in
+    // flight once it has returned, and the key can be evicted right away.
+
+    #[test]
+    fn test_keys_trip_independently_and_idle_keys_are_evicted() {
+        let clock = FakeClock::new();
+        let keyed = KeyedBreaker::with_clock(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(10))
+                .inline_when_closed(true),
+            Duration::from_secs(60),
+            clock.clone(),
+        )
+        .unwrap();
+        let noisy = "noisy".to_string();
+        let quiet = "quiet".to_string();
+        let idle = "idle".to_string();
+
+        while keyed.breaker(&noisy).state() != State::Open {
+            let _ = keyed.call(&noisy, || Err::<(), _>("boom"));
+        }
+        assert!(matches!(keyed.call(&quiet, || Ok::<_, ()>(1)), Ok(Some(1))));
+        assert_eq!(keyed.get(&quiet).unwrap().state(), State::Closed);
+        assert!(matches!(
+            keyed.call(&noisy, || Ok::<_, ()>(1)),
+            Err(CircuitBreakerError::Rejected(_))
+        ));
+        let _ = keyed.call(&idle, || Ok::<_, ()>(()));
+        assert_eq!(keyed.len(), 3);
+
+        // The closed key that is no longer used does not survive the sweep.
+        clock.advance(Duration::from_secs(40));
+        let _ = keyed.call(&quiet, || Ok::<_, ()>(()));
+        clock.advance(Duration::from_secs(40));
+        let _ = keyed.call(&quiet, || Ok::<_, ()>(()));
+        assert_eq!(keyed.len(), 2);
+        assert!(keyed.get(&idle).is_none());
+        assert!(keyed.get(&noisy).is_some());
+    }
+
+    #[test]
+    fn test_evict_idle_keeps_open_breakers() {
+        let clock = FakeClock::new();
+        let keyed = KeyedBreaker::with_clock(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(600))
+                .inline_when_closed(true),
+            Duration::from_secs(60),
+            clock.clone(),
+        )
+        .unwrap();
+        for key in 0..5 {
+            let _ = keyed.call(&key, || Ok::<_, ()>(()));
+        }
+        while keyed.breaker(&0).state() != State::Open {
+            let _ = keyed.call(&0, || Err::<(), _>("boom"));
+        }
+        let _ = keyed.call(&1, || Err::<(), _>("boom"));
+        let held = keyed.breaker(&2).start_call().unwrap();
+
+        clock.advance(Duration::from_secs(30));
+        keyed.evict_idle();
+        assert_eq!(keyed.len(), 5);
+
+        // Only the open breaker, the one with a failure counted, the one
+        // with a call in flight and the one just used are left.
+        clock.advance(Duration::from_secs(31));
+        let _ = keyed.call(&4, || Ok::<_, ()>(()));
+        keyed.evict_idle();
+        assert_eq!(keyed.len(), 4);
+        clock.advance(Duration::from_secs(60));
+        keyed.evict_idle();
+        let open = keyed.get(&0).unwrap();
+        assert_eq!(open.state(), State::Open);
+        assert_eq!(keyed.len(), 3);
+
+        // The open one is kept even once its recovery time has passed, so
+        // the key is probed before it takes full traffic again.
+        assert!(held.success().is_ok());
+        clock.advance(Duration::from_secs(600));
+        keyed.evict_idle();
+        assert_eq!(keyed.len(), 2);
+        assert!(keyed.get(&0).is_some());
+        assert!(keyed.get(&2).is_none());
+    }
+
+    #[test]
+    fn test_max_keys_drops_least_recently_used() {
+        let clock = FakeClock::new();
+        let keyed = KeyedBreaker::with_clock(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .inline_when_closed(true),
+            Duration::from_secs(3600),
+            clock.clone(),
+        )
+        .unwrap()
+        .max_keys(2);
+
+        let _ = keyed.call(&"a", || Ok::<_, ()>(()));
+        clock.advance(Duration::from_secs(1));
+        let _ = keyed.call(&"b", || Ok::<_, ()>(()));
+        clock.advance(Duration::from_secs(1));
+        let _ = keyed.call(&"a", || Ok::<_, ()>(()));
+        clock.advance(Duration::from_secs(1));
+        let _ = keyed.call(&"c", || Ok::<_, ()>(()));
+        assert_eq!(keyed.len(), 2);
+        assert!(keyed.get(&"b").is_none());
+
+        // An open breaker is kept even when it is the least recently used.
+        while keyed.breaker(&"a").state() != State::Open {
+            let _ = keyed.call(&"a", || Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = keyed.call(&"c", || Ok::<_, ()>(()));
+        let _ = keyed.call(&"d", || Ok::<_, ()>(()));
+        assert!(keyed.get(&"a").is_some());
+        assert!(keyed.get(&"c").is_none());
+    }
+
+    #[test]
+    fn test_invalid_builder_is_rejected_up_front() {
+        let err = KeyedBreaker::<u32>::new(
+            CircuitBreaker::builder().failure_threshold(0),
+            Duration::from_secs(60),
+        )
+        .unwrap_err();
+        assert_eq!(err, BuildError::ZeroFailureThreshold);
+    }
+}
@@ -0,0 +1,110 @@
+//! A circuit breaker for protecting calls to unreliable services.
+//!
+//! Calls are run on a worker thread with a timeout. Once failures exceed the
+//! configured threshold the breaker opens and rejects calls until the
+//! recovery time has elapsed, after which a few probe calls are let through
+//! in the half-open state to decide whether to close again.
+//!
+//! With the `metrics` feature, breakers report through the
+//! [`metrics`](https://docs.rs/metrics) facade to whatever recorder is
+//! installed, labelled `breaker=<name>` when named:
+//!
+//! - `circuit_breaker_calls_total`, labelled `outcome` = `success`,
+//!   `failure`, `timeout` or `rejected`
+//! - `circuit_breaker_transitions_total`, labelled `to` with the new state
+//! - `circuit_breaker_state`, a gauge: 0 closed, 1 degraded, 2 half-open,
+//!   3 open, 4 soft-open
+//!
+//! Call counts can be batched with
+//! [`batch_telemetry`](CircuitBreakerBuilder::batch_telemetry) at very high
+//! call rates, at the cost of reporting them a little late.
+//!
+//! With the `opentelemetry` feature, every call is also recorded as a
+//! `circuit_breaker.call` span under the current span, through the
+//! [`opentelemetry`](https://docs.rs/opentelemetry) global tracer provider,
+//! with these attributes:
+//!
+//! - `circuit_breaker.name`, when named
+//! - `circuit_breaker.decision`: `admitted` or `rejected`
+//! - `circuit_breaker.outcome`: `success`, `failure`, `timeout`,
+//!   `rejected` or `skipped`
+//! - `circuit_breaker.rejection_reason`, for rejected calls
+//!
+//! Transitions are added to the current span as
+//! `circuit_breaker.transition` events with `from` and `to` attributes.
+//!
+//! With the `tracing` feature, calls run on a worker thread run inside the
+//! caller's current [`tracing`](https://docs.rs/tracing) span and
+//! subscriber, so what the protected function logs stays correlated with
+//! the caller.
+
+#[cfg(feature = "tokio")]
+mod async_call;
+mod bounds;
+mod boxed;
+mod breaker;
+mod builder;
+mod call_guard;
+mod clock;
+mod compose;
+mod config;
+mod control;
+mod deadline;
+mod error;
+mod failover;
+mod failure;
+mod guard;
+mod hooks;
+#[cfg(feature = "reqwest")]
+mod http;
+mod keyed;
+mod metrics;
+mod permit;
+mod policy;
+mod pool;
+#[cfg(any(test, feature = "test-util"))]
+mod replay;
+mod semaphore;
+mod shared;
+mod state;
+mod telemetry;
+mod timeout;
+
+#[cfg(feature = "tokio")]
+pub use async_call::StreamProgress;
+pub use bounds::{runs_on_worker_thread, RunsOnWorkerThread};
+pub use boxed::{BoxError, BoxedCircuitBreaker};
+pub use breaker::CircuitBreaker;
+pub use builder::CircuitBreakerBuilder;
+pub use call_guard::CallGuard;
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::FakeClock;
+pub use clock::{Clock, SystemClock};
+pub use compose::{Admit, All, Any};
+pub use config::{Config, FieldDiff};
+pub use control::{Override, StateStore};
+pub use deadline::DeadlineGuard;
+#[allow(deprecated)]
+pub use error::MyError;
+pub use error::{BuildError, CircuitBreakerError, RejectionReason};
+pub use failover::{FailoverBreaker, FailoverMetrics};
+pub use failure::{
+    CallOutcome, CallResult, Classification, FailureKind, FailureRecord, LateResult,
+};
+pub use guard::{Guard, RateLimiter};
+#[cfg(feature = "reqwest")]
+pub use http::reqwest_breaker;
+pub use keyed::KeyedBreaker;
+pub use metrics::{
+    LatencyBuckets, LatencyByOutcome, Metrics, RelaxedMetrics, SelfTestReport, StateDurations,
+};
+pub use permit::Permit;
+pub use policy::{HalfOpenIdle, InFlightAfterTrip, Priority, RejectionPolicy, TripPolicy};
+pub use pool::{ThreadLimit, WorkerPool};
+#[cfg(any(test, feature = "test-util"))]
+pub use replay::Replay;
+pub use semaphore::Semaphore;
+pub use shared::{RecoveryTimer, SharedCircuitBreaker};
+pub use state::{State, StateTransition, TransitionRecord, TripReason};
+pub use telemetry::sanitize_label;
+pub use timeout::{Completion, TimeoutStrategy};
@@ -0,0 +1,288 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::Duration;
+
+use crate::{CallOutcome, State};
+
+/// A point-in-time snapshot of a breaker's counters, returned by
+/// [`CircuitBreaker::metrics`](crate::CircuitBreaker::metrics).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// The breaker's name, if one was configured.
+    pub name: Option<String>,
+    /// The name as it appears in telemetry labels, see
+    /// [`metrics_label`](crate::CircuitBreakerBuilder::metrics_label).
+    pub label: Option<String>,
+    pub state: State,
+    /// Consecutive failures counted in the current state.
+    pub failure_count: u32,
+    /// Total calls that completed successfully.
+    pub successes: u64,
+    /// Total calls that returned an error or a value classified as a
+    /// failure. Timeouts are counted separately.
+    pub failures: u64,
+    /// Total calls that timed out.
+    pub timeouts: u64,
+    /// Total calls the breaker let run.
+    pub admitted: u64,
+    /// Total calls the breaker turned away while open.
+    pub rejected: u64,
+    /// Total successful calls slower than the
+    /// [`slow_call_threshold`](crate::CircuitBreakerBuilder::slow_call_threshold).
+    pub slow_calls: u64,
+    /// Median latency of successful calls.
+    #[cfg(feature = "hdrhistogram")]
+    pub latency_p50: Option<Duration>,
+    #[cfg(feature = "hdrhistogram")]
+    pub latency_p90: Option<Duration>,
+    #[cfg(feature = "hdrhistogram")]
+    pub latency_p99: Option<Duration>,
+    /// How long finished calls took, counted separately for successes,
+    /// failures and timeouts.
+    pub latency_by_outcome: LatencyByOutcome,
+    /// How long calls waited in the
+    /// [`half_open_queue`](crate::CircuitBreakerBuilder::half_open_queue)
+    /// before they were admitted or turned away. Latencies, slow calls and
+    /// timeouts only count the time after a call was admitted.
+    pub queue_wait: LatencyBuckets,
+    /// Total time spent in each state since the breaker was built, up to
+    /// the moment of the snapshot.
+    pub time_in_state: StateDurations,
+}
+
+impl Metrics {
+    /// Labels identifying this breaker to a metrics backend.
+    ///
+    /// Unnamed breakers produce no labels.
+    pub fn labels(&self) -> Vec<(&'static str, String)> {
+        match &self.label {
+            Some(label) => vec![("breaker", label.clone())],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The counters of [`Metrics`] that can be read without locking the
+/// breaker, returned by
+/// [`SharedCircuitBreaker::metrics_relaxed`](crate::SharedCircuitBreaker::metrics_relaxed).
+///
+/// Each field is read on its own, so a snapshot can be slightly
+/// inconsistent: a call that just finished may show up in `admitted` but
+/// not yet in `successes`, or the state may already be a newer one than
+/// the counts. Use [`CircuitBreaker::metrics`](crate::CircuitBreaker::metrics)
+/// where the fields have to agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelaxedMetrics {
+    pub state: State,
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub admitted: u64,
+    pub rejected: u64,
+    pub slow_calls: u64,
+}
+
+/// Copies of the breaker's counters, written by the breaker as they change
+/// and read with relaxed ordering by anyone holding the `Arc`.
+#[derive(Debug)]
+pub(crate) struct PublishedMetrics {
+    state: AtomicU8,
+    successes: AtomicU64,
+    failures: AtomicU64,
+    timeouts: AtomicU64,
+    admitted: AtomicU64,
+    rejected: AtomicU64,
+    slow_calls: AtomicU64,
+}
+
+// In declaration order, so `state as u8` indexes it.
+const STATES: [State; 5] = [
+    State::Open,
+    State::Closed,
+    State::Degraded,
+    State::HalfOpen,
+    State::SoftOpen,
+];
+
+impl PublishedMetrics {
+    pub(crate) fn new(state: State) -> Self {
+        let published = PublishedMetrics {
+            state: AtomicU8::new(0),
+            successes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            admitted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            slow_calls: AtomicU64::new(0),
+        };
+        published.set_state(state);
+        published
+    }
+
+    pub(crate) fn set_state(&self, state: State) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn store(&self, metrics: RelaxedMetrics) {
+        self.set_state(metrics.state);
+        self.successes.store(metrics.successes, Ordering::Relaxed);
+        self.failures.store(metrics.failures, Ordering::Relaxed);
+        self.timeouts.store(metrics.timeouts, Ordering::Relaxed);
+        self.admitted.store(metrics.admitted, Ordering::Relaxed);
+        self.rejected.store(metrics.rejected, Ordering::Relaxed);
+        self.slow_calls.store(metrics.slow_calls, Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> RelaxedMetrics {
+        RelaxedMetrics {
+            state: STATES[usize::from(self.state.load(Ordering::Relaxed))],
+            successes: self.successes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            admitted: self.admitted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            slow_calls: self.slow_calls.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Time accumulated in each [`State`], see [`Metrics::time_in_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StateDurations {
+    pub closed: Duration,
+    pub degraded: Duration,
+    pub half_open: Duration,
+    pub open: Duration,
+    pub soft_open: Duration,
+}
+
+impl StateDurations {
+    /// The time spent in `state`.
+    pub fn get(&self, state: State) -> Duration {
+        match state {
+            State::Closed => self.closed,
+            State::Degraded => self.degraded,
+            State::HalfOpen => self.half_open,
+            State::Open => self.open,
+            State::SoftOpen => self.soft_open,
+        }
+    }
+
+    /// The fraction of the total time spent in `state`, from 0.0 to 1.0, or
+    /// 0.0 if no time has passed.
+    pub fn fraction(&self, state: State) -> f64 {
+        let total = self.closed + self.degraded + self.half_open + self.open + self.soft_open;
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.get(state).as_secs_f64() / total.as_secs_f64()
+    }
+
+    pub(crate) fn add(&mut self, state: State, elapsed: Duration) {
+        let slot = match state {
+            State::Closed => &mut self.closed,
+            State::Degraded => &mut self.degraded,
+            State::HalfOpen => &mut self.half_open,
+            State::Open => &mut self.open,
+            State::SoftOpen => &mut self.soft_open,
+        };
+        *slot = slot.saturating_add(elapsed);
+    }
+}
+
+/// Call latencies counted into fixed buckets, see
+/// [`Metrics::latency_by_outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyBuckets {
+    /// One count per bound, then one for calls slower than all of them.
+    counts: [u64; LatencyBuckets::BOUNDS.len() + 1],
+}
+
+impl LatencyBuckets {
+    /// Upper bounds of the buckets, inclusive.
+    pub const BOUNDS: [Duration; 11] = [
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+        Duration::from_millis(25),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(250),
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+        Duration::from_millis(2500),
+        Duration::from_secs(5),
+        Duration::from_secs(10),
+    ];
+
+    /// Each bucket's upper bound with the number of calls in it, fastest
+    /// first. The last bucket, with no bound, holds calls slower than
+    /// every bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        Self::BOUNDS
+            .iter()
+            .copied()
+            .map(Some)
+            .chain([None])
+            .zip(self.counts.iter().copied())
+    }
+
+    /// The number of calls recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let bucket = Self::BOUNDS.partition_point(|&bound| bound < latency);
+        self.counts[bucket] += 1;
+    }
+}
+
+/// [`LatencyBuckets`] for each way a call can finish, see
+/// [`Metrics::latency_by_outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyByOutcome {
+    pub success: LatencyBuckets,
+    pub failure: LatencyBuckets,
+    pub timeout: LatencyBuckets,
+}
+
+impl LatencyByOutcome {
+    /// The buckets for calls that finished with `outcome`, or `None` for
+    /// calls that did not run.
+    pub fn get(&self, outcome: CallOutcome) -> Option<&LatencyBuckets> {
+        match outcome {
+            CallOutcome::Success => Some(&self.success),
+            CallOutcome::Failure => Some(&self.failure),
+            CallOutcome::Timeout => Some(&self.timeout),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn record(&mut self, outcome: CallOutcome, latency: Duration) {
+        let buckets = match outcome {
+            CallOutcome::Success => &mut self.success,
+            CallOutcome::Failure => &mut self.failure,
+            CallOutcome::Timeout => &mut self.timeout,
+            _ => return,
+        };
+        buckets.record(latency);
+    }
+}
+
+/// The result of [`CircuitBreaker::self_test`](crate::CircuitBreaker::self_test).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub state: State,
+    /// Whether the breaker's clock reads no earlier than when the breaker
+    /// entered its current state, and does not go backwards between reads.
+    pub clock_ok: bool,
+    /// Settings that are invalid or work against each other, described for
+    /// a person to read. Empty if the configuration looks sane.
+    pub problems: Vec<String>,
+}
+
+impl SelfTestReport {
+    /// Whether nothing was found wrong.
+    pub fn is_ok(&self) -> bool {
+        self.clock_ok && self.problems.is_empty()
+    }
+}
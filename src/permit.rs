@@ -0,0 +1,154 @@
+use std::{fmt, mem};
+
+use crate::breaker::{Admission, InFlightGuard, Runner};
+use crate::{
+    CircuitBreakerError, Clock, RunsOnWorkerThread, SharedCircuitBreaker, State, SystemClock,
+};
+
+impl<C: Clock, D> SharedCircuitBreaker<C, D> {
+    /// Reserves a call ahead of running it, for operations with costly
+    /// setup that should not be done at all if the breaker would reject the
+    /// call.
+    ///
+    /// Returns `None` if a `call` made now would not run, counting the
+    /// rejection as `call` would. Otherwise the admission is taken, e.g. one
+    /// of the half-open probes, and held by the returned [`Permit`] until it
+    /// is executed or dropped.
+    pub fn reserve(&self) -> Option<Permit<C, D>> {
+        let mut breaker = self.lock();
+        match breaker.admit::<(), ()>() {
+            Ok(Admission::Run {
+                inline,
+                slots,
+                admitted_in,
+            }) => Some(Permit {
+                breaker: Some(self.clone()),
+                runner: breaker.runner(),
+                inline,
+                slots,
+                admitted_in,
+            }),
+            Ok(Admission::Skip(_)) | Err(_) => None,
+        }
+    }
+}
+
+/// A call admitted by [`SharedCircuitBreaker::reserve`] that has not run
+/// yet.
+///
+/// Dropping a permit without executing it hands the admission back, as if
+/// the call had never been attempted.
+#[must_use = "dropping a permit releases the reservation"]
+pub struct Permit<C: Clock = SystemClock, D = ()> {
+    /// Taken once the permit is executed, so `drop` knows not to release
+    /// it.
+    breaker: Option<SharedCircuitBreaker<C, D>>,
+    runner: Runner,
+    inline: bool,
+    slots: Vec<InFlightGuard>,
+    admitted_in: Option<State>,
+}
+
+impl<C: Clock, D> Permit<C, D> {
+    /// Runs `func` as the reserved call and records how it went, like
+    /// [`SharedCircuitBreaker::call`]. The call always runs: the breaker was
+    /// checked when the permit was taken.
+    pub fn execute<F, R, E>(mut self, func: F) -> Result<R, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let breaker = self.breaker.take().expect("permit executed twice");
+        let (outcome, elapsed) = self.runner.run(self.inline, func);
+        let res = breaker
+            .lock()
+            .complete(outcome, elapsed, self.admitted_in, None);
+        breaker.release_slots(mem::take(&mut self.slots));
+        res
+    }
+}
+
+impl<C: Clock, D> Drop for Permit<C, D> {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.breaker.take() {
+            breaker.lock().release_admission(self.admitted_in);
+            breaker.release_slots(mem::take(&mut self.slots));
+        }
+    }
+}
+
+impl<C: Clock, D> fmt::Debug for Permit<C, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Permit")
+            .field("inline", &self.inline)
+            .field("admitted_in", &self.admitted_in)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{CircuitBreaker, FakeClock, HalfOpenIdle};
+
+    fn half_open_breaker() -> (SharedCircuitBreaker<FakeClock>, FakeClock) {
+        let clock = FakeClock::new();
+        let mut cb = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(1)
+            .half_open_idle(HalfOpenIdle::Reopen {
+                after: Duration::from_secs(5),
+            })
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+        (SharedCircuitBreaker::new(cb), clock)
+    }
+
+    #[test]
+    fn test_reserve_then_execute() {
+        let cb = SharedCircuitBreaker::new(CircuitBreaker::builder().build().unwrap());
+        let permit = cb.reserve().expect("closed breaker admits");
+        assert_eq!(cb.metrics().admitted, 1);
+
+        let res = permit.execute(|| Ok::<_, ()>(42));
+        assert!(matches!(res, Ok(42)));
+        let metrics = cb.metrics();
+        assert_eq!((metrics.admitted, metrics.successes), (1, 1));
+
+        let (cb, _) = half_open_breaker();
+        let permit = cb.reserve().unwrap();
+        let _ = permit.execute(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::Closed);
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert!(cb.reserve().is_none());
+        assert_eq!(cb.metrics().rejected, 1);
+    }
+
+    #[test]
+    fn test_dropped_permit_releases_reservation() {
+        let (cb, clock) = half_open_breaker();
+        let admitted = cb.metrics().admitted;
+
+        drop(cb.reserve().unwrap());
+        assert_eq!(cb.metrics().admitted, admitted);
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        // With the probe handed back the breaker counts as idle again.
+        clock.advance(Duration::from_secs(5));
+        assert!(cb.reserve().is_none());
+        assert_eq!(cb.state(), State::Open);
+    }
+}
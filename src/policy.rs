@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+/// How failures in the closed state are counted against
+/// `failure_threshold`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TripPolicy {
+    /// Trip once consecutive failures exceed the threshold. Any success
+    /// resets the count.
+    #[default]
+    Consecutive,
+    /// Trip once failures within the trailing `window` exceed the
+    /// threshold, however many successes happened in between. Catches
+    /// dependencies that fail intermittently but often.
+    Windowed { window: Duration },
+    /// Trip on whichever comes first: consecutive failures exceeding the
+    /// threshold, or more than `total` failures within the trailing
+    /// `window`. Catches both sudden outages and slow-burn intermittent
+    /// failures; only the streak is reset by a success.
+    ConsecutiveOrWindowed { window: Duration, total: u32 },
+    /// Trip once an exponentially weighted failure score exceeds the
+    /// threshold. Every failure adds one to the score, which halves every
+    /// `half_life`, so recent failures weigh the most and old ones fade out
+    /// gradually instead of falling off the end of a window. Successes do
+    /// not lower it; see [`CircuitBreaker::failure_score`](crate::CircuitBreaker::failure_score).
+    Decaying { half_life: Duration },
+}
+
+/// How important a call is, see
+/// [`CircuitBreaker::call_with_priority`](crate::CircuitBreaker::call_with_priority).
+/// Calls made any other way are [`Priority::Normal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// What a half-open breaker does when probes stop arriving, see
+/// [`CircuitBreakerBuilder::half_open_idle`](crate::CircuitBreakerBuilder::half_open_idle).
+///
+/// The policy is applied when the next call arrives, so a breaker nobody
+/// calls keeps reporting half-open until then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HalfOpenIdle {
+    /// Stay half-open until enough probes succeed or one fails.
+    #[default]
+    Stay,
+    /// Close once the breaker has been half-open for `after`, if at least
+    /// one probe succeeded and none failed, rather than waiting for the
+    /// full `half_open_successes`.
+    Close { after: Duration },
+    /// Reopen once the breaker has been half-open for `after` without
+    /// admitting a single probe, so recovery is checked again from scratch.
+    Reopen { after: Duration },
+}
+
+/// Whether calls already running when the breaker is tripped by hand still
+/// count, see
+/// [`CircuitBreakerBuilder::in_flight_after_trip`](crate::CircuitBreakerBuilder::in_flight_after_trip).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InFlightAfterTrip {
+    /// Their results are counted in the metrics like those of any call
+    /// admitted before the breaker left the state it was in, without moving
+    /// it or restarting its recovery time.
+    #[default]
+    Count,
+    /// Their results go back to the callers but are not counted while the
+    /// breaker stays open, so the manual decision is not muddied by calls
+    /// made before it. Calls still running once it has moved on to
+    /// half-open are counted in the metrics again.
+    Ignore,
+}
+
+/// What `call` returns for a call the breaker did not run, see
+/// [`CircuitBreakerBuilder::rejection_policy`](crate::CircuitBreakerBuilder::rejection_policy).
+///
+/// `call` always returns `Result<Option<R>, CircuitBreakerError<E>>`; the
+/// policy only picks which of its shapes a call that did not run gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RejectionPolicy {
+    /// `Err(CircuitBreakerError::Rejected(reason))`. The call that only
+    /// moves the breaker to half-open returns `Ok(None)`.
+    #[default]
+    Error,
+    /// `Ok(None)` for every call that did not run, so callers only need to
+    /// handle errors from calls that did.
+    ReturnNone,
+    /// `Ok(Some(value))` from
+    /// [`default_when_open`](crate::CircuitBreakerBuilder::default_when_open)
+    /// or [`fallback_when_open`](crate::CircuitBreakerBuilder::fallback_when_open),
+    /// including for the call that moves the breaker to half-open. Calls
+    /// whose result type does not match the configured value behave as
+    /// with [`RejectionPolicy::Error`].
+    ReturnDefault,
+}
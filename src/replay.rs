@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use crate::breaker::{Admission, Outcome};
+use crate::{CircuitBreaker, FakeClock, State};
+
+/// Drives a breaker through a scripted sequence of call outcomes, checking
+/// its state along the way, for state-machine tests that read like the
+/// scenario they cover, e.g. `Replay::new(&mut cb).fail(3).expect_state(State::Open)`
+/// followed by `.advance(recovery).succeed(2).expect_state(State::Closed)`.
+///
+/// Nothing runs and nothing sleeps: each scripted call is admitted as a
+/// real call would be and then completed with the given outcome and
+/// [`latency`](Self::latency). A call the breaker does not admit, e.g.
+/// while it is open, completes nothing, as in real use; the call that
+/// moves it to half-open is one of those. Time only moves on
+/// [`advance`](Self::advance).
+///
+/// Failed expectations panic, naming the step of the script that failed.
+pub struct Replay<'a> {
+    cb: &'a mut CircuitBreaker<FakeClock>,
+    latency: Duration,
+    steps: usize,
+    rejected: u64,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(cb: &'a mut CircuitBreaker<FakeClock>) -> Self {
+        Replay {
+            cb,
+            latency: Duration::ZERO,
+            steps: 0,
+            rejected: 0,
+        }
+    }
+
+    /// How long the calls scripted after this take, e.g. to cross a
+    /// slow-call threshold. Zero by default. Timed-out calls always take
+    /// the breaker's timeout.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// `n` calls that succeed.
+    pub fn succeed(self, n: usize) -> Self {
+        self.calls(n, || Outcome::Ok(()))
+    }
+
+    /// `n` calls that return an error.
+    pub fn fail(self, n: usize) -> Self {
+        self.calls(n, || Outcome::Err("replayed failure"))
+    }
+
+    /// `n` calls that time out.
+    pub fn time_out(self, n: usize) -> Self {
+        self.calls(n, || Outcome::Timeout)
+    }
+
+    fn calls(mut self, n: usize, outcome: impl Fn() -> Outcome<(), &'static str>) -> Self {
+        self.steps += 1;
+        for _ in 0..n {
+            let rejected = self.cb.metrics().rejected;
+            let admission = self.cb.admit::<(), &'static str>();
+            let Ok(Admission::Run {
+                slots, admitted_in, ..
+            }) = admission
+            else {
+                self.rejected += self.cb.metrics().rejected - rejected;
+                continue;
+            };
+            let outcome = outcome();
+            let elapsed = match outcome {
+                Outcome::Timeout => self.cb.call_timeout(),
+                _ => self.latency,
+            };
+            let _ = self.cb.complete(outcome, elapsed, admitted_in, None);
+            drop(slots);
+        }
+        self
+    }
+
+    /// Moves the breaker's clock forward by `by`.
+    pub fn advance(mut self, by: Duration) -> Self {
+        self.steps += 1;
+        self.cb.clock().advance(by);
+        self
+    }
+
+    /// Checks the breaker is in `state`.
+    #[track_caller]
+    pub fn expect_state(mut self, state: State) -> Self {
+        self.steps += 1;
+        let actual = self.cb.state();
+        assert_eq!(
+            actual, state,
+            "step {} of the replay: expected the breaker to be {state:?}, but it is {actual:?}",
+            self.steps
+        );
+        self
+    }
+
+    /// Checks that `n` scripted calls were rejected since the last check,
+    /// or since the replay started.
+    #[track_caller]
+    pub fn expect_rejected(mut self, n: u64) -> Self {
+        self.steps += 1;
+        assert_eq!(
+            self.rejected, n,
+            "step {} of the replay: expected {n} rejected calls, but {} were",
+            self.steps, self.rejected
+        );
+        self.rejected = 0;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker<FakeClock> {
+        CircuitBreaker::builder()
+            .failure_threshold(2)
+            .timeout(Duration::from_millis(100))
+            .recovery_time(Duration::from_secs(5))
+            .half_open_successes(2)
+            .build_with_clock(FakeClock::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_replay_trip_and_recovery() {
+        let mut cb = breaker();
+        Replay::new(&mut cb)
+            .fail(2)
+            .expect_state(State::Closed)
+            .fail(1)
+            .expect_state(State::Open)
+            .succeed(2)
+            .expect_rejected(2)
+            .advance(Duration::from_secs(5))
+            .succeed(1)
+            .expect_state(State::HalfOpen)
+            .expect_rejected(0)
+            .succeed(2)
+            .expect_state(State::Closed)
+            .time_out(1)
+            .expect_state(State::Open);
+        let metrics = cb.metrics();
+        assert_eq!(
+            (metrics.successes, metrics.failures, metrics.timeouts),
+            (2, 3, 1)
+        );
+    }
+
+    #[test]
+    fn test_replay_latency_counts_slow_calls() {
+        let mut cb = CircuitBreaker::builder()
+            .slow_call_threshold(Duration::from_millis(50))
+            .build_with_clock(FakeClock::new())
+            .unwrap();
+        Replay::new(&mut cb)
+            .succeed(1)
+            .latency(Duration::from_millis(80))
+            .succeed(2)
+            .expect_state(State::Closed);
+        assert_eq!(cb.metrics().slow_calls, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "step 2 of the replay: expected the breaker to be Open")]
+    fn test_replay_names_the_failed_step() {
+        let mut cb = breaker();
+        Replay::new(&mut cb).fail(1).expect_state(State::Open);
+    }
+}
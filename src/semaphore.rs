@@ -0,0 +1,120 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::breaker::InFlightGuard;
+
+/// A bound on the calls running at once across every breaker given a
+/// clone of the same `Arc<Semaphore>`, see
+/// [`CircuitBreakerBuilder::semaphore`](crate::CircuitBreakerBuilder::semaphore),
+/// e.g. for dependencies that share one connection pool.
+///
+/// A call takes a permit when it is admitted and gives it back when it
+/// returns. A call admitted while every permit is taken is rejected with
+/// [`RejectionReason::SemaphoreExhausted`](crate::RejectionReason::SemaphoreExhausted)
+/// instead of waiting.
+pub struct Semaphore {
+    permits: usize,
+    in_use: Arc<AtomicUsize>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits,
+            in_use: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Permits not taken by a running call right now.
+    pub fn available(&self) -> usize {
+        self.permits
+            .saturating_sub(self.in_use.load(Ordering::SeqCst))
+    }
+
+    /// Takes a permit until the returned guard is dropped, or returns
+    /// `None` if there is none left.
+    pub(crate) fn try_acquire(&self) -> Option<InFlightGuard> {
+        self.in_use
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.permits).then_some(n + 1)
+            })
+            .ok()?;
+        Some(InFlightGuard::counted(&self.in_use))
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.permits)
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{CircuitBreaker, CircuitBreakerError, RejectionReason, SharedCircuitBreaker};
+
+    fn breaker(semaphore: &Arc<Semaphore>) -> SharedCircuitBreaker {
+        SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .timeout(Duration::from_secs(5))
+                .semaphore(Arc::clone(semaphore))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_breakers_share_one_limit() {
+        let semaphore = Arc::new(Semaphore::new(2));
+        let (a, b) = (breaker(&semaphore), breaker(&semaphore));
+
+        // One call held running on each breaker takes both permits.
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+        let calls: Vec<_> = [a.clone(), b.clone()]
+            .into_iter()
+            .map(|cb| {
+                let gate = Arc::clone(&gate);
+                thread::spawn(move || {
+                    cb.call(move || {
+                        drop(gate.read().unwrap());
+                        Ok::<_, ()>(())
+                    })
+                })
+            })
+            .collect();
+        while semaphore.available() > 0 {
+            thread::yield_now();
+        }
+
+        for cb in [&a, &b] {
+            assert!(matches!(
+                cb.call(|| Ok::<_, ()>(())),
+                Err(CircuitBreakerError::Rejected(
+                    RejectionReason::SemaphoreExhausted
+                ))
+            ));
+        }
+        assert_eq!((a.metrics().rejected, b.metrics().rejected), (1, 1));
+
+        drop(closed);
+        for call in calls {
+            assert!(matches!(call.join().unwrap(), Ok(Some(()))));
+        }
+        assert_eq!(semaphore.available(), 2);
+        assert!(matches!(a.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+    }
+}
@@ -0,0 +1,1105 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::{fmt, io};
+
+use crate::breaker::{time, Admission, InFlightGuard};
+use crate::metrics::PublishedMetrics;
+use crate::{
+    BuildError, CircuitBreaker, CircuitBreakerError, Clock, Config, LateResult, Metrics, Priority,
+    RelaxedMetrics, RunsOnWorkerThread, SelfTestReport, State, SystemClock, TripReason,
+};
+
+/// A [`CircuitBreaker`] that can be cloned and used from many threads at
+/// once.
+///
+/// `SharedCircuitBreaker` is `Send + Sync`, and so is `CircuitBreaker`
+/// itself: every hook the builder accepts must be `Send + Sync + 'static`,
+/// so it can be stored in an `Arc` and moved across threads or async tasks.
+///
+/// The breaker is locked only to decide whether a call may run and to
+/// record its outcome, never while the protected function executes, so
+/// concurrent calls do not wait on each other.
+pub struct SharedCircuitBreaker<C = SystemClock, D = ()> {
+    inner: Arc<Mutex<CircuitBreaker<C, D>>>,
+    published: Arc<PublishedMetrics>,
+    /// Wakes calls waiting in the half-open queue.
+    probe_freed: Arc<Condvar>,
+}
+
+impl<C: Clock, D> SharedCircuitBreaker<C, D> {
+    pub fn new(breaker: CircuitBreaker<C, D>) -> Self {
+        SharedCircuitBreaker {
+            published: breaker.published_metrics(),
+            inner: Arc::new(Mutex::new(breaker)),
+            probe_freed: Arc::new(Condvar::new()),
+        }
+    }
+
+    /// Locks the breaker. A panic while it was locked cannot leave it
+    /// half-updated in a way that matters, so poisoning is ignored.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, CircuitBreaker<C, D>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// See [`CircuitBreaker::call`].
+    pub fn call<F, R, E>(&self, func: F) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(None, Priority::Normal, func)
+    }
+
+    /// See [`CircuitBreaker::call_with_priority`]. A call whose priority is
+    /// too low to probe does not wait in the half-open queue.
+    pub fn call_with_priority<F, R, E>(
+        &self,
+        priority: Priority,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(None, priority, func)
+    }
+
+    /// See [`CircuitBreaker::call_labeled`].
+    pub fn call_labeled<F, R, E>(
+        &self,
+        label: &str,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_with_label(Some(label), Priority::Normal, func)
+    }
+
+    /// See [`CircuitBreaker::call_with_state`]. The state is read under the
+    /// same lock that records the call's outcome, or that rejected it, so no
+    /// other caller's call can land in between.
+    pub fn call_with_state<F, R, E>(
+        &self,
+        func: F,
+    ) -> (Result<Option<R>, CircuitBreakerError<E>>, State)
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_and_state(None, Priority::Normal, func)
+    }
+
+    fn call_with_label<F, R, E>(
+        &self,
+        label: Option<&str>,
+        priority: Priority,
+        func: F,
+    ) -> Result<Option<R>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        self.call_and_state(label, priority, func).0
+    }
+
+    fn call_and_state<F, R, E>(
+        &self,
+        label: Option<&str>,
+        priority: Priority,
+        func: F,
+    ) -> (Result<Option<R>, CircuitBreakerError<E>>, State)
+    where
+        F: RunsOnWorkerThread<R, E>,
+        R: Send + 'static,
+        E: Send + 'static,
+    {
+        let (inline, slots, admitted_in, runner) = {
+            let mut breaker = self.lock();
+            if breaker.may_probe(priority) {
+                breaker = self.wait_for_probe_slot(breaker);
+            }
+            match breaker.admit_with_priority(priority) {
+                Ok(Admission::Skip(value)) => return (Ok(value), breaker.state()),
+                Ok(Admission::Run {
+                    inline,
+                    slots,
+                    admitted_in,
+                }) => (inline, slots, admitted_in, breaker.runner()),
+                Err(e) => return (Err(e), breaker.state()),
+            }
+        };
+        let (outcome, elapsed) = runner.run(inline, func);
+        let (res, state) = {
+            let mut breaker = self.lock();
+            let res = breaker.complete(outcome, elapsed, admitted_in, label);
+            (res, breaker.state())
+        };
+        self.release_slots(slots);
+        (res.map(Some), state)
+    }
+
+    /// See [`CircuitBreaker::call_and_then`]. `check` runs without the
+    /// breaker locked.
+    pub fn call_and_then<F, G, R, T, E>(
+        &self,
+        func: F,
+        check: G,
+    ) -> Result<Option<T>, CircuitBreakerError<E>>
+    where
+        F: RunsOnWorkerThread<R, E>,
+        G: FnOnce(R) -> Result<T, E>,
+        R: Send + 'static,
+        T: 'static,
+        E: Send + 'static,
+    {
+        let (admission, runner) = {
+            let mut breaker = self.wait_for_probe_slot(self.lock());
+            (breaker.admit::<T, E>()?, breaker.runner())
+        };
+        match admission {
+            Admission::Skip(value) => Ok(value),
+            Admission::Run {
+                inline,
+                slots,
+                admitted_in,
+            } => {
+                let (outcome, elapsed) = runner.run(inline, func);
+                let outcome = outcome.and_then(check);
+                let res = self.lock().complete(outcome, elapsed, admitted_in, None);
+                self.release_slots(slots);
+                res.map(Some)
+            }
+        }
+    }
+
+    /// Waits in the half-open queue, if there is one with room, while every
+    /// probe slot is taken. Returns once a slot frees up, the breaker
+    /// leaves half-open or the wait runs out; `admit` then decides.
+    fn wait_for_probe_slot<'a>(
+        &self,
+        mut breaker: MutexGuard<'a, CircuitBreaker<C, D>>,
+    ) -> MutexGuard<'a, CircuitBreaker<C, D>> {
+        let Some((capacity, max_wait)) = breaker.half_open_queue else {
+            return breaker;
+        };
+        if !breaker.probes_busy() || breaker.half_open_queued >= capacity {
+            return breaker;
+        }
+        let queued_at = Instant::now();
+        let deadline = queued_at + max_wait;
+        breaker.half_open_queued += 1;
+        while breaker.probes_busy() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            breaker = self
+                .probe_freed
+                .wait_timeout(breaker, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        breaker.half_open_queued -= 1;
+        breaker.record_queue_wait(queued_at.elapsed());
+        breaker
+    }
+
+    /// Releases a finished call's concurrency slots, if it had any, and
+    /// wakes the calls queued for a probe slot. Done under the lock, so a
+    /// caller cannot miss the wakeup between checking for a slot and
+    /// starting to wait.
+    pub(crate) fn release_slots(&self, slots: Vec<InFlightGuard>) {
+        if !slots.is_empty() {
+            let _breaker = self.lock();
+            drop(slots);
+            self.probe_freed.notify_all();
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.lock().state()
+    }
+
+    /// See [`CircuitBreaker::measure`]. `func` runs without holding the
+    /// lock, so it does not hold up other calls.
+    pub fn measure<F, R>(&self, func: F) -> Duration
+    where
+        F: FnOnce() -> R,
+    {
+        let elapsed = time(func);
+        self.lock().record_latency(elapsed);
+        elapsed
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.lock().metrics()
+    }
+
+    /// The counters of [`SharedCircuitBreaker::metrics`], read without
+    /// locking the breaker, so frequent reads, e.g. for a dashboard polled
+    /// while the breaker is busy, never make calls wait. The breaker copies
+    /// them out as calls are admitted, rejected and completed; each is read
+    /// on its own, so they may not quite agree with each other, see
+    /// [`RelaxedMetrics`].
+    pub fn metrics_relaxed(&self) -> RelaxedMetrics {
+        self.published.load()
+    }
+
+    /// See [`CircuitBreaker::would_admit`].
+    pub fn would_admit(&self) -> bool {
+        self.lock().would_admit()
+    }
+
+    /// Runs `f` on the breaker's [`data`](CircuitBreaker::data), with the
+    /// breaker locked, so `f` should be quick and must not call back into
+    /// the breaker.
+    pub fn with_data<R>(&self, f: impl FnOnce(&mut D) -> R) -> R {
+        f(self.lock().data_mut())
+    }
+
+    /// See [`CircuitBreaker::is_flapping`].
+    pub fn is_flapping(&self) -> bool {
+        self.lock().is_flapping()
+    }
+
+    /// See [`CircuitBreaker::failed_recovery_cycles`].
+    pub fn failed_recovery_cycles(&self) -> u32 {
+        self.lock().failed_recovery_cycles()
+    }
+
+    /// See [`CircuitBreaker::last_trip_reason`].
+    pub fn last_trip_reason(&self) -> Option<TripReason> {
+        self.lock().last_trip_reason()
+    }
+
+    /// See [`CircuitBreaker::effective_recovery_time`].
+    pub fn effective_recovery_time(&self) -> Duration {
+        self.lock().effective_recovery_time()
+    }
+
+    /// See [`CircuitBreaker::in_flight`].
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight()
+    }
+
+    /// See [`CircuitBreaker::orphaned_workers`].
+    pub fn orphaned_workers(&self) -> usize {
+        self.lock().orphaned_workers()
+    }
+
+    /// See [`CircuitBreaker::reap_finished`].
+    pub fn reap_finished(&self) {
+        self.lock().reap_finished();
+    }
+
+    /// See [`CircuitBreaker::drain_late_results`].
+    pub fn drain_late_results(&self) -> Vec<LateResult> {
+        self.lock().drain_late_results()
+    }
+
+    /// See [`CircuitBreaker::config`].
+    pub fn config(&self) -> Config {
+        self.lock().config()
+    }
+
+    /// See [`CircuitBreaker::reconfigure`]. Calls already running keep the
+    /// timeout they started with.
+    pub fn reconfigure(&self, config: Config) -> Result<(), BuildError> {
+        self.lock().reconfigure(config)
+    }
+
+    /// See [`CircuitBreaker::status_line`].
+    pub fn status_line(&self) -> String {
+        self.lock().status_line()
+    }
+
+    /// See [`CircuitBreaker::to_dot`].
+    pub fn to_dot(&self) -> String {
+        self.lock().to_dot()
+    }
+
+    /// See [`CircuitBreaker::self_test`].
+    pub fn self_test(&self) -> SelfTestReport {
+        self.lock().self_test()
+    }
+
+    /// See [`CircuitBreaker::flush_window`].
+    pub fn flush_window(&self) {
+        self.lock().flush_window()
+    }
+
+    /// See [`CircuitBreaker::flush_telemetry`].
+    pub fn flush_telemetry(&self) {
+        self.lock().flush_telemetry()
+    }
+
+    /// See [`CircuitBreaker::set_enabled`].
+    pub fn set_enabled(&self, enabled: bool) {
+        self.lock().set_enabled(enabled)
+    }
+
+    /// See [`CircuitBreaker::trip`].
+    pub fn trip(&self) {
+        self.lock().trip()
+    }
+
+    /// See [`CircuitBreaker::reset`].
+    pub fn reset(&self) {
+        self.lock().reset()
+    }
+
+    /// See [`CircuitBreaker::restart_recovery`].
+    pub fn restart_recovery(&self) {
+        self.lock().restart_recovery()
+    }
+
+    /// See [`CircuitBreaker::poll_recovery`].
+    pub fn poll_recovery(&self) -> bool {
+        self.lock().poll_recovery()
+    }
+
+    /// Starts a thread that moves the breaker to half-open the moment its
+    /// recovery time elapses, through [`CircuitBreaker::poll_recovery`],
+    /// instead of waiting for the next call. The thread sleeps until the
+    /// breaker may recover, or for `interval` while it is not open, and
+    /// stops once the returned timer or every clone of the breaker is
+    /// dropped. Fails only if the thread cannot be spawned.
+    ///
+    /// It also flushes call counts held back by
+    /// [`batch_telemetry`](crate::CircuitBreakerBuilder::batch_telemetry)
+    /// once their interval passes, so an idle breaker reports them on time.
+    pub fn spawn_recovery_timer(&self, interval: Duration) -> io::Result<RecoveryTimer>
+    where
+        C: Send + 'static,
+        D: Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let breaker = Arc::downgrade(&self.inner);
+        let thread = thread::Builder::new()
+            .name("cb-recovery-timer".to_string())
+            .spawn(move || {
+                let mut wait = Duration::ZERO;
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                    let Some(breaker) = Weak::upgrade(&breaker) else {
+                        return;
+                    };
+                    let mut breaker = breaker.lock().unwrap_or_else(PoisonError::into_inner);
+                    breaker.poll_recovery();
+                    breaker.flush_due_telemetry();
+                    // A recovery that is due but did not happen, e.g. because
+                    // it was vetoed, is retried after `interval`.
+                    wait = breaker
+                        .time_until_retry()
+                        .filter(|until| !until.is_zero())
+                        .map_or(interval, |until| until.min(interval));
+                    if let Some(due) = breaker.telemetry_due_in() {
+                        wait = wait.min(due);
+                    }
+                }
+            })?;
+        Ok(RecoveryTimer {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Stops the thread started by
+/// [`SharedCircuitBreaker::spawn_recovery_timer`] when dropped.
+#[derive(Debug)]
+pub struct RecoveryTimer {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for RecoveryTimer {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Written out so `C` needs neither `Clone` nor `Debug`.
+impl<C, D> Clone for SharedCircuitBreaker<C, D> {
+    fn clone(&self) -> Self {
+        SharedCircuitBreaker {
+            inner: Arc::clone(&self.inner),
+            published: Arc::clone(&self.published),
+            probe_freed: Arc::clone(&self.probe_freed),
+        }
+    }
+}
+
+impl<C: Clock, D> fmt::Debug for SharedCircuitBreaker<C, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedCircuitBreaker")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<C: Clock, D> From<CircuitBreaker<C, D>> for SharedCircuitBreaker<C, D> {
+    fn from(breaker: CircuitBreaker<C, D>) -> Self {
+        SharedCircuitBreaker::new(breaker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<CircuitBreaker>();
+        assert_send_sync::<SharedCircuitBreaker>();
+    }
+
+    #[test]
+    fn test_call_with_state_reads_the_state_its_call_left() {
+        use std::sync::{Arc, Barrier};
+
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(3)
+                .timeout(Duration::from_secs(1))
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+        );
+
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (cb, barrier) = (cb.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    cb.call_with_state(|| Err::<(), _>("boom")).1
+                })
+            })
+            .collect();
+        let states: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(states.iter().filter(|&&s| s == State::Closed).count(), 3);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_concurrent_calls_do_not_serialize() {
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .timeout(Duration::from_secs(1))
+                .build()
+                .unwrap(),
+        );
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cb = cb.clone();
+                thread::spawn(move || {
+                    cb.call(|| {
+                        thread::sleep(Duration::from_millis(100));
+                        Ok::<_, ()>(())
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(matches!(handle.join().unwrap(), Ok(Some(()))));
+        }
+        assert!(start.elapsed() < Duration::from_millis(350));
+        assert_eq!(cb.metrics().successes, 4);
+    }
+
+    #[test]
+    fn test_concurrent_failures_open_exactly_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Barrier;
+
+        let opened = Arc::new(AtomicUsize::new(0));
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(2)
+                .recovery_time(Duration::from_secs(60))
+                .on_state_change({
+                    let opened = Arc::clone(&opened);
+                    move |t| {
+                        if t.to == State::Open {
+                            opened.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let barrier = Arc::new(Barrier::new(32));
+        let handles: Vec<_> = (0..32)
+            .map(|_| {
+                let cb = cb.clone();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    let _ = cb.call(|| Err::<(), _>("boom"));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_concurrent_timeouts_open_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Barrier;
+
+        let opened = Arc::new(AtomicUsize::new(0));
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .timeout(Duration::from_millis(20))
+                .recovery_time(Duration::from_secs(60))
+                .on_state_change({
+                    let opened = Arc::clone(&opened);
+                    move |t| {
+                        if t.to == State::Open {
+                            opened.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let barrier = Arc::new(Barrier::new(10));
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let cb = cb.clone();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    cb.call(|| {
+                        thread::sleep(Duration::from_millis(100));
+                        Ok::<_, ()>(())
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(matches!(
+                handle.join().unwrap(),
+                Err(CircuitBreakerError::TimeoutError)
+            ));
+        }
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.state, State::Open);
+        assert_eq!(metrics.failure_count, 1);
+        assert_eq!(metrics.timeouts, 10);
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_concurrent_errors_do_not_move_a_tripped_breaker() {
+        use std::sync::mpsc;
+
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_millis(200))
+                .build_with_clock(clock.clone())
+                .unwrap(),
+        );
+        // Two calls admitted while closed, which fail once released.
+        let (releases, handles): (Vec<_>, Vec<_>) = (0..2)
+            .map(|_| {
+                let (release, held) = mpsc::channel::<()>();
+                let cb = cb.clone();
+                let handle = thread::spawn(move || {
+                    cb.call(move || {
+                        held.recv().unwrap();
+                        Err::<(), _>("late")
+                    })
+                });
+                (release, handle)
+            })
+            .unzip();
+        while cb.in_flight() < 2 {
+            thread::yield_now();
+        }
+        for _ in 0..2 {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        assert_eq!(cb.state(), State::Open);
+
+        let mut late = releases.into_iter().zip(handles);
+        let mut finish = || {
+            let (release, handle) = late.next().unwrap();
+            release.send(()).unwrap();
+            assert!(matches!(
+                handle.join().unwrap(),
+                Err(CircuitBreakerError::FunctionError("late"))
+            ));
+        };
+        // Landing while open, it does not restart the recovery time.
+        clock.advance(Duration::from_millis(150));
+        finish();
+        clock.advance(Duration::from_millis(100));
+        assert!(cb.would_admit());
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        // Landing while half-open, it is not taken for a failed probe.
+        finish();
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert_eq!(cb.metrics().failures, 4);
+    }
+
+    #[test]
+    fn test_simultaneous_timeouts_coalesce() {
+        use std::sync::Barrier;
+
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .timeout(Duration::from_millis(20))
+                .timeout_threshold(1)
+                .timeout_coalescing(Duration::from_millis(200))
+                .build()
+                .unwrap(),
+        );
+        let slow = || {
+            thread::sleep(Duration::from_millis(60));
+            Ok::<_, ()>(())
+        };
+
+        let barrier = Arc::new(Barrier::new(5));
+        let handles: Vec<_> = (0..5)
+            .map(|_| {
+                let cb = cb.clone();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    cb.call(slow)
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().is_err());
+        }
+        // Five timeouts, counted as the one the threshold tolerates.
+        let metrics = cb.metrics();
+        assert_eq!(metrics.timeouts, 5);
+        assert_eq!(metrics.state, State::Closed);
+
+        thread::sleep(Duration::from_millis(250));
+        let _ = cb.call(slow);
+        assert_eq!(cb.state(), State::Open);
+    }
+
+    #[test]
+    fn test_half_open_queue_tiers() {
+        use std::sync::mpsc;
+
+        use crate::{FakeClock, RejectionReason};
+
+        let clock = FakeClock::new();
+        let mut breaker = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(3)
+            .half_open_max_concurrent(1)
+            .half_open_queue(1, Duration::from_secs(5))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while breaker.state() != State::Open {
+            let _ = breaker.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        assert_eq!(breaker.state(), State::HalfOpen);
+        let cb = SharedCircuitBreaker::new(breaker);
+
+        // The first caller takes the only probe slot and holds it.
+        let (release, held) = mpsc::channel::<()>();
+        let probe = thread::spawn({
+            let cb = cb.clone();
+            move || {
+                cb.call(move || {
+                    held.recv().unwrap();
+                    Ok::<_, ()>("probe")
+                })
+            }
+        });
+        while cb
+            .lock()
+            .probes_in_flight
+            .load(std::sync::atomic::Ordering::SeqCst)
+            == 0
+        {
+            thread::yield_now();
+        }
+
+        // The second waits in the queue.
+        let queued = thread::spawn({
+            let cb = cb.clone();
+            move || cb.call(|| Ok::<_, ()>("queued"))
+        });
+        while cb.lock().half_open_queued == 0 {
+            thread::yield_now();
+        }
+
+        // The third finds the queue full and is turned away.
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>("rejected")),
+            Err(CircuitBreakerError::Rejected(RejectionReason::ProbesBusy))
+        ));
+
+        release.send(()).unwrap();
+        assert!(matches!(probe.join().unwrap(), Ok(Some("probe"))));
+        assert!(matches!(queued.join().unwrap(), Ok(Some("queued"))));
+        let metrics = cb.metrics();
+        assert_eq!((metrics.successes, metrics.rejected), (2, 1));
+    }
+
+    #[test]
+    fn test_queue_wait_is_not_execution_time() {
+        use std::sync::mpsc;
+
+        use crate::FakeClock;
+
+        let clock = FakeClock::new();
+        let mut breaker = CircuitBreaker::builder()
+            .failure_threshold(1)
+            .recovery_time(Duration::from_secs(1))
+            .half_open_successes(3)
+            .half_open_max_concurrent(1)
+            .half_open_queue(1, Duration::from_secs(5))
+            .slow_call_threshold(Duration::from_millis(50))
+            .build_with_clock(clock.clone())
+            .unwrap();
+        while breaker.state() != State::Open {
+            let _ = breaker.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        let cb = SharedCircuitBreaker::new(breaker);
+
+        let (release, held) = mpsc::channel::<()>();
+        let probe = thread::spawn({
+            let cb = cb.clone();
+            move || cb.call(move || held.recv().map_err(drop))
+        });
+        while cb.in_flight() == 0 {
+            thread::yield_now();
+        }
+        let queued = thread::spawn({
+            let cb = cb.clone();
+            move || cb.call(|| Ok::<_, ()>(()))
+        });
+        while cb.lock().half_open_queued == 0 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(80));
+        release.send(()).unwrap();
+        assert!(matches!(probe.join().unwrap(), Ok(Some(()))));
+        assert!(matches!(queued.join().unwrap(), Ok(Some(()))));
+
+        let metrics = cb.metrics();
+        // Only the probe that held the slot ran slowly.
+        assert_eq!((metrics.successes, metrics.slow_calls), (2, 1));
+        assert_eq!(metrics.queue_wait.count(), 1);
+        let (bound, _) = metrics
+            .queue_wait
+            .buckets()
+            .find(|&(_, count)| count > 0)
+            .unwrap();
+        assert!(bound.is_none_or(|bound| bound >= Duration::from_millis(50)));
+        assert_eq!(metrics.latency_by_outcome.success.count(), 2);
+    }
+
+    #[test]
+    fn test_would_admit_while_probes_busy() {
+        use crate::{FakeClock, RejectionReason};
+
+        let clock = FakeClock::new();
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(1))
+                .half_open_successes(2)
+                .half_open_max_concurrent(1)
+                .build_with_clock(clock.clone())
+                .unwrap(),
+        );
+        cb.trip();
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(cb.call(|| Ok::<_, ()>(())), Ok(None)));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert!(cb.would_admit());
+
+        let probe = cb.start_call().unwrap();
+        assert!(!cb.would_admit());
+        assert!(matches!(
+            cb.call(|| Ok::<_, ()>(())),
+            Err(CircuitBreakerError::Rejected(RejectionReason::ProbesBusy))
+        ));
+        assert!(probe.success().is_ok());
+        assert!(cb.would_admit());
+    }
+
+    #[test]
+    fn test_half_open_limit_is_stricter_than_closed() {
+        use std::sync::{mpsc, RwLock};
+
+        use crate::{FakeClock, RejectionReason};
+
+        // Starts six calls at once, all held until the gate opens, and
+        // checks that only `runs` of them get to run.
+        fn burst(cb: &SharedCircuitBreaker<FakeClock>, runs: usize) -> Vec<RejectionReason> {
+            let gate = Arc::new(RwLock::new(()));
+            let closed = gate.write().unwrap();
+            let (done, results) = mpsc::channel();
+            for _ in 0..6 {
+                let (cb, gate, done) = (cb.clone(), Arc::clone(&gate), done.clone());
+                thread::spawn(move || {
+                    let result = cb.call(move || {
+                        drop(gate.read().unwrap());
+                        Ok::<_, ()>(())
+                    });
+                    done.send(result).unwrap();
+                });
+            }
+            let wait = Duration::from_secs(5);
+            let rejected = (runs..6)
+                .map(|_| match results.recv_timeout(wait).unwrap() {
+                    Err(CircuitBreakerError::Rejected(reason)) => reason,
+                    other => panic!("expected a rejection, got {other:?}"),
+                })
+                .collect();
+            drop(closed);
+            for _ in 0..runs {
+                assert!(matches!(results.recv_timeout(wait).unwrap(), Ok(Some(()))));
+            }
+            rejected
+        }
+
+        let clock = FakeClock::new();
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(1))
+                .half_open_successes(10)
+                .timeout(Duration::from_secs(10))
+                .max_concurrent(State::Closed, 4)
+                .max_concurrent(State::HalfOpen, 1)
+                .build_with_clock(clock.clone())
+                .unwrap(),
+        );
+        assert_eq!(burst(&cb, 4), [RejectionReason::ConcurrencyLimit; 2]);
+
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+        assert_eq!(burst(&cb, 1), [RejectionReason::ProbesBusy; 5]);
+        assert_eq!(cb.state(), State::HalfOpen);
+    }
+
+    #[test]
+    fn test_half_open_probes_go_to_high_priority_calls() {
+        use std::sync::mpsc;
+
+        use crate::{FakeClock, RejectionReason};
+
+        let clock = FakeClock::new();
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(1)
+                .recovery_time(Duration::from_secs(1))
+                .half_open_successes(2)
+                .half_open_max_concurrent(2)
+                .half_open_min_priority(Priority::High)
+                .timeout(Duration::from_secs(10))
+                .build_with_clock(clock.clone())
+                .unwrap(),
+        );
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        clock.advance(Duration::from_secs(1));
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        assert_eq!(cb.state(), State::HalfOpen);
+
+        let (release, held) = mpsc::channel::<()>();
+        let probe = thread::spawn({
+            let cb = cb.clone();
+            move || {
+                cb.call_with_priority(Priority::High, move || {
+                    held.recv().unwrap();
+                    Ok::<_, ()>("probe")
+                })
+            }
+        });
+        while cb.in_flight() == 0 {
+            thread::yield_now();
+        }
+        // A probe slot is still free, but not for these.
+        for priority in [Priority::Low, Priority::Normal] {
+            assert!(matches!(
+                cb.call_with_priority(priority, || Ok::<_, ()>("low")),
+                Err(CircuitBreakerError::Rejected(RejectionReason::LowPriority))
+            ));
+        }
+        assert!(matches!(
+            cb.call_with_priority(Priority::High, || Ok::<_, ()>("high")),
+            Ok(Some("high"))
+        ));
+        release.send(()).unwrap();
+        assert!(matches!(probe.join().unwrap(), Ok(Some("probe"))));
+        assert_eq!(cb.state(), State::Closed);
+
+        // Once closed, priority no longer matters.
+        assert!(matches!(
+            cb.call_with_priority(Priority::Low, || Ok::<_, ()>("low")),
+            Ok(Some("low"))
+        ));
+    }
+
+    #[test]
+    fn test_manual_trip_with_call_in_flight() {
+        use std::sync::mpsc;
+
+        use crate::{InFlightAfterTrip, TripReason};
+
+        // Trips the breaker while a failing call is running and returns the
+        // breaker once that call has finished.
+        fn trip_mid_call(policy: InFlightAfterTrip) -> SharedCircuitBreaker {
+            let cb = SharedCircuitBreaker::new(
+                CircuitBreaker::builder()
+                    .failure_threshold(5)
+                    .recovery_time(Duration::from_secs(60))
+                    .timeout(Duration::from_secs(5))
+                    .in_flight_after_trip(policy)
+                    .build()
+                    .unwrap(),
+            );
+            let (release, held) = mpsc::channel::<()>();
+            let call = thread::spawn({
+                let cb = cb.clone();
+                move || {
+                    cb.call(move || {
+                        held.recv().unwrap();
+                        Err::<(), _>("boom")
+                    })
+                }
+            });
+            while cb.in_flight() == 0 {
+                thread::yield_now();
+            }
+            cb.trip();
+            release.send(()).unwrap();
+            let res = call.join().unwrap();
+            assert!(matches!(
+                res,
+                Err(CircuitBreakerError::FunctionError("boom"))
+            ));
+            assert_eq!(cb.state(), State::Open);
+            assert_eq!(cb.last_trip_reason(), Some(TripReason::Manual));
+            cb
+        }
+
+        let counted = trip_mid_call(InFlightAfterTrip::Count).metrics();
+        assert_eq!((counted.failures, counted.failure_count), (1, 0));
+        let ignored = trip_mid_call(InFlightAfterTrip::Ignore).metrics();
+        assert_eq!((ignored.failures, ignored.failure_count), (0, 0));
+        assert_eq!(ignored.admitted, 1);
+    }
+
+    #[test]
+    fn test_metrics_relaxed_matches_once_settled() {
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .failure_threshold(2)
+                .recovery_time(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+        );
+        let _ = cb.call(|| Ok::<_, ()>(()));
+        while cb.state() != State::Open {
+            let _ = cb.call(|| Err::<(), _>("boom"));
+        }
+        let _ = cb.call(|| Ok::<_, ()>(()));
+
+        let (relaxed, metrics) = (cb.metrics_relaxed(), cb.metrics());
+        assert_eq!(relaxed.state, State::Open);
+        assert_eq!(
+            (
+                relaxed.successes,
+                relaxed.failures,
+                relaxed.admitted,
+                relaxed.rejected
+            ),
+            (
+                metrics.successes,
+                metrics.failures,
+                metrics.admitted,
+                metrics.rejected
+            )
+        );
+        assert_eq!((relaxed.failures, relaxed.rejected), (3, 1));
+    }
+
+    #[test]
+    fn test_recovery_timer_half_opens_without_a_call() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let half_opened = Arc::new(AtomicBool::new(false));
+        let cb = SharedCircuitBreaker::new(
+            CircuitBreaker::builder()
+                .recovery_time(Duration::from_millis(50))
+                .on_half_open({
+                    let half_opened = Arc::clone(&half_opened);
+                    move || half_opened.store(true, Ordering::SeqCst)
+                })
+                .build()
+                .unwrap(),
+        );
+        cb.trip();
+        let started = Instant::now();
+        let timer = cb.spawn_recovery_timer(Duration::from_secs(10)).unwrap();
+        while cb.state() != State::HalfOpen {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(half_opened.load(Ordering::SeqCst));
+        assert_eq!(cb.metrics().admitted, 0);
+
+        // Dropping the timer stops its thread without waiting out the interval.
+        drop(timer);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Write;
+use std::time::Instant;
+
+/// The state a [`CircuitBreaker`](crate::CircuitBreaker) is currently in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum State {
+    /// Calls are rejected until the recovery time has elapsed.
+    Open,
+    /// Calls pass through and failures are counted.
+    Closed,
+    /// Like [`State::Closed`], but failures have crossed the
+    /// [`warning_threshold`](crate::CircuitBreakerBuilder::warning_threshold).
+    /// Calls are still admitted; callers may use this to shed optional work.
+    Degraded,
+    /// A limited number of probe calls are let through to test recovery.
+    HalfOpen,
+    /// Failures crossed the threshold on a breaker configured with
+    /// [`soft_open`](crate::CircuitBreakerBuilder::soft_open): only a share
+    /// of calls is admitted, and another failure opens the breaker.
+    SoftOpen,
+}
+
+impl State {
+    /// The state in snake case, as used in metric labels and
+    /// [`CircuitBreaker::status_line`](crate::CircuitBreaker::status_line).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Degraded => "degraded",
+            State::HalfOpen => "half_open",
+            State::Open => "open",
+            State::SoftOpen => "soft_open",
+        }
+    }
+}
+
+/// What last tripped a breaker, see
+/// [`CircuitBreaker::last_trip_reason`](crate::CircuitBreaker::last_trip_reason).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TripReason {
+    /// Failures exceeded the
+    /// [`failure_threshold`](crate::CircuitBreakerBuilder::failure_threshold),
+    /// counted by the [`TripPolicy`](crate::TripPolicy).
+    FailureThreshold,
+    /// A call timed out past the
+    /// [`timeout_threshold`](crate::CircuitBreakerBuilder::timeout_threshold),
+    /// if any.
+    Timeout,
+    /// An error matched
+    /// [`is_fatal`](crate::CircuitBreakerBuilder::is_fatal).
+    FatalError,
+    /// A half-open or soft-open call failed or timed out.
+    ProbeFailed,
+    /// The breaker sat half-open without probes for too long, per
+    /// [`HalfOpenIdle::Reopen`](crate::HalfOpenIdle::Reopen).
+    HalfOpenIdle,
+    /// [`CircuitBreaker::trip`](crate::CircuitBreaker::trip) was called.
+    Manual,
+}
+
+impl TripReason {
+    /// The reason in snake case, as written to the
+    /// [`transition_log`](crate::CircuitBreakerBuilder::transition_log).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TripReason::FailureThreshold => "failure_threshold",
+            TripReason::Timeout => "timeout",
+            TripReason::FatalError => "fatal_error",
+            TripReason::ProbeFailed => "probe_failed",
+            TripReason::HalfOpenIdle => "half_open_idle",
+            TripReason::Manual => "manual",
+        }
+    }
+}
+
+/// A change from one [`State`] to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateTransition {
+    pub from: State,
+    pub to: State,
+    pub at: Instant,
+}
+
+/// A transition as written to the
+/// [`transition_log`](crate::CircuitBreakerBuilder::transition_log), one
+/// JSON object per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransitionRecord {
+    /// The breaker's name, if one was configured.
+    pub breaker: Option<String>,
+    pub from: State,
+    pub to: State,
+    /// Wall-clock time of the transition, in milliseconds since the Unix
+    /// epoch.
+    pub at: u64,
+    /// Why the breaker tripped, for a move to open or soft-open.
+    pub reason: Option<TripReason>,
+}
+
+impl TransitionRecord {
+    /// The record as a single line of JSON, without the newline. Matches
+    /// what `serde_json` makes of it with the `serde` feature.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"breaker\":");
+        match &self.breaker {
+            Some(name) => push_json_string(&mut json, name),
+            None => json.push_str("null"),
+        }
+        let _ = write!(
+            json,
+            ",\"from\":\"{}\",\"to\":\"{}\",\"at\":{},\"reason\":",
+            self.from.as_str(),
+            self.to.as_str(),
+            self.at
+        );
+        match self.reason {
+            Some(reason) => {
+                let _ = write!(json, "\"{}\"", reason.as_str());
+            }
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    #[test]
+    fn test_state_as_map_key() {
+        let mut seen = HashMap::new();
+        for state in [State::Closed, State::Open, State::HalfOpen, State::Open] {
+            *seen.entry(state).or_insert(0) += 1;
+        }
+        assert_eq!(seen[&State::Open], 2);
+        assert_eq!(seen[&State::Closed], 1);
+        assert!(!seen.contains_key(&State::Degraded));
+
+        let at = Instant::now();
+        let transitions: HashSet<_> = [
+            StateTransition {
+                from: State::Closed,
+                to: State::Open,
+                at,
+            },
+            StateTransition {
+                from: State::Closed,
+                to: State::Open,
+                at,
+            },
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(transitions.len(), 1);
+    }
+
+    #[test]
+    fn test_transition_record_json_escapes_name() {
+        let record = TransitionRecord {
+            breaker: Some("a \"quoted\"\\name\n".to_string()),
+            from: State::HalfOpen,
+            to: State::Open,
+            at: 42,
+            reason: Some(TripReason::ProbeFailed),
+        };
+        let json: serde_json::Value = serde_json::from_str(&record.to_json()).unwrap();
+        assert_eq!(json["breaker"], "a \"quoted\"\\name\n");
+        assert_eq!(json["from"], "half_open");
+        assert_eq!(json["reason"], "probe_failed");
+
+        let unnamed = TransitionRecord {
+            breaker: None,
+            reason: None,
+            ..record
+        };
+        assert_eq!(
+            unnamed.to_json(),
+            r#"{"breaker":null,"from":"half_open","to":"open","at":42,"reason":null}"#
+        );
+    }
+}
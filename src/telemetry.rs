@@ -0,0 +1,592 @@
+//! Breaker telemetry through the `metrics` facade, OpenTelemetry and
+//! `tracing`, when the `metrics`, `opentelemetry` and `tracing` features are
+//! on. Without them these functions do nothing, so call sites need no
+//! `cfg`.
+
+use std::time::{Duration, Instant};
+
+use crate::{CallOutcome, State, StateTransition};
+
+const OUTCOMES: [&str; 4] = ["success", "failure", "timeout", "rejected"];
+
+/// Replaces every character but ASCII letters, digits and `_` with `_`,
+/// and prefixes a leading digit with `_`, so `payments/v2` becomes
+/// `payments_v2`: a name valid even where label values are held to the
+/// rules for Prometheus label names. Meant for
+/// [`metrics_label`](crate::CircuitBreakerBuilder::metrics_label).
+pub fn sanitize_label(name: &str) -> String {
+    let mut label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if label.is_empty() || label.starts_with(|c: char| c.is_ascii_digit()) {
+        label.insert(0, '_');
+    }
+    label
+}
+
+/// Counts a call under `outcome`: `success`, `failure`, `timeout` or
+/// `rejected`.
+pub(crate) fn call(name: Option<&str>, outcome: &'static str) {
+    calls(name, outcome, 1);
+}
+
+fn calls(_name: Option<&str>, _outcome: &'static str, _count: u64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(
+        "circuit_breaker_calls_total",
+        labels(_name, Some(("outcome", _outcome)))
+    )
+    .increment(_count);
+}
+
+/// Call counts held back to be reported together, see
+/// [`batch_telemetry`](crate::CircuitBreakerBuilder::batch_telemetry).
+#[derive(Debug)]
+pub(crate) struct CallBatch {
+    max_pending: u64,
+    interval: Duration,
+    counts: [u64; OUTCOMES.len()],
+    pending: u64,
+    since: Option<Instant>,
+}
+
+impl CallBatch {
+    pub(crate) fn new(max_pending: u64, interval: Duration) -> Self {
+        CallBatch {
+            max_pending,
+            interval,
+            counts: [0; OUTCOMES.len()],
+            pending: 0,
+            since: None,
+        }
+    }
+
+    /// Holds back a call counted under `outcome` at `now`, and says whether
+    /// the batch is due to be flushed.
+    pub(crate) fn add(&mut self, outcome: &'static str, now: Instant) -> bool {
+        if let Some(i) = OUTCOMES.iter().position(|&o| o == outcome) {
+            self.counts[i] += 1;
+        }
+        self.pending += 1;
+        let since = *self.since.get_or_insert(now);
+        self.pending >= self.max_pending || now.saturating_duration_since(since) >= self.interval
+    }
+
+    /// How long until the calls held back are due to be flushed by age, or
+    /// `None` if there are none.
+    pub(crate) fn due_in(&self, now: Instant) -> Option<Duration> {
+        let since = self.since?;
+        Some(
+            self.interval
+                .saturating_sub(now.saturating_duration_since(since)),
+        )
+    }
+
+    /// Reports everything held back, one counter update per outcome.
+    pub(crate) fn flush(&mut self, name: Option<&str>) {
+        for (outcome, count) in OUTCOMES.into_iter().zip(&mut self.counts) {
+            if *count > 0 {
+                calls(name, outcome, std::mem::take(count));
+            }
+        }
+        self.pending = 0;
+        self.since = None;
+    }
+}
+
+/// Reports that the breaker is now in `state`.
+pub(crate) fn state(_name: Option<&str>, _state: State) {
+    #[cfg(feature = "metrics")]
+    ::metrics::gauge!("circuit_breaker_state", labels(_name, None)).set(match _state {
+        State::Closed => 0.0,
+        State::Degraded => 1.0,
+        State::HalfOpen => 2.0,
+        State::Open => 3.0,
+        State::SoftOpen => 4.0,
+    });
+}
+
+/// Counts a transition, updates the state gauge and adds the transition to
+/// the current span.
+pub(crate) fn transition(name: Option<&str>, transition: StateTransition) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(
+        "circuit_breaker_transitions_total",
+        labels(name, Some(("to", transition.to.as_str())))
+    )
+    .increment(1);
+    #[cfg(feature = "opentelemetry")]
+    otel::transition_event(name, transition);
+    state(name, transition.to);
+}
+
+/// Records a finished call as a span started `latency` ago, or just now for
+/// a call that did not run.
+pub(crate) fn call_span(_name: Option<&str>, _outcome: CallOutcome, _latency: Option<Duration>) {
+    #[cfg(feature = "opentelemetry")]
+    otel::call_span(_name, _outcome, _latency);
+}
+
+/// The caller's `tracing` span and subscriber, taken along to the worker
+/// thread that runs its call, so events the protected function emits there
+/// belong to the caller's span.
+pub(crate) struct TraceContext {
+    #[cfg(feature = "tracing")]
+    dispatch: tracing::Dispatch,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl TraceContext {
+    pub(crate) fn current() -> Self {
+        TraceContext {
+            #[cfg(feature = "tracing")]
+            dispatch: tracing::dispatcher::get_default(tracing::Dispatch::clone),
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::current(),
+        }
+    }
+
+    /// Runs `f` in the captured span, with the captured subscriber as the
+    /// thread's default.
+    pub(crate) fn in_scope<T>(self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        return tracing::dispatcher::with_default(&self.dispatch, || self.span.in_scope(f));
+        #[cfg(not(feature = "tracing"))]
+        f()
+    }
+}
+
+/// The `breaker` label, matching [`Metrics::labels`](crate::Metrics::labels),
+/// plus `extra`.
+#[cfg(feature = "metrics")]
+fn labels(
+    name: Option<&str>,
+    extra: Option<(&'static str, &'static str)>,
+) -> Vec<::metrics::Label> {
+    name.map(|name| ::metrics::Label::new("breaker", name.to_owned()))
+        .into_iter()
+        .chain(extra.map(|(key, value)| ::metrics::Label::new(key, value)))
+        .collect()
+}
+
+#[cfg(feature = "opentelemetry")]
+mod otel {
+    use std::time::{Duration, SystemTime};
+
+    use opentelemetry::trace::{get_active_span, Span, Status, Tracer};
+    use opentelemetry::{global, KeyValue};
+
+    use crate::{CallOutcome, StateTransition};
+
+    pub(super) fn call_span(name: Option<&str>, outcome: CallOutcome, latency: Option<Duration>) {
+        let mut attributes: Vec<KeyValue> = name
+            .map(|name| KeyValue::new("circuit_breaker.name", name.to_owned()))
+            .into_iter()
+            .collect();
+        let (decision, label) = match outcome {
+            CallOutcome::Success => ("admitted", "success"),
+            CallOutcome::Failure => ("admitted", "failure"),
+            CallOutcome::Timeout => ("admitted", "timeout"),
+            CallOutcome::Internal => ("admitted", "internal"),
+            CallOutcome::Rejected(reason) => {
+                attributes.push(KeyValue::new(
+                    "circuit_breaker.rejection_reason",
+                    reason.to_string(),
+                ));
+                ("rejected", "rejected")
+            }
+            CallOutcome::Skipped => ("rejected", "skipped"),
+        };
+        attributes.push(KeyValue::new("circuit_breaker.decision", decision));
+        attributes.push(KeyValue::new("circuit_breaker.outcome", label));
+
+        let end = SystemTime::now();
+        let start = latency
+            .and_then(|latency| end.checked_sub(latency))
+            .unwrap_or(end);
+        let tracer = global::tracer("circuit_breaker");
+        let mut span = tracer
+            .span_builder("circuit_breaker.call")
+            .with_start_time(start)
+            .with_attributes(attributes)
+            .start(&tracer);
+        if matches!(
+            outcome,
+            CallOutcome::Failure | CallOutcome::Timeout | CallOutcome::Internal
+        ) {
+            span.set_status(Status::error(label));
+        }
+        span.end_with_timestamp(end);
+    }
+
+    pub(super) fn transition_event(name: Option<&str>, transition: StateTransition) {
+        get_active_span(|span| {
+            let mut attributes = vec![
+                KeyValue::new("from", transition.from.as_str()),
+                KeyValue::new("to", transition.to.as_str()),
+            ];
+            if let Some(name) = name {
+                attributes.push(KeyValue::new("circuit_breaker.name", name.to_owned()));
+            }
+            span.add_event("circuit_breaker.transition", attributes);
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use opentelemetry::trace::{Tracer, TracerProvider};
+        use opentelemetry::{global, KeyValue, Value};
+        use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+        use crate::CircuitBreaker;
+
+        #[test]
+        fn test_call_spans_and_transition_events() {
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider.clone());
+
+            let mut cb = CircuitBreaker::builder()
+                .name("otel-test")
+                .failure_threshold(1)
+                .build()
+                .unwrap();
+            provider.tracer("test").in_span("request", |_| {
+                let _ = cb.call(|| Ok::<_, &str>(()));
+                let _ = cb.call(|| Err::<(), _>("boom"));
+                let _ = cb.call(|| Err::<(), _>("boom"));
+                let _ = cb.call(|| Ok::<_, &str>(()));
+            });
+
+            let spans = exporter.get_finished_spans().unwrap();
+            let request = spans.iter().find(|s| s.name == "request").unwrap();
+            let attribute = |attributes: &[KeyValue], key: &str| {
+                attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == key)
+                    .map(|kv| kv.value.clone())
+            };
+            let calls: Vec<_> = spans
+                .iter()
+                .filter(|s| {
+                    s.name == "circuit_breaker.call"
+                        && attribute(&s.attributes, "circuit_breaker.name")
+                            == Some(Value::from("otel-test"))
+                })
+                .collect();
+            assert_eq!(calls.len(), 4);
+            for span in &calls {
+                assert_eq!(span.parent_span_id, request.span_context.span_id());
+            }
+            let decisions: Vec<_> = calls
+                .iter()
+                .map(|s| {
+                    (
+                        attribute(&s.attributes, "circuit_breaker.decision").unwrap(),
+                        attribute(&s.attributes, "circuit_breaker.outcome").unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                decisions,
+                [
+                    ("admitted", "success"),
+                    ("admitted", "failure"),
+                    ("admitted", "failure"),
+                    ("rejected", "rejected"),
+                ]
+                .map(|(d, o)| (Value::from(d), Value::from(o)))
+            );
+            assert_eq!(
+                attribute(&calls[3].attributes, "circuit_breaker.rejection_reason"),
+                Some(Value::from("breaker is open"))
+            );
+
+            let event = request
+                .events
+                .iter()
+                .find(|e| e.name == "circuit_breaker.transition")
+                .unwrap();
+            assert_eq!(
+                attribute(&event.attributes, "from"),
+                Some(Value::from("closed"))
+            );
+            assert_eq!(
+                attribute(&event.attributes, "to"),
+                Some(Value::from("open"))
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::time::Duration;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    use crate::{sanitize_label, CircuitBreaker};
+
+    #[test]
+    fn test_label_is_sanitized() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        let metrics = ::metrics::with_local_recorder(&recorder, || {
+            let mut cb = CircuitBreaker::builder()
+                .name("payments/v2 (eu)")
+                .metrics_label(sanitize_label)
+                .build()
+                .unwrap();
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            cb.metrics()
+        });
+        assert_eq!(metrics.name.as_deref(), Some("payments/v2 (eu)"));
+        assert_eq!(
+            metrics.labels(),
+            [("breaker", "payments_v2__eu_".to_string())]
+        );
+
+        let (key, _, _, _) = snapshotter.snapshot().into_vec().remove(0);
+        let label = key.key().labels().next().unwrap();
+        assert_eq!(
+            (label.key(), label.value()),
+            ("breaker", "payments_v2__eu_")
+        );
+        assert_eq!(sanitize_label("2fa"), "_2fa");
+    }
+
+    #[test]
+    fn test_emits_through_metrics_facade() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            let mut cb = CircuitBreaker::builder()
+                .name("payments")
+                .failure_threshold(1)
+                .build()
+                .unwrap();
+            let _ = cb.call(|| Ok::<_, &str>(()));
+            let _ = cb.call(|| Err::<(), _>("boom"));
+            let _ = cb.call(|| Err::<(), _>("boom"));
+            let _ = cb.call(|| Ok::<_, &str>(()));
+        });
+
+        let mut seen: Vec<(String, Vec<String>, DebugValue)> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels = key
+                    .labels()
+                    .map(|l| format!("{}={}", l.key(), l.value()))
+                    .collect();
+                (key.name().to_string(), labels, value)
+            })
+            .collect();
+        seen.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+        let breaker = "breaker=payments".to_string();
+        let expected = vec![
+            (
+                "circuit_breaker_calls_total".to_string(),
+                vec![breaker.clone(), "outcome=failure".to_string()],
+                DebugValue::Counter(2),
+            ),
+            (
+                "circuit_breaker_calls_total".to_string(),
+                vec![breaker.clone(), "outcome=rejected".to_string()],
+                DebugValue::Counter(1),
+            ),
+            (
+                "circuit_breaker_calls_total".to_string(),
+                vec![breaker.clone(), "outcome=success".to_string()],
+                DebugValue::Counter(1),
+            ),
+            (
+                "circuit_breaker_state".to_string(),
+                vec![breaker.clone()],
+                DebugValue::Gauge(3.0.into()),
+            ),
+            (
+                "circuit_breaker_transitions_total".to_string(),
+                vec![breaker, "to=open".to_string()],
+                DebugValue::Counter(1),
+            ),
+        ];
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_batched_calls_are_flushed() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let successes = || {
+            snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .find_map(|(key, _, _, value)| {
+                    (key.key().name() == "circuit_breaker_calls_total").then_some(value)
+                })
+        };
+
+        ::metrics::with_local_recorder(&recorder, || {
+            let mut cb = CircuitBreaker::builder()
+                .batch_telemetry(3, Duration::from_secs(60))
+                .build()
+                .unwrap();
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(successes(), None);
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(successes(), Some(DebugValue::Counter(3)));
+
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            drop(cb);
+        });
+        assert_eq!(successes(), Some(DebugValue::Counter(1)));
+    }
+
+    #[test]
+    fn test_idle_batch_is_flushed_once_due() {
+        use crate::FakeClock;
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let calls = || {
+            snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .find_map(|(key, _, _, value)| {
+                    (key.key().name() == "circuit_breaker_calls_total").then_some(value)
+                })
+        };
+        let clock = FakeClock::new();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            let mut cb = CircuitBreaker::builder()
+                .batch_telemetry(1000, Duration::from_secs(1))
+                .build_with_clock(clock.clone())
+                .unwrap();
+            let _ = cb.call(|| Ok::<_, ()>(()));
+            assert_eq!(cb.telemetry_due_in(), Some(Duration::from_secs(1)));
+            cb.flush_due_telemetry();
+            assert_eq!(calls(), None);
+
+            clock.advance(Duration::from_secs(1));
+            cb.flush_due_telemetry();
+            assert_eq!(cb.telemetry_due_in(), None);
+            assert_eq!(calls(), Some(DebugValue::Counter(1)));
+        });
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tracing_tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, ThreadId};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing_core::span::Current;
+
+    use crate::CircuitBreaker;
+
+    /// Records, for every event, the span that was current on the thread
+    /// that emitted it.
+    #[derive(Default)]
+    struct Capture {
+        next_id: AtomicU64,
+        spans: Mutex<HashMap<u64, &'static Metadata<'static>>>,
+        entered: Mutex<HashMap<ThreadId, Vec<Id>>>,
+        events: Mutex<Vec<(Option<u64>, ThreadId)>>,
+    }
+
+    impl Capture {
+        fn current(&self) -> Option<Id> {
+            let entered = self.entered.lock().unwrap();
+            entered.get(&thread::current().id())?.last().cloned()
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            self.spans.lock().unwrap().insert(id, span.metadata());
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {
+            let span = self.current().map(|id| id.into_u64());
+            self.events
+                .lock()
+                .unwrap()
+                .push((span, thread::current().id()));
+        }
+
+        fn enter(&self, span: &Id) {
+            let mut entered = self.entered.lock().unwrap();
+            entered
+                .entry(thread::current().id())
+                .or_default()
+                .push(span.clone());
+        }
+
+        fn exit(&self, _: &Id) {
+            let mut entered = self.entered.lock().unwrap();
+            if let Some(stack) = entered.get_mut(&thread::current().id()) {
+                stack.pop();
+            }
+        }
+
+        fn current_span(&self) -> Current {
+            match self.current() {
+                Some(id) => {
+                    let metadata = self.spans.lock().unwrap()[&id.into_u64()];
+                    Current::new(id, metadata)
+                }
+                None => Current::none(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_worker_events_carry_the_callers_span() {
+        let capture = Arc::new(Capture::default());
+        let span_id = tracing::subscriber::with_default(Arc::clone(&capture), || {
+            let span = tracing::info_span!("request");
+            let _entered = span.enter();
+            let mut cb = CircuitBreaker::builder().build().unwrap();
+            let res = cb.call(|| {
+                tracing::info!("calling the dependency");
+                Ok::<_, ()>(())
+            });
+            assert!(matches!(res, Ok(Some(()))));
+            span.id().unwrap().into_u64()
+        });
+
+        let events = capture.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let (span, thread) = events[0];
+        assert_eq!(span, Some(span_id));
+        assert_ne!(thread, thread::current().id());
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a call run by a [`TimeoutStrategy`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    /// The job ran to the end within the timeout.
+    Finished,
+    /// The timeout passed first. The job may still be running, or may
+    /// never have started; either way the call counts as timed out.
+    TimedOut,
+}
+
+/// Runs a call's function and enforces its timeout, in place of the
+/// breaker's own worker threads, see
+/// [`CircuitBreakerBuilder::timeout_strategy`](crate::CircuitBreakerBuilder::timeout_strategy),
+/// e.g. to hand the work to an executor the application already has.
+///
+/// `job` runs the protected function and hands its result back to the
+/// breaker by itself, so the strategy only decides where it runs and how
+/// long to wait. A job that is dropped without running leaves the call
+/// with [`CircuitBreakerError::Internal`](crate::CircuitBreakerError::Internal)
+/// if the strategy reports it finished.
+pub trait TimeoutStrategy: Send + Sync {
+    /// Runs `job`, returning once it finished or `timeout` passed,
+    /// whichever is first.
+    fn run_with_timeout(&self, job: Box<dyn FnOnce() + Send>, timeout: Duration) -> Completion;
+}
+
+impl<T: TimeoutStrategy + ?Sized> TimeoutStrategy for Arc<T> {
+    fn run_with_timeout(&self, job: Box<dyn FnOnce() + Send>, timeout: Duration) -> Completion {
+        (**self).run_with_timeout(job, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{CircuitBreaker, CircuitBreakerError, State};
+
+    /// Runs jobs on the calling thread until `fail_after` of them ran, then
+    /// times every call out without running it.
+    struct Mock {
+        fail_after: usize,
+        runs: AtomicUsize,
+    }
+
+    impl TimeoutStrategy for Mock {
+        fn run_with_timeout(&self, job: Box<dyn FnOnce() + Send>, _: Duration) -> Completion {
+            if self.runs.fetch_add(1, Ordering::SeqCst) >= self.fail_after {
+                return Completion::TimedOut;
+            }
+            job();
+            Completion::Finished
+        }
+    }
+
+    #[test]
+    fn test_strategy_decides_timeouts() {
+        let strategy = Arc::new(Mock {
+            fail_after: 2,
+            runs: AtomicUsize::new(0),
+        });
+        let mut cb = CircuitBreaker::builder()
+            .timeout(Duration::from_secs(60))
+            .timeout_threshold(1)
+            .timeout_strategy(Arc::clone(&strategy))
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            assert!(matches!(cb.call(|| Ok::<_, ()>(1)), Ok(Some(1))));
+        }
+        for _ in 0..2 {
+            assert!(matches!(
+                cb.call(|| Ok::<_, ()>(1)),
+                Err(CircuitBreakerError::TimeoutError)
+            ));
+        }
+        assert_eq!(cb.state(), State::Open);
+        assert_eq!(strategy.runs.load(Ordering::SeqCst), 4);
+        assert_eq!(cb.in_flight(), 0);
+    }
+}